@@ -49,6 +49,10 @@ pub trait DatabaseConnection: Sync + Send {
 	fn new_query (&self, collection: &str) -> Box<dyn QueryBuilder>;
 	fn exec_first (&mut self, model: &Box<dyn ModelMetaImpl>, query_any: Box<dyn Any>) -> Result<Option<EntityHandle>, String>;
     fn exec_all (&mut self, model: &Box<dyn ModelMetaImpl>, query_any: Box<dyn Any>) -> Result<EntityList, String>;
+	/// Called once on server shutdown after all requests are finished, it's skipped when shutdown timeout interrupts them.
+	/// Added last with default body, so methods above keep their vtable slots and drivers need no source changes,
+	/// but the vtable grows: drivers built against older bindings must be rebuilt before they are loaded.
+	fn close (&self) {}
 }
 
 pub trait DatabaseImpl {
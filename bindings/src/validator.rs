@@ -125,6 +125,52 @@ macro_rules! json_parse_impl {
 
 				return Ok(());
 			}
+
+			fn assign_coerced (&mut self, parsed: &json::JsonValue) -> Result<(), ValidationError> {
+				$(
+					let name = stringify!($field);
+					if !parsed[stringify!($field)].is_null() {
+						bindings::json_coerce_impl!(name, self.$field, parsed[stringify!($field)], $type);
+					}
+				)*
+
+				return Ok(());
+			}
+		}
+	};
+}
+
+/// Same as `json_parse_impl`, but bools and numbers may be given as strings
+#[macro_export]
+macro_rules! json_coerce_impl {
+	($name:ident, $value:expr, $input:expr, bool) => {
+		match $input.as_bool().or_else(|| $input.as_str().and_then(bindings::validator::parse_bool)) {
+			Some(value) => $value = value,
+			None => return Err(ValidationError {
+				message: "expected bool".to_owned(),
+				path: vec![$name.to_owned()]
+			})
+		}
+	};
+	($name:ident, $value:expr, $input:expr, String) => {
+		bindings::json_parse_impl!($name, $value, $input, String);
+	};
+	($name:ident, $value:expr, $input:expr, i32) => {
+		match $input.as_i32().or_else(|| $input.as_str().and_then(|value| value.trim().parse::<i32>().ok())) {
+			Some(value) => $value = value,
+			None => return Err(ValidationError {
+				message: "expected i32".to_owned(),
+				path: vec![$name.to_owned()]
+			})
+		}
+	};
+	($name:ident, $value:expr, $input:expr, u16) => {
+		match $input.as_u16().or_else(|| $input.as_str().and_then(|value| value.trim().parse::<u16>().ok())) {
+			Some(value) => $value = value,
+			None => return Err(ValidationError {
+				message: "expected u16".to_owned(),
+				path: vec![$name.to_owned()]
+			})
 		}
 	};
 }
@@ -151,6 +197,11 @@ impl ValidationError {
 pub trait ValidateJson {
 	fn parse_json (&mut self, raw: &str) -> Result<(), ValidationError>;
 	fn assign_json (&mut self, parsed: &json::JsonValue) -> Result<(), ValidationError>;
+	/// Like `assign_json`, but bools and numbers may be strings, as parameters of query and form are.
+	/// Types implementing the trait by hand get strict `assign_json` by default.
+	fn assign_coerced (&mut self, parsed: &json::JsonValue) -> Result<(), ValidationError> {
+		return self.assign_json(parsed);
+	}
 }
 
 pub trait Validate {
@@ -164,3 +215,21 @@ pub fn validate_json<T: Validate + ValidateJson + Default> (raw: &str) -> Result
 
 	return Ok(payload);
 }
+
+/// Validates parameters of query or form collected into object of strings, as `Query::to_json` of core gives them
+pub fn validate_coerced<T: Validate + ValidateJson + Default> (parsed: &JsonValue) -> Result<T, ValidationError> {
+	let mut payload: T = Default::default();
+	payload.assign_coerced(parsed)?;
+	payload.validate()?;
+
+	return Ok(payload);
+}
+
+/// Bool given as text, empty one is a flag without value
+pub fn parse_bool (value: &str) -> Option<bool> {
+	return match value.trim().to_ascii_lowercase().as_str() {
+		"" | "true" | "1" | "yes" | "on" => Some(true),
+		"false" | "0" | "no" | "off" => Some(false),
+		_ => None
+	};
+}
@@ -0,0 +1,13 @@
+#![no_main]
+
+use std::net::{IpAddr, Ipv4Addr};
+use libfuzzer_sys::fuzz_target;
+use photonyx::http::entity::{HttpConnection, ParsingResult};
+use photonyx::http1::Http1Connection;
+use photonyx::utils::socket::MemoryStream;
+
+fuzz_target!(|data: &[u8]| {
+    let transport = MemoryStream::new(data.to_vec());
+    let mut connection = Http1Connection::from_transport(transport, IpAddr::V4(Ipv4Addr::LOCALHOST));
+    while let ParsingResult::Complete(_) = connection.parse() {}
+});
@@ -0,0 +1,78 @@
+#[cfg(unix)]
+use std::{env, io, os::fd::{FromRawFd, RawFd}, os::unix::net::UnixListener, net::TcpListener, process};
+#[cfg(unix)]
+use socket2::{Domain, Socket, Type};
+#[cfg(unix)]
+use crate::utils::log::log_warning;
+use crate::utils::socket::SocketListener;
+
+/// Listening socket opened by the service manager
+pub struct InheritedListener {
+    pub listener: SocketListener,
+    /// Name from `FileDescriptorName=` of the socket unit, matched against `listen[...].name`
+    pub name: String,
+    /// Local address for logging
+    pub address: String
+}
+
+/// Listeners passed by systemd socket activation, see `sd_listen_fds(3)`.
+/// Nothing is returned when `LISTEN_PID` is meant for another process, e.g. the parent one.
+#[cfg(unix)]
+pub fn inherited_listeners () -> Vec<InheritedListener> {
+    /// First descriptor after stdin, stdout and stderr
+    const LISTEN_FDS_START: RawFd = 3;
+
+    let is_ours = env::var("LISTEN_PID").ok().and_then(|pid| pid.parse::<u32>().ok()) == Some(process::id());
+    let count = env::var("LISTEN_FDS").ok().and_then(|count| count.parse::<RawFd>().ok()).unwrap_or(0);
+    if !is_ours || count <= 0 {
+        return Vec::new();
+    }
+
+    let names = env::var("LISTEN_FDNAMES").unwrap_or_default();
+    let mut names = names.split(':');
+    let mut listeners = Vec::new();
+
+    for fd in LISTEN_FDS_START..LISTEN_FDS_START + count {
+        let name = names.next().unwrap_or_default().to_owned();
+        // Safety: service manager passes these descriptors to this process only, nothing else owns them
+        let socket = unsafe { Socket::from_raw_fd(fd) };
+        match adopt(socket) {
+            Ok((listener, address)) => listeners.push(InheritedListener { listener, name, address }),
+            Err(error) => log_warning(&format!("Inherited descriptor {fd} is skipped: {error}"))
+        }
+    }
+
+    return listeners;
+}
+
+#[cfg(not(unix))]
+pub fn inherited_listeners () -> Vec<InheritedListener> {
+    return Vec::new();
+}
+
+/// Takes listening stream socket, so it's not leaked into processes spawned by modules
+#[cfg(unix)]
+fn adopt (socket: Socket) -> io::Result<(SocketListener, String)> {
+    if socket.r#type()? != Type::STREAM {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "not a stream socket"));
+    }
+
+    socket.set_cloexec(true)?;
+    let local = socket.local_addr()?;
+    if local.domain() == Domain::UNIX {
+        let listener = UnixListener::from(socket);
+        let address = match listener.local_addr()?.as_pathname() {
+            Some(path) => format!("unix:{}", path.display()),
+            None => "unix:(unnamed)".to_owned()
+        };
+
+        // Socket file belongs to the service manager, so it's kept on shutdown
+        return Ok((SocketListener::Unix(listener, None), address));
+    }
+
+    let Some(address) = local.as_socket() else {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "unsupported address family"));
+    };
+
+    return Ok((SocketListener::Tcp(TcpListener::from(socket)), address.to_string()));
+}
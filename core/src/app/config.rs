@@ -1,6 +1,6 @@
-use crate::utils::{bake_fatal, json_read_array, log::log_error_lines, sync::{AppStatic, LazyInit}};
+use crate::utils::{bake_fatal, json_read_array, log::{log_error, log_error_lines, log_warning}, proxy::Cidr, sync::{AppStatic, LazyInit}};
 use json::{object, JsonValue};
-use std::{env, fs, ops::{Index, IndexMut}, path::Path, process, str::FromStr};
+use std::{env, fmt, fs, io, num::NonZeroUsize, ops::{Index, IndexMut}, path::{Path, PathBuf}, process, str::FromStr, thread, time::Duration};
 
 
 pub static CONFIG: AppStatic<Config> = AppStatic::new();
@@ -10,22 +10,56 @@ pub struct Config {
 
     pub host: String,
     pub port: u16,
-    pub cors: CorsConfig
+    /// Requests of paths with `//`, dot segments or needless encoding are redirected to their canonical form
+    pub redirect_non_canonical: bool,
+    pub cors: CorsConfig,
+    pub keep_alive: KeepAliveConfig,
+    pub limits: LimitsConfig,
+    pub timeouts: TimeoutsConfig,
+    pub http2: Http2Config,
+    pub compression: CompressionConfig,
+    pub uploads: UploadsConfig,
+    pub tls: TlsConfig,
+    pub workers: WorkersConfig,
+    /// Sockets accepting connections, without `listen` array it's `host:port` and TLS port when certificates are set
+    pub listen: Vec<ListenConfig>,
+    /// Proxies whose `Forwarded` and `X-Forwarded-*` headers are honored, in CIDR notation
+    pub trusted_proxies: Vec<Cidr>,
+    /// Directories served as static files, mounted after module routes so they don't shadow them
+    pub static_dirs: Vec<StaticDirConfig>
 }
 
 impl Config {
+    #[allow(clippy::should_implement_trait)]
     pub fn default () -> Self {
         Config {
             obj: object! {},
             host: "127.0.0.1".to_owned(),
             port: 8081,
-            cors: CorsConfig::default()
+            redirect_non_canonical: false,
+            cors: CorsConfig::default(),
+            keep_alive: KeepAliveConfig::default(),
+            limits: LimitsConfig::default(),
+            timeouts: TimeoutsConfig::default(),
+            http2: Http2Config::default(),
+            compression: CompressionConfig::default(),
+            uploads: UploadsConfig::default(),
+            tls: TlsConfig::default(),
+            workers: WorkersConfig::default(),
+            listen: Vec::new(),
+            trusted_proxies: Vec::new(),
+            static_dirs: Vec::new()
         }
     }
 
+    /// Missing file is read as empty object, so defaults still get overrides from environment, e.g. `PORT`
     pub fn load<P: AsRef<Path>> (&mut self, path: P) {
         let raw = match fs::read_to_string(path) {
             Ok(content) => content,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                log_warning("Config file not found, using defaults");
+                "{}".to_owned()
+            }
             Err(err) => {
                 log_error_lines("Config reading error", err.to_string());
                 process::exit(-1);
@@ -52,7 +86,61 @@ impl Config {
             self.host = host.to_owned();
         }
 
+        if let Some(redirect) = self.obj["redirect_non_canonical"].as_bool() {
+            self.redirect_non_canonical = redirect;
+        }
+
         self.cors.load(&self.obj);
+        self.keep_alive.load(&self.obj);
+        self.limits.load(&self.obj);
+        self.timeouts.load(&self.obj);
+        self.http2.load(&self.obj);
+        self.compression.load(&self.obj);
+        self.uploads.load(&self.obj);
+        self.tls.load(&self.obj);
+        self.workers.load(&self.obj);
+
+        let listen = json_read_array(
+            &self.obj["listen"],
+            ListenConfig::read,
+            bake_fatal("Config parsing error: listen[...] must be an address string or object with address or path")
+        );
+
+        if let Some(list) = listen {
+            self.listen = list;
+        }
+
+        let trusted_proxies = json_read_array(
+            &self.obj["trusted_proxies"],
+            |network| network.as_str()?.parse::<Cidr>().ok(),
+            bake_fatal("Config parsing error: trusted_proxies[...] must be an address or network in CIDR notation")
+        );
+
+        if let Some(list) = trusted_proxies {
+            self.trusted_proxies = list;
+        }
+
+        let static_dirs = json_read_array(
+            &self.obj["static"],
+            StaticDirConfig::read,
+            bake_fatal("Config parsing error: static[...] must be an object with path and dir")
+        );
+
+        if let Some(list) = static_dirs {
+            self.static_dirs = list;
+        }
+    }
+
+    /// Listeners used when `listen` isn't configured
+    fn default_listen (&self) -> Vec<ListenConfig> {
+        let mut listen = vec![ListenConfig::tcp(format!("{}:{}", self.host, self.port))];
+        if !self.tls.certificates.is_empty() {
+            let mut tls = ListenConfig::tcp(format!("{}:{}", self.host, self.tls.port));
+            tls.tls = true;
+            listen.push(tls);
+        }
+
+        return listen;
     }
 
     pub fn get_path (&self, path: Vec<&str>) -> &JsonValue {
@@ -89,6 +177,10 @@ impl LazyInit for Config {
     fn init () -> Self {
         let mut config = Config::default();
         config.load("config.json");
+        if config.listen.is_empty() {
+            config.listen = config.default_listen();
+        }
+
         return config;
     }
 }
@@ -109,7 +201,6 @@ impl IndexMut<&str> for Config {
 
 pub struct CorsConfig {
 	pub origin: String,
-	pub methods: String,
 	pub headers: String,
 	pub ttl: String
 }
@@ -118,7 +209,6 @@ impl CorsConfig {
     pub const fn default () -> Self {
         CorsConfig {
             origin: String::new(),
-            methods: String::new(),
             headers: String::new(),
             ttl: String::new()
         }
@@ -129,18 +219,6 @@ impl CorsConfig {
             self.origin = origin.to_owned();
         }
 
-		let methods = json_read_array(
-			&config["cors"]["methods"],
-			JsonValue::as_str,
-			bake_fatal("Config parsing error: cors.methods[...] must be a string")
-		);
-
-        if let Some(list) = methods {
-            self.methods = list.join(",");
-        } else {
-            self.methods = "GET,POST".to_owned();
-        }
-
 		let headers = json_read_array(
 			&config["cors"]["headers"],
 			JsonValue::as_str,
@@ -160,3 +238,483 @@ impl CorsConfig {
         }
 	}
 }
+
+pub struct KeepAliveConfig {
+    /// How long an idle persistent connection waits for the next request
+    pub timeout: Duration,
+    /// Maximum number of requests served over one connection, `0` disables keep-alive
+    pub max_requests: usize
+}
+
+impl KeepAliveConfig {
+    pub const fn default () -> Self {
+        KeepAliveConfig {
+            timeout: Duration::from_secs(5),
+            max_requests: 100
+        }
+    }
+
+    fn load (&mut self, config: &JsonValue) {
+        if let Some(timeout) = config["keep_alive"]["timeout"].as_u64() {
+            self.timeout = Duration::from_secs(timeout);
+        }
+
+        if let Some(max_requests) = config["keep_alive"]["max_requests"].as_usize() {
+            self.max_requests = max_requests;
+        }
+    }
+}
+
+/// Request size limits, exceeding them is answered with 414, 431 or 413
+pub struct LimitsConfig {
+    /// Maximum length of request target (path and query)
+    pub uri: usize,
+    /// Maximum length of a single header line
+    pub header_line: usize,
+    /// Maximum number of header lines
+    pub headers: usize,
+    /// Maximum body size in bytes
+    pub body: usize,
+    /// Maximum size of body after undoing its `Content-Encoding`, protects against decompression bombs
+    pub decompressed_body: usize
+}
+
+impl LimitsConfig {
+    pub const fn default () -> Self {
+        LimitsConfig {
+            uri: 8192,
+            header_line: 8192,
+            headers: 100,
+            body: 10 * 1024 * 1024,
+            decompressed_body: 10 * 1024 * 1024
+        }
+    }
+
+    fn load (&mut self, config: &JsonValue) {
+        let limits = &config["limits"];
+        if let Some(uri) = limits["uri"].as_usize() {
+            self.uri = uri;
+        }
+
+        if let Some(header_line) = limits["header_line"].as_usize() {
+            self.header_line = header_line;
+        }
+
+        if let Some(headers) = limits["headers"].as_usize() {
+            self.headers = headers;
+        }
+
+        if let Some(body) = limits["body"].as_usize() {
+            self.body = body;
+        }
+
+        if let Some(decompressed_body) = limits["decompressed_body"].as_usize() {
+            self.decompressed_body = decompressed_body;
+        }
+    }
+}
+
+/// Socket timeouts in seconds, `0` disables the timeout.
+/// Idle time between requests is limited by `keep_alive.timeout`.
+pub struct TimeoutsConfig {
+    /// Time to receive request line and headers, counted from the connection accept for the first request
+    pub header: Duration,
+    /// Time to receive the whole request body
+    pub body: Duration,
+    /// Time for each response write to complete
+    pub write: Duration,
+    /// Silence period after which WebSocket is pinged, if nothing comes in the next period it gets closed
+    pub websocket: Duration,
+    /// Time given to running requests to finish after the shutdown signal, `0` doesn't wait at all
+    pub shutdown: Duration
+}
+
+impl TimeoutsConfig {
+    pub const fn default () -> Self {
+        TimeoutsConfig {
+            header: Duration::from_secs(10),
+            body: Duration::from_secs(60),
+            write: Duration::from_secs(30),
+            websocket: Duration::from_secs(30),
+            shutdown: Duration::from_secs(30)
+        }
+    }
+
+    fn load (&mut self, config: &JsonValue) {
+        let timeouts = &config["timeouts"];
+        if let Some(header) = timeouts["header"].as_u64() {
+            self.header = Duration::from_secs(header);
+        }
+
+        if let Some(body) = timeouts["body"].as_u64() {
+            self.body = Duration::from_secs(body);
+        }
+
+        if let Some(write) = timeouts["write"].as_u64() {
+            self.write = Duration::from_secs(write);
+        }
+
+        if let Some(websocket) = timeouts["websocket"].as_u64() {
+            self.websocket = Duration::from_secs(websocket);
+        }
+
+        if let Some(shutdown) = timeouts["shutdown"].as_u64() {
+            self.shutdown = Duration::from_secs(shutdown);
+        }
+    }
+}
+
+pub struct Http2Config {
+    /// Whether cleartext HTTP/2 is accepted, both with prior knowledge and via `Upgrade: h2c`
+    pub enabled: bool,
+    /// Maximum number of streams client may have open at once. Requests of one connection are handled one after another,
+    /// streams waiting for their turn buffer at most 64 KiB of the body each.
+    pub max_concurrent_streams: u32
+}
+
+impl Http2Config {
+    pub const fn default () -> Self {
+        Http2Config {
+            enabled: true,
+            max_concurrent_streams: 100
+        }
+    }
+
+    fn load (&mut self, config: &JsonValue) {
+        let http2 = &config["http2"];
+        if let Some(enabled) = http2["enabled"].as_bool() {
+            self.enabled = enabled;
+        }
+
+        if let Some(max_concurrent_streams) = http2["max_concurrent_streams"].as_u32() {
+            self.max_concurrent_streams = max_concurrent_streams;
+        }
+    }
+}
+
+/// Compression of payload responses negotiated by `Accept-Encoding`
+pub struct CompressionConfig {
+    pub enabled: bool,
+    /// Smaller bodies are sent as is, since compression wouldn't pay off
+    pub min_size: usize,
+    /// Content types worth compressing, `type/*` matches any subtype
+    pub types: Vec<String>
+}
+
+impl CompressionConfig {
+    #[allow(clippy::should_implement_trait)]
+    pub fn default () -> Self {
+        CompressionConfig {
+            enabled: true,
+            min_size: 1024,
+            types: [
+                "text/*", "application/json", "application/javascript", "application/xml",
+                "application/wasm", "image/svg+xml"
+            ].map(str::to_owned).to_vec()
+        }
+    }
+
+    fn load (&mut self, config: &JsonValue) {
+        let compression = &config["compression"];
+        if let Some(enabled) = compression["enabled"].as_bool() {
+            self.enabled = enabled;
+        }
+
+        if let Some(min_size) = compression["min_size"].as_usize() {
+            self.min_size = min_size;
+        }
+
+        let types = json_read_array(
+            &compression["types"],
+            |content_type| content_type.as_str().map(str::to_ascii_lowercase),
+            bake_fatal("Config parsing error: compression.types[...] must be a string")
+        );
+
+        if let Some(list) = types {
+            self.types = list;
+        }
+    }
+}
+
+/// Limits of `multipart/form-data` bodies sent to existing routes, exceeding them is answered with 413.
+/// On HTTP/1 such bodies with `Content-Length` are streamed, files go to upload sink of the route or to temp files,
+/// so `total_size` replaces `limits.body` for them. Chunked, compressed and HTTP/2 bodies are read into memory first
+/// and stay within `limits.body` as well.
+pub struct UploadsConfig {
+    /// Directory where uploaded files are kept while the request is handled
+    pub dir: PathBuf,
+    /// Maximum size of a single uploaded file
+    pub part_size: u64,
+    /// Maximum size of a single text field, those are kept in memory
+    pub field_size: usize,
+    /// Maximum size of the whole body
+    pub total_size: u64,
+    /// Maximum number of parts, both fields and files
+    pub parts: usize
+}
+
+impl UploadsConfig {
+    #[allow(clippy::should_implement_trait)]
+    pub fn default () -> Self {
+        UploadsConfig {
+            dir: env::temp_dir(),
+            part_size: 100 * 1024 * 1024,
+            field_size: 64 * 1024,
+            total_size: 100 * 1024 * 1024,
+            parts: 100
+        }
+    }
+
+    fn load (&mut self, config: &JsonValue) {
+        let uploads = &config["uploads"];
+        if let Some(dir) = uploads["dir"].as_str() {
+            self.dir = PathBuf::from(dir);
+        }
+
+        if let Some(part_size) = uploads["part_size"].as_u64() {
+            self.part_size = part_size;
+        }
+
+        if let Some(field_size) = uploads["field_size"].as_usize() {
+            self.field_size = field_size;
+        }
+
+        if let Some(total_size) = uploads["total_size"].as_u64() {
+            self.total_size = total_size;
+        }
+
+        if let Some(parts) = uploads["parts"].as_usize() {
+            self.parts = parts;
+        }
+    }
+}
+
+pub struct WorkersConfig {
+    /// Number of threads running handlers, `0` means available CPU parallelism
+    pub count: usize,
+    /// How many ready connections may wait for a free worker, new connections beyond it get 503
+    pub queue: usize,
+    /// Seconds sent in `Retry-After` header of 503 response
+    pub retry_after: u64
+}
+
+impl WorkersConfig {
+    pub const fn default () -> Self {
+        WorkersConfig {
+            count: 0,
+            queue: 1024,
+            retry_after: 1
+        }
+    }
+
+    fn load (&mut self, config: &JsonValue) {
+        let workers = &config["workers"];
+        if let Some(count) = Config::parse_env("WORKERS") {
+            self.count = count;
+        } else if let Some(count) = workers["count"].as_usize() {
+            self.count = count;
+        }
+
+        if let Some(queue) = workers["queue"].as_usize() {
+            self.queue = queue;
+        }
+
+        if let Some(retry_after) = workers["retry_after"].as_u64() {
+            self.retry_after = retry_after;
+        }
+    }
+
+    /// Configured worker count or available parallelism, if it can't be determined 4 workers are used
+    pub fn threads (&self) -> usize {
+        if self.count != 0 {
+            return self.count;
+        }
+
+        return thread::available_parallelism().map_or(4, NonZeroUsize::get);
+    }
+}
+
+pub enum ListenAddress {
+    /// `host:port`, IPv6 hosts are written in brackets, e.g. `[::]:8080`
+    Tcp(String),
+    /// Path of Unix domain socket file
+    Unix(String)
+}
+
+impl fmt::Display for ListenAddress {
+    fn fmt (&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ListenAddress::Tcp(address) => write!(f, "{address}"),
+            ListenAddress::Unix(path) => write!(f, "unix:{path}")
+        }
+    }
+}
+
+/// Socket accepting connections, configured either as address string, `unix:` prefix marks socket path,
+/// or as object with `address` or `path` and the fields below
+pub struct ListenConfig {
+    pub address: ListenAddress,
+    /// Name routes can be restricted to, unnamed listeners serve only unrestricted routes
+    pub name: String,
+    /// Connections are served over TLS, certificates are taken from `tls` section
+    pub tls: bool,
+    /// Whether IPv6 socket refuses IPv4 connections, `false` makes `[::]` dual-stack, unset keeps system default
+    pub ipv6_only: Option<bool>,
+    /// Octal permissions of Unix socket file, e.g. `"660"`, unset leaves them to umask
+    pub mode: Option<u32>,
+    /// Connections start with PROXY protocol header carrying client address, connections without it are closed
+    pub proxy_protocol: bool
+}
+
+impl ListenConfig {
+    pub fn tcp (address: String) -> Self {
+        ListenConfig {
+            address: ListenAddress::Tcp(address),
+            name: String::new(),
+            tls: false,
+            ipv6_only: None,
+            mode: None,
+            proxy_protocol: false
+        }
+    }
+
+    fn read (entry: &JsonValue) -> Option<Self> {
+        if let Some(address) = entry.as_str() {
+            if let Some(path) = address.strip_prefix("unix:") {
+                return Some(ListenConfig { address: ListenAddress::Unix(path.to_owned()), ..ListenConfig::tcp(String::new()) });
+            }
+
+            return Some(ListenConfig::tcp(address.to_owned()));
+        }
+
+        let address = match (entry["address"].as_str(), entry["path"].as_str()) {
+            (Some(address), None) => ListenAddress::Tcp(address.to_owned()),
+            (None, Some(path)) => ListenAddress::Unix(path.to_owned()),
+            _ => return None
+        };
+
+        let mode = match &entry["mode"] {
+            JsonValue::Null => None,
+            mode => Some(mode.as_str().and_then(|mode| u32::from_str_radix(mode, 8).ok())?)
+        };
+
+        return Some(ListenConfig {
+            address,
+            name: entry["name"].as_str().unwrap_or_default().to_owned(),
+            tls: entry["tls"].as_bool().unwrap_or(false),
+            ipv6_only: entry["ipv6_only"].as_bool(),
+            mode,
+            proxy_protocol: entry["proxy_protocol"].as_bool().unwrap_or(false)
+        });
+    }
+}
+
+/// Directory with static files mounted at URL path prefix
+pub struct StaticDirConfig {
+    /// URL path prefix, `/` serves the directory at the root
+    pub path: String,
+    pub dir: String,
+    /// Files served for directory requests, the first existing one is taken
+    pub index: Vec<String>,
+    /// File served instead of missing ones, e.g. `index.html` of single-page application
+    pub fallback: Option<String>
+}
+
+impl StaticDirConfig {
+    fn read (entry: &JsonValue) -> Option<Self> {
+        let index = match &entry["index"] {
+            JsonValue::Null => vec!["index.html".to_owned()],
+            JsonValue::String(_) | JsonValue::Short(_) => vec![entry["index"].as_str()?.to_owned()],
+            index if index.is_array() => index.members().map(|file| file.as_str().map(str::to_owned)).collect::<Option<Vec<_>>>()?,
+            _ => return None
+        };
+
+        let fallback = match &entry["fallback"] {
+            JsonValue::Null => None,
+            fallback => Some(fallback.as_str()?.to_owned())
+        };
+
+        return Some(StaticDirConfig {
+            path: entry["path"].as_str()?.to_owned(),
+            dir: entry["dir"].as_str()?.to_owned(),
+            index,
+            fallback
+        });
+    }
+}
+
+/// Certificates for TLS listeners, without `listen` array TLS listener on `port` is started when at least one is configured
+pub struct TlsConfig {
+    pub port: u16,
+    /// Certificate chains with private keys, selected by SNI.
+    /// `tls.cert` and `tls.key` make the default one, `tls.certificates` adds named ones.
+    pub certificates: Vec<CertificateConfig>,
+    /// How often certificate files are checked for changes, `0` disables reloading
+    pub reload_interval: Duration
+}
+
+pub struct CertificateConfig {
+    /// Path to PEM file with certificate chain, leaf certificate goes first
+    pub cert: String,
+    /// Path to PEM file with private key
+    pub key: String,
+    /// Server names this certificate is served for, `*.` prefix matches one label.
+    /// Empty list marks the certificate used when no name matches.
+    pub names: Vec<String>
+}
+
+impl TlsConfig {
+    pub const fn default () -> Self {
+        TlsConfig {
+            port: 8443,
+            certificates: Vec::new(),
+            reload_interval: Duration::from_secs(60)
+        }
+    }
+
+    fn load (&mut self, config: &JsonValue) {
+        let tls = &config["tls"];
+        if let Some(port) = Config::parse_env("TLS_PORT") {
+            self.port = port;
+        } else if let Some(port) = tls["port"].as_u16() {
+            self.port = port;
+        }
+
+        if let Some(reload_interval) = tls["reload_interval"].as_u64() {
+            self.reload_interval = Duration::from_secs(reload_interval);
+        }
+
+        if !tls["cert"].is_null() || !tls["key"].is_null() {
+            self.certificates.push(TlsConfig::read_certificate(tls, "tls"));
+        }
+
+        let certificates = json_read_array(
+            &tls["certificates"],
+            |entry| Some(TlsConfig::read_certificate(entry, "tls.certificates[...]")),
+            bake_fatal("Config parsing error: tls.certificates must be an array")
+        );
+
+        if let Some(list) = certificates {
+            self.certificates.extend(list);
+        }
+    }
+
+    fn read_certificate (entry: &JsonValue, prefix: &str) -> CertificateConfig {
+        let (Some(cert), Some(key)) = (entry["cert"].as_str(), entry["key"].as_str()) else {
+            log_error(&format!("Config parsing error: {prefix} must have cert and key paths"));
+            process::exit(-1);
+        };
+
+        let names = json_read_array(
+            &entry["names"],
+            |name| name.as_str().map(str::to_ascii_lowercase),
+            bake_fatal("Config parsing error: tls.certificates[...].names[...] must be a string")
+        );
+
+        return CertificateConfig {
+            cert: cert.to_owned(),
+            key: key.to_owned(),
+            names: names.unwrap_or_default()
+        };
+    }
+}
@@ -1,3 +1,5 @@
+#![allow(clippy::missing_safety_doc)]
+
 use std::ops::Deref;
 
 use json::JsonValue;
@@ -10,6 +12,6 @@ pub extern "C" fn get_config () -> *const Config {
 }
 
 #[no_mangle]
-pub unsafe extern "C" fn config_get_path<'a> (config: &'a Config, path: c_str) -> &'a JsonValue {
+pub unsafe extern "C" fn config_get_path (config: &Config, path: c_str) -> &JsonValue {
 	return config.get_path(c_string(path).split('.').collect());
 }
@@ -1,12 +1,16 @@
 use crate::{app::modules::Module, websocket::WebSocketEndpoints};
 use self::router::Router;
 
+pub mod activation;
 pub mod config;
 pub mod config_c;
 pub mod modules;
 pub mod server;
+pub mod server_c;
+pub mod static_files;
 pub mod router;
 pub mod router_c;
+pub mod tls;
 
 
 pub struct App {
@@ -16,6 +20,7 @@ pub struct App {
 }
 
 impl App {
+	#[allow(clippy::new_without_default)]
 	pub fn new () -> Self {
 		App {
 			ws_endpoints: WebSocketEndpoints::empty(),
@@ -6,6 +6,7 @@ use crate::{app::router::Router, utils::log::{log_error, log_info}};
 
 
 type InitModuleFn = extern "C" fn ();
+type DeinitModuleFn = extern "C" fn ();
 type ProvideDatabaseFn = fn () -> Box<dyn DatabaseImpl>;
 type ProvideModelsFn = extern "C" fn ();
 type ProvideRoutesFn = extern "C" fn (router: *mut Router);
@@ -20,6 +21,9 @@ pub struct Module {
 	init_module: Option<Symbol<'this, InitModuleFn>>,
 	#[borrows(lib)]
 	#[covariant]
+	deinit_module: Option<Symbol<'this, DeinitModuleFn>>,
+	#[borrows(lib)]
+	#[covariant]
 	provide_database: Option<Symbol<'this, ProvideDatabaseFn>>,
 	#[borrows(lib)]
 	#[covariant]
@@ -35,6 +39,7 @@ impl Module {
 			name: name.to_owned(),
 			lib: unsafe { Library::new(path) }?,
 			init_module_builder: |lib: &Library| unsafe { lib.get(b"init_module") }.ok(),
+			deinit_module_builder: |lib: &Library| unsafe { lib.get(b"deinit_module") }.ok(),
 			provide_database_builder: |lib: &Library| unsafe { lib.get(b"provide_database") }.ok(),
 			provide_models_builder: |lib: &Library| unsafe { lib.get(b"provide_models") }.ok(),
 			provide_routes_builder: |lib: &Library| unsafe { lib.get(b"provide_routes") }.ok(),
@@ -54,7 +59,17 @@ impl Module {
 
 	#[inline]
 	pub fn get_name (&self) -> &str {
-		return &self.borrow_name();
+		return self.borrow_name();
+	}
+
+	/// Lets module release its resources, called on shutdown after all requests are finished
+	pub fn deinit (&self) {
+		self.with_deinit_module(|symbol| {
+			if let Some(call) = symbol {
+				log_info(&format!("{}: calling deinit", self.get_name()));
+				call();
+			}
+		});
 	}
 
 	pub fn provide_database (&self) -> Option<Box<dyn DatabaseImpl>> {
@@ -1,27 +1,62 @@
 use std::collections::HashMap;
-use crate::{context::http::HttpContext, http::entity::ResponseRet, utils::log::log_info};
+use std::io::Write;
+use std::sync::Arc;
+use crate::{app::static_files::StaticDir, context::http::HttpContext, http::{entity::{HttpMethod, MethodSet, Request, ResponseRet}, multipart::PartInfo, url::percent_decode}, utils::log::log_info};
 
 type ActionCallerType = dyn Fn(&mut HttpContext) -> ResponseRet + Sync + Send + 'static;
+type UploadSinkType = dyn Fn(&Request, &PartInfo) -> Option<Box<dyn Write>> + Sync + Send + 'static;
 
 pub struct Route {
     pub matcher: PathMatcher,
+    pub methods: MethodSet,
     pub call: Box<ActionCallerType>,
     // Module name can be used for unloading later
-    pub origin_module: Option<String>
+    pub origin_module: Option<String>,
+    /// Name of the only listener serving the route, on others it doesn't exist
+    pub listener: Option<String>
 }
 
 impl Route {
-    pub fn new (pattern: String, action: Box<ActionCallerType>) -> Self {
+    pub fn new (methods: MethodSet, pattern: String, action: Box<ActionCallerType>) -> Self {
         return Route {
             matcher: PathMatcher::from_pattern(pattern),
+            methods,
             call: action,
-            origin_module: None
+            origin_module: None,
+            listener: None
         };
     }
+
+    #[inline]
+    pub fn is_served_on (&self, listener: &str) -> bool {
+        return self.listener.as_ref().is_none_or(|name| name == listener);
+    }
+
+    /// `HEAD` requests are served by `GET` handlers
+    pub fn allows (&self, method: HttpMethod) -> bool {
+        return self.methods.contains(method) || (method == HttpMethod::HEAD && self.methods.contains(HttpMethod::GET));
+    }
+}
+
+/// Destination of files uploaded to matching paths, it gets request without body
+pub struct UploadSink {
+    pub matcher: PathMatcher,
+    pub methods: MethodSet,
+    pub sink: Box<UploadSinkType>
+}
+
+pub enum RouteMatch<'a> {
+    Found(&'a Route, HashMap<String, String>),
+    /// Path is known, but none of its routes accepts the method, contains methods that are accepted
+    MethodNotAllowed(MethodSet),
+    NotFound
 }
 
 pub struct Router {
     pub routes: Vec<Route>,
+    /// Checks run before the body of `Expect: 100-continue` request is accepted
+    pub expect_checks: Vec<Route>,
+    pub upload_sinks: Vec<UploadSink>,
     origin_module: Option<String>
 }
 
@@ -29,35 +64,148 @@ impl Router {
     pub const fn empty () -> Self {
         Router {
             routes: Vec::new(),
+            expect_checks: Vec::new(),
+            upload_sinks: Vec::new(),
             origin_module: None
         }
     }
 
+    /// Registers route accepting any method
     #[inline]
     pub fn register<Caller: Fn(&mut HttpContext) -> ResponseRet + Sync + Send + 'static> (&mut self, pattern: String, action: Caller) {
-        let reg_msg = format!("registered route '{pattern}' to {:p}", &action);
+        self.register_methods(MethodSet::ALL, pattern, action);
+    }
+
+    pub fn register_methods<Caller: Fn(&mut HttpContext) -> ResponseRet + Sync + Send + 'static> (&mut self, methods: MethodSet, pattern: String, action: Caller) {
+        let route = self.create_route("route", methods, pattern, Box::new(action));
+        self.routes.push(route);
+    }
+
+    /// Registers route served only on the named listener, e.g. for admin endpoints
+    pub fn register_on<Caller: Fn(&mut HttpContext) -> ResponseRet + Sync + Send + 'static> (&mut self, listener: &str, methods: MethodSet, pattern: String, action: Caller) {
+        let mut route = self.create_route(&format!("route on '{listener}'"), methods, pattern, Box::new(action));
+        route.listener = Some(listener.to_owned());
+        self.routes.push(route);
+    }
+
+    /// Serves files of `dir` for `GET` and `HEAD` requests of paths starting with `prefix`
+    pub fn serve_dir (&mut self, prefix: &str, dir: StaticDir) {
+        let prefix = prefix.trim_end_matches('/');
+        let dir = Arc::new(dir);
+
+        // Mount point itself is redirected to its slashed form, where index file is served
+        if !prefix.is_empty() {
+            let dir = dir.clone();
+            self.register_methods(HttpMethod::GET.into(), prefix.to_owned(), move |ctx| dir.serve(ctx, ""));
+        }
+
+        let root = dir.clone();
+        self.register_methods(HttpMethod::GET.into(), format!("{prefix}/"), move |ctx| root.serve(ctx, ""));
+        self.register_methods(HttpMethod::GET.into(), format!("{prefix}/{{path}}"), move |ctx| {
+            let path = ctx.params.get("path").cloned().unwrap_or_default();
+            return dir.serve(ctx, &path);
+        });
+    }
+
+    /// Registers check for requests with `Expect: 100-continue`, it gets context without body
+    /// and rejects the request by setting or replacing the response, so body is never sent
+    pub fn register_expect<Caller: Fn(&mut HttpContext) -> ResponseRet + Sync + Send + 'static> (&mut self, methods: MethodSet, pattern: String, check: Caller) {
+        let route = self.create_route("expect check", methods, pattern, Box::new(check));
+        self.expect_checks.push(route);
+    }
+
+    /// Registers where files of `multipart/form-data` requests go while their body is read,
+    /// sink giving `None` for a part leaves it in a temporary file
+    pub fn register_upload<Sink: Fn(&Request, &PartInfo) -> Option<Box<dyn Write>> + Sync + Send + 'static> (&mut self, methods: MethodSet, pattern: String, sink: Sink) {
+        let sink: Box<UploadSinkType> = Box::new(sink);
+        self.log_registration(&format!("upload sink '{pattern}'"), methods, &format!("{:p}", &sink));
+        self.upload_sinks.push(UploadSink { matcher: PathMatcher::from_pattern(pattern), methods, sink });
+    }
+
+    fn create_route (&self, kind: &str, methods: MethodSet, pattern: String, action: Box<ActionCallerType>) -> Route {
+        self.log_registration(&format!("{kind} '{pattern}'"), methods, &format!("{:p}", &action));
+
+        let mut route = Route::new(methods, pattern, action);
+        route.origin_module = self.origin_module.clone();
+        return route;
+    }
+
+    fn log_registration (&self, subject: &str, methods: MethodSet, target: &str) {
+        let methods_str = if methods == MethodSet::ALL { "*".to_owned() } else { methods.to_header() };
+        let reg_msg = format!("registered {subject} [{methods_str}] to {target}");
 
-        let mut route = Route::new(pattern, Box::new(action));
         if let Some(ref mod_name) = self.origin_module {
-            route.origin_module = Some(mod_name.clone());
             log_info(&format!("{mod_name}: {reg_msg}"));
         } else {
             log_info(&format!("core: {reg_msg}"));
         }
+    }
 
-        self.routes.push(route);
+    /// Finds route for request which came through `listener`
+    pub fn match_route (&self, method: HttpMethod, path: &str, listener: &str) -> RouteMatch<'_> {
+        let mut allowed = MethodSet::EMPTY;
+        for route in self.routes.iter().filter(|route| route.is_served_on(listener)) {
+            if let Some(params) = route.matcher.exec(path) {
+                if route.allows(method) {
+                    return RouteMatch::Found(route, params);
+                }
+
+                allowed = allowed | route.methods;
+            }
+        }
+
+        if allowed.is_empty() {
+            return RouteMatch::NotFound;
+        } else {
+            return RouteMatch::MethodNotAllowed(Router::complete_allowed(allowed));
+        }
     }
 
-    pub fn match_path (&self, path: &String) -> Option<(&Route, HashMap<String, String>)> {
-        for route in &self.routes {
-            if let Some(params) = route.matcher.exec(&path) {
-                return Some((route, params));
+    /// Finds expectation check for request which came through `listener`
+    pub fn match_expect (&self, method: HttpMethod, path: &str, listener: &str) -> Option<(&Route, HashMap<String, String>)> {
+        for check in self.expect_checks.iter().filter(|check| check.is_served_on(listener)) {
+            if check.allows(method) {
+                if let Some(params) = check.matcher.exec(path) {
+                    return Some((check, params));
+                }
             }
         }
 
         return None;
     }
 
+    /// Opens destination of uploaded file from the first sink matching the request, if there is any
+    pub fn open_upload (&self, req: &Request, part: &PartInfo) -> Option<Box<dyn Write>> {
+        let upload = self.upload_sinks.iter().find(|upload| upload.methods.contains(req.method) && upload.matcher.exec(&req.path).is_some())?;
+        return (upload.sink)(req, part);
+    }
+
+    /// Collects methods of all routes matching `path`, empty set means that path is unknown
+    pub fn allowed_methods (&self, path: &str, listener: &str) -> MethodSet {
+        let mut allowed = MethodSet::EMPTY;
+        for route in self.routes.iter().filter(|route| route.is_served_on(listener)) {
+            if route.matcher.exec(path).is_some() {
+                allowed = allowed | route.methods;
+            }
+        }
+
+        if allowed.is_empty() {
+            return allowed;
+        } else {
+            return Router::complete_allowed(allowed);
+        }
+    }
+
+    /// Adds methods that are handled implicitly: `HEAD` for `GET` routes and `OPTIONS` handled by core
+    fn complete_allowed (mut allowed: MethodSet) -> MethodSet {
+        if allowed.contains(HttpMethod::GET) {
+            allowed.insert(HttpMethod::HEAD);
+        }
+
+        allowed.insert(HttpMethod::OPTIONS);
+        return allowed;
+    }
+
     pub fn with_module<C: Fn (&mut Self)> (&mut self, name: &str, consume: C) {
         self.origin_module = Some(name.to_owned());
         consume(self);
@@ -72,6 +220,7 @@ enum PathPart {
 
 pub struct PathMatcher(Vec<PathPart>);
 impl PathMatcher {
+    #[allow(clippy::len_zero)]
     pub fn from_pattern (pattern: String) -> Self {
         let mut sequence = Vec::new();
 
@@ -111,12 +260,13 @@ impl PathMatcher {
         return PathMatcher(sequence);
     }
 
-    pub fn exec (&self, path: &String) -> Option<HashMap<String, String>> {
+    #[allow(clippy::redundant_pattern_matching)]
+    pub fn exec (&self, path: &str) -> Option<HashMap<String, String>> {
         let mut offset = 0usize;
         let mut path_iter = path.chars();
         let mut params: HashMap<String, String> = HashMap::new();
 
-        for part in &self.0 {
+        for (index, part) in self.0.iter().enumerate() {
             match part {
                 PathPart::String(value) => {
                     let mut part_iter = value.chars();
@@ -126,6 +276,9 @@ impl PathMatcher {
                             if next_ch.is_none() || ch != next_ch.unwrap() {
                                 return None;
                             }
+                        } else if index + 1 < self.0.len() {
+                            // Rest of the path belongs to the following variable
+                            break;
                         } else {
                             if let None = path_iter.next() { break; }
                             else { return None; }
@@ -135,17 +288,20 @@ impl PathMatcher {
                     offset += value.len();
                 }
                 PathPart::Variable(name, stop_char) => {
+                    // Counting bytes, not chars, so multibyte paths are sliced correctly
                     let mut i = 0usize;
                     loop {
-                        let next = path_iter.next();
-                        if next.is_none() || next.unwrap() == *stop_char { break }
-                        i += 1;
+                        match path_iter.next() {
+                            Some(ch) if ch != *stop_char => i += ch.len_utf8(),
+                            _ => break
+                        }
                     }
 
                     if offset >= path.len() { return None; }
                     let value = &path[(offset)..(offset + i)];
-                    offset += i + 1;
-					params.insert(name.to_string(), value.to_string());
+                    offset += i + stop_char.len_utf8();
+                    // Path that isn't valid encoding wasn't normalized, its params are given as they are
+                    params.insert(name.to_string(), percent_decode(value).unwrap_or_else(|| value.to_string()));
                 }
             }
         }
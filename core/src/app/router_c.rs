@@ -1,5 +1,20 @@
-use crate::{app::router::Router, c::{c_str, c_string, c_unwrap}, context::http::HttpContext, http::entity::{Response, ResponseRet}};
+#![allow(clippy::missing_safety_doc)]
 
+use crate::{app::{router::Router, static_files::StaticDir}, c::{c_str, c_string, c_unwrap}, context::http::HttpContext, http::entity::{MethodSet, Response, ResponseRet}};
+
+/// Handler returns response replacing the one of the context or null to keep it
+type CAction = extern "C" fn (*mut HttpContext) -> *mut Response;
+
+fn c_action (action: CAction) -> impl Fn(&mut HttpContext) -> ResponseRet + Sync + Send + 'static {
+	return move |ctx| {
+		let res = (action)(ctx);
+		if res.is_null() {
+			return ResponseRet::Return;
+		} else {
+			return ResponseRet::Replace(unsafe { c_unwrap(res) });
+		}
+	};
+}
 
 // #[no_mangle]
 // pub extern "C" fn router_new () -> *mut Router {
@@ -8,15 +23,53 @@ use crate::{app::router::Router, c::{c_str, c_string, c_unwrap}, context::http::
 
 
 #[no_mangle]
-pub unsafe extern "C" fn router_register (router: &mut Router, pattern: c_str, action: extern "C" fn (*mut HttpContext) -> *mut Response) {
-	router.register(c_string(pattern), move |ctx| {
-		let res = (action)(ctx);
-		if res.is_null() {
-			return ResponseRet::Return;
-		} else {
-			return ResponseRet::Replace(c_unwrap(res));
-		}
-	});
+pub unsafe extern "C" fn router_register (router: &mut Router, pattern: c_str, action: CAction) {
+	router_register_methods(router, MethodSet::ALL.bits(), pattern, action);
+}
+
+/// `methods` is a bit mask, see `MethodSet` for bits order
+#[no_mangle]
+pub unsafe extern "C" fn router_register_methods (
+	router: &mut Router,
+	methods: u16,
+	pattern: c_str,
+	action: CAction
+) {
+	router.register_methods(MethodSet::from_bits(methods), c_string(pattern), c_action(action));
+}
+
+/// Route is served only on the listener with `listener` name from `listen` config
+#[no_mangle]
+pub unsafe extern "C" fn router_register_on (
+	router: &mut Router,
+	listener: c_str,
+	methods: u16,
+	pattern: c_str,
+	action: CAction
+) {
+	router.register_on(&c_string(listener), MethodSet::from_bits(methods), c_string(pattern), c_action(action));
+}
+
+/// Check rejects the request by returning response or setting it on the context, otherwise body is accepted
+#[no_mangle]
+pub unsafe extern "C" fn router_register_expect (
+	router: &mut Router,
+	methods: u16,
+	pattern: c_str,
+	check: CAction
+) {
+	router.register_expect(MethodSet::from_bits(methods), c_string(pattern), c_action(check));
+}
+
+/// Serves files of `dir` under `prefix`, `fallback` is nullable path of file served instead of missing ones
+#[no_mangle]
+pub unsafe extern "C" fn router_serve_dir (router: &mut Router, prefix: c_str, dir: c_str, fallback: c_str) {
+	let mut static_dir = StaticDir::new(c_string(dir));
+	if !fallback.is_null() {
+		static_dir = static_dir.with_fallback(&c_string(fallback));
+	}
+
+	router.serve_dir(&c_string(prefix), static_dir);
 }
 
 // #[no_mangle]
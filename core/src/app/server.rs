@@ -1,110 +1,663 @@
-use std::io::Error;
-use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::fmt::Display;
+use std::fs;
+use std::io::{self, Error, ErrorKind, Write};
+use std::net::{Shutdown, SocketAddr, ToSocketAddrs};
+#[cfg(unix)]
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+#[cfg(unix)]
+use std::os::unix::net::UnixListener;
+#[cfg(unix)]
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, SystemTime};
+use httpdate::fmt_http_date;
+use signal_hook::consts::{SIGINT, SIGTERM};
+use socket2::{Domain, Type};
 use threadpool::ThreadPool;
 
-use crate::app::config::CONFIG;
+use crate::app::config::{ListenAddress, ListenConfig, CONFIG};
+use crate::http::compression::{compress_response, decompress_request, REQUEST_ENCODINGS};
+use crate::http::multipart::{self, Multipart};
+use crate::http::url::{encode_path, normalize_path};
 use crate::http::cors::Cors;
 use crate::utils::log::*;
+use crate::utils::reactor::{Idle, Listener, LoadStats, Reactor};
+use crate::utils::proxy::read_proxy_header;
+use crate::utils::socket::{Socket, SocketListener, Stream, Transport};
 use super::App;
+use super::activation::inherited_listeners;
+use super::router::RouteMatch;
+use super::tls::{TlsAcceptor, ALPN_H2};
 use crate::context::http::HttpContext;
 use crate::context::ws::SocketContext;
 use crate::http::{entity::*, codes::HttpCode};
 use crate::http1::{Http1Engine, Http1Connection};
-use crate::websocket::{websocket_handshake, HandshakeResult, maintain_websocket};
-
-
-pub fn start_server (app: &'static App) {
-    let bind_address = format!("{}:{}", CONFIG.host, CONFIG.port);
-
-	match TcpListener::bind(&bind_address) {
-        Ok(listener) => {
-            let pool = ThreadPool::new(32);
-			log_success(&format!("Listening on {}", bind_address));
-
-			loop {
-				let socket = listener.accept().unwrap();
-				pool.execute(move || proceed_connection::<Http1Engine, Http1Connection>(app, socket));
-			}
-		}
-		Err(error) => {
-            log_error_lines("Listen error", error.to_string());
-		}
-	}
-}
-
-fn proceed_connection<Http: HttpEngine<Connection> + Send, Connection: HttpConnection>
-(app: &App, socket: (TcpStream, SocketAddr)) {
-    let mut connection = Http::handle_connection(socket);
-
-    match connection.parse() {
-        ParsingResult::Complete(req) => {
-            if is_connection_upgrade(&req) {
-                if is_websocket_upgrade(&req) {
-                    proceed_websocket::<Connection>(app, connection, req);
-                } else {
-                    let _ = connection.respond(Response::from_status(HttpCode::BadRequest));
+use crate::http2::{Http2Engine, Http2Connection};
+use crate::websocket::{websocket_handshake, HandshakeResult, WebSocketSession};
+
+
+/// Load of the running server, e.g. for health checks
+pub static LOAD: LoadStats = LoadStats::new();
+
+/// Set by the first SIGINT/SIGTERM, listeners are closed and running requests are finished
+static SHUTDOWN: AtomicBool = AtomicBool::new(false);
+
+/// Event loop of the running server, closed connections are drained by it
+static REACTOR: OnceLock<Arc<Reactor>> = OnceLock::new();
+
+#[inline]
+pub fn is_shutting_down () -> bool {
+    return SHUTDOWN.load(Ordering::Relaxed);
+}
+
+/// Serves connections until the shutdown signal, then waits for running requests within the shutdown timeout.
+/// Returns false when some requests are still running after the timeout.
+pub fn start_server (app: &'static App) -> bool {
+    let threads = CONFIG.workers.threads();
+    log_info(&format!("Starting {threads} workers"));
+
+    let reactor = match Reactor::new(ThreadPool::new(threads), &LOAD) {
+        Ok(reactor) => REACTOR.get_or_init(|| Arc::new(reactor)),
+        Err(error) => {
+            log_error_lines("Event loop error", error.to_string());
+            process::exit(-1);
+        }
+    };
+
+    let mut acceptor = None;
+    if CONFIG.listen.iter().any(|listen| listen.tls) {
+        let tls = match TlsAcceptor::new(&CONFIG.tls) {
+            Ok(tls) => Arc::new(tls),
+            Err(error) => {
+                log_error_lines("TLS setup error", error);
+                process::exit(-1);
+            }
+        };
+
+        tls.watch(CONFIG.tls.reload_interval);
+        acceptor = Some(tls);
+    }
+
+    let serve = |listener: SocketListener, address: &dyn Display, name: &str, config: Option<&ListenConfig>| {
+        let is_tls = config.is_some_and(|config| config.tls);
+        log_success(&format!("Listening on {address}{}", if is_tls { " (TLS)" } else { "" }));
+        reactor.listen(Box::new(HttpListener {
+            listener,
+            name: Arc::from(name),
+            app,
+            tls: if is_tls { acceptor.clone() } else { None },
+            proxy_protocol: config.is_some_and(|config| config.proxy_protocol),
+            is_overloaded: false
+        }));
+    };
+
+    // Sockets opened by systemd replace configured ones, their settings are taken from `listen` entry of the same name
+    let inherited = inherited_listeners();
+    let is_activated = !inherited.is_empty();
+    for socket in inherited {
+        let config = CONFIG.listen.iter().find(|config| !config.name.is_empty() && config.name == socket.name);
+        serve(socket.listener, &format_args!("inherited {}", socket.address), &socket.name, config);
+    }
+
+    if !is_activated {
+        // Every address is bound before serving, so the server doesn't run with a part of its listeners
+        let mut listeners = Vec::with_capacity(CONFIG.listen.len());
+        for config in &CONFIG.listen {
+            match bind(config) {
+                Ok(listener) => listeners.push((listener, config)),
+                Err(error) => {
+                    log_error_lines(&format!("Listen error on {}", config.address), error.to_string());
+                    process::exit(-1);
                 }
-            } else {
-                let _ = proceed_http::<Connection>(app, connection, req);
             }
         }
-        ParsingResult::Partial => {}
-        ParsingResult::Error(res_code) => {
-            let _ = connection.respond(Response::from_status(res_code));
-            let _ = connection.disconnect();
+
+        for (listener, config) in listeners {
+            serve(listener, &config.address, &config.name, Some(config));
+        }
+    }
+
+    handle_signals();
+    reactor.run(&SHUTDOWN);
+
+    log_info(&format!("Shutting down, waiting for {} running requests", LOAD.queued() + LOAD.active()));
+    if !reactor.wait_idle(CONFIG.timeouts.shutdown) {
+        log_warning(&format!("Shutdown timeout is over, {} requests are interrupted", LOAD.queued() + LOAD.active()));
+        return false;
+    }
+
+    return true;
+}
+
+fn bind (config: &ListenConfig) -> io::Result<SocketListener> {
+    match &config.address {
+        ListenAddress::Tcp(address) => {
+            let Some(address) = address.to_socket_addrs()?.next() else {
+                return Err(io::Error::new(ErrorKind::InvalidInput, "address resolved to nothing"));
+            };
+
+            let socket = socket2::Socket::new(Domain::for_address(address), Type::STREAM, None)?;
+            #[cfg(unix)]
+            socket.set_reuse_address(true)?;
+            if let (true, Some(ipv6_only)) = (address.is_ipv6(), config.ipv6_only) {
+                socket.set_only_v6(ipv6_only)?;
+            }
+
+            socket.bind(&address.into())?;
+            socket.listen(1024)?;
+            return Ok(SocketListener::Tcp(socket.into()));
+        }
+        #[cfg(unix)]
+        ListenAddress::Unix(path) => {
+            // Socket file left by the previous run would make binding fail
+            if fs::symlink_metadata(path).is_ok_and(|meta| meta.file_type().is_socket()) {
+                fs::remove_file(path)?;
+            }
+
+            let listener = UnixListener::bind(path)?;
+            if let Some(mode) = config.mode {
+                fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+            }
+
+            return Ok(SocketListener::Unix(listener, Some(PathBuf::from(path))));
+        }
+        #[cfg(not(unix))]
+        ListenAddress::Unix(_) => {
+            return Err(io::Error::new(ErrorKind::Unsupported, "Unix domain sockets aren't supported on this platform"));
         }
-        ParsingResult::Invalid => {
-            let _ = connection.disconnect();
+    }
+}
+
+/// First signal starts graceful shutdown, the second one exits immediately
+fn handle_signals () {
+    for signal in [SIGINT, SIGTERM] {
+        // Safety: handler only touches an atomic and exits, both are async-signal-safe
+        let result = unsafe {
+            signal_hook::low_level::register(signal, || {
+                if SHUTDOWN.swap(true, Ordering::Relaxed) {
+                    signal_hook::low_level::exit(130);
+                }
+            })
+        };
+
+        if let Err(error) = result {
+            log_warning(&format!("Signal handler error: {error}"));
         }
     }
 }
 
-fn proceed_http<Connection: HttpConnection> (app: &App, mut connection: Connection, req: Request) -> Result<(), Error> {
+/// Accepted sockets wait for the first bytes in the event loop,
+/// while workers are behind by more than the queue limit new ones are turned away
+struct HttpListener {
+    listener: SocketListener,
+    name: Arc<str>,
+    app: &'static App,
+    tls: Option<Arc<TlsAcceptor>>,
+    proxy_protocol: bool,
+    is_overloaded: bool
+}
+
+impl Listener for HttpListener {
+    fn socket (&self) -> &SocketListener { &self.listener }
+
+    fn accept (&mut self, reactor: &Reactor) {
+        loop {
+            let (stream, address) = match self.listener.accept() {
+                Ok(socket) => socket,
+                Err(error) if error.kind() == ErrorKind::WouldBlock => return,
+                // Aborted handshakes and exhausted descriptors shouldn't stop the listener
+                Err(error) => {
+                    log_warning(&format!("Accept error: {error}"));
+                    return;
+                }
+            };
+
+            // Some systems pass non-blocking mode of the listener to accepted sockets
+            if stream.set_nonblocking(false).is_err() {
+                continue;
+            }
+
+            if LOAD.queued() >= CONFIG.workers.queue {
+                if !self.is_overloaded {
+                    log_warning(&format!("Worker queue is full ({} connections), rejecting new ones", LOAD.queued()));
+                    self.is_overloaded = true;
+                }
+
+                reject_connection(reactor, stream, self.tls.is_none());
+                continue;
+            }
+
+            if self.is_overloaded {
+                log_info(&format!("Worker queue is freed, {} connections rejected so far", LOAD.rejected()));
+                self.is_overloaded = false;
+            }
+
+            reactor.park(Box::new(AcceptedSocket {
+                app: self.app,
+                stream,
+                address,
+                listener: self.name.clone(),
+                tls: self.tls.clone(),
+                proxy_protocol: self.proxy_protocol
+            }));
+        }
+    }
+}
+
+/// Answers `503` without taking a worker, TLS connections are just closed since they can't be answered before handshake
+fn reject_connection (reactor: &Reactor, stream: Stream, is_plain: bool) {
+    LOAD.add_rejected();
+    if stream.set_nonblocking(true).is_err() {
+        return;
+    }
+
+    if is_plain {
+        let response = format!(
+            "HTTP/1.1 503 Service Unavailable\r\ndate: {}\r\nretry-after: {}\r\ncontent-length: 0\r\nconnection: close\r\n\r\n",
+            fmt_http_date(SystemTime::now()),
+            CONFIG.workers.retry_after
+        );
+
+        // Fresh socket has empty send buffer, so such small response fits
+        let _ = (&stream).write_all(response.as_bytes());
+    }
+
+    let _ = stream.shutdown(Shutdown::Write);
+    reactor.linger(stream);
+}
+
+/// Socket which hasn't sent anything yet
+struct AcceptedSocket {
+    app: &'static App,
+    stream: Stream,
+    address: SocketAddr,
+    listener: Arc<str>,
+    tls: Option<Arc<TlsAcceptor>>,
+    proxy_protocol: bool
+}
+
+impl AcceptedSocket {
+    /// Replaces balancer address with the client one from PROXY header, `None` means connection must be closed
+    fn read_proxy_header (&mut self) -> Option<()> {
+        let timeout = Some(CONFIG.timeouts.header).filter(|timeout| !timeout.is_zero());
+        self.stream.set_read_timeout(timeout).ok()?;
+
+        match read_proxy_header(&mut self.stream) {
+            Ok(Some(address)) => self.address = address,
+            Ok(None) => {}
+            Err(error) => {
+                log_warning(&format!("PROXY protocol error from {}: {error}", self.address));
+                return None;
+            }
+        }
+
+        return self.stream.set_read_timeout(None).ok();
+    }
+}
+
+impl Idle for AcceptedSocket {
+    fn socket (&self) -> &Stream { &self.stream }
+    fn timeout (&self) -> Duration { CONFIG.timeouts.header }
+
+    /// HTTP version over TLS is chosen by ALPN, clients not using it get HTTP/1.1
+    fn resume (mut self: Box<Self>) -> Option<Box<dyn Idle>> {
+        // PROXY header goes before anything else, including TLS handshake
+        if self.proxy_protocol {
+            self.read_proxy_header()?;
+        }
+
+        let mut socket = match &self.tls {
+            Some(acceptor) => acceptor.accept(self.stream).ok()?,
+            None => Socket::new(self.stream)
+        };
+
+        socket.set_listener(self.listener);
+
+        if socket.alpn_protocol() == Some(ALPN_H2) {
+            return proceed_connection::<Http2Engine, Http2Connection>(self.app, (socket, self.address));
+        } else {
+            return proceed_connection::<Http1Engine, Http1Connection>(self.app, (socket, self.address));
+        }
+    }
+
+    fn expire (self: Box<Self>) -> Option<Box<dyn Idle>> {
+        return None;
+    }
+}
+
+/// Persistent connection waiting for the next request
+struct IdleConnection<Connection> {
+    app: &'static App,
+    connection: Connection
+}
+
+impl<Connection: HttpConnection<Transport = Socket> + 'static> Idle for IdleConnection<Connection> {
+    fn socket (&self) -> &Stream { self.connection.get_transport().get_stream() }
+    fn timeout (&self) -> Duration { self.connection.idle_timeout() }
+
+    fn resume (self: Box<Self>) -> Option<Box<dyn Idle>> {
+        return serve_connection(self.app, self.connection);
+    }
+
+    fn expire (self: Box<Self>) -> Option<Box<dyn Idle>> {
+        close_connection(self.connection);
+        return None;
+    }
+
+    fn close (self: Box<Self>) {
+        close_connection(self.connection);
+    }
+}
+
+struct IdleWebSocket {
+    app: &'static App,
+    session: WebSocketSession
+}
+
+impl Idle for IdleWebSocket {
+    fn socket (&self) -> &Stream { self.session.ctx.stream.get_ref().get_ref().get_stream() }
+    fn timeout (&self) -> Duration { CONFIG.timeouts.websocket }
+
+    fn resume (mut self: Box<Self>) -> Option<Box<dyn Idle>> {
+        self.session.receive(self.app).ok()?;
+        return Some(self);
+    }
+
+    fn expire (mut self: Box<Self>) -> Option<Box<dyn Idle>> {
+        self.session.ping().ok()?;
+        return Some(self);
+    }
+
+    fn close (mut self: Box<Self>) {
+        self.session.close();
+    }
+}
+
+fn proceed_connection<Http: HttpEngine<Connection> + Send, Connection: HttpConnection<Transport = Socket> + 'static>
+(app: &'static App, socket: (Socket, SocketAddr)) -> Option<Box<dyn Idle>> {
+    return serve_connection(app, Http::handle_connection(socket));
+}
+
+/// Serves requests while they come without waiting, then returns connection to wait in the event loop
+fn serve_connection<Connection: HttpConnection<Transport = Socket> + 'static> (app: &'static App, mut connection: Connection) -> Option<Box<dyn Idle>> {
+    loop {
+        if !connection.has_pending_input() {
+            return Some(Box::new(IdleConnection { app, connection }));
+        }
+
+        let mut req = match connection.parse_head() {
+            ParsingResult::Complete(req) => req,
+            ParsingResult::Error(res_code) => {
+                let _ = connection.respond(Response::from_status(res_code));
+                break;
+            }
+            ParsingResult::Http2 => {
+                let address = connection.get_address();
+                return serve_connection(app, Http2Connection::from_stream(connection.into_stream(), address, false));
+            }
+            // Connection goes to wait for the rest of the request in the event loop
+            ParsingResult::Partial => continue,
+            ParsingResult::Invalid => break
+        };
+
+        if let Some(res) = normalize_request_path(&mut req) {
+            if connection.respond(res).is_err() || !connection.is_persistent() {
+                break;
+            }
+
+            continue;
+        }
+
+        if let Some(res) = check_expectation(app, &connection, &req) {
+            if connection.respond(res).is_err() || !connection.is_persistent() {
+                break;
+            }
+
+            continue;
+        }
+
+        if let Err(res) = read_request_body(app, &mut connection, &mut req) {
+            // Connection that can't be used anymore is closed without response
+            let Some(res) = res else {
+                break;
+            };
+
+            if connection.respond(res).is_err() || !connection.is_persistent() {
+                break;
+            }
+
+            continue;
+        }
+
+        if is_connection_upgrade(&req) {
+            if is_websocket_upgrade(&req) {
+                return proceed_websocket::<Connection>(app, connection, req);
+            } else if let Some(settings) = get_h2c_settings(&req) {
+                let mut res = Response::from_status(HttpCode::SwitchingProtocols);
+                res.headers.set("connection".to_owned(), "Upgrade".to_owned());
+                res.headers.set("upgrade".to_owned(), "h2c".to_owned());
+                res.payload = ResponseType::Upgrade;
+                if connection.respond(res).is_err() {
+                    break;
+                }
+
+                let address = connection.get_address();
+                return serve_connection(app, Http2Connection::from_upgrade(connection.into_stream(), address, req, &settings));
+            }
+
+            // Upgrade to a protocol that isn't supported or enabled is ignored, request is served as usual
+        }
+
+        if proceed_http::<Connection>(app, &mut connection, req).is_err() || !connection.is_persistent() {
+            break;
+        }
+    }
+
+    close_connection(connection);
+    return None;
+}
+
+/// Closing socket with unread input resets the connection and client may lose responses it hasn't read yet,
+/// e.g. pipelined requests left after `Connection: close`, so the input is discarded by the event loop for a while
+fn close_connection<Connection: HttpConnection<Transport = Socket>> (connection: Connection) {
+    if let (Ok(socket), Some(reactor)) = (connection.disconnect(), REACTOR.get()) {
+        reactor.linger(socket.into_stream());
+    }
+}
+
+/// Replaces path of the request with its canonical form before routing, returns response for malformed
+/// or, when configured so, non-canonical path. Only origin-form targets are handled, `*` is left as is.
+fn normalize_request_path (req: &mut Request) -> Option<Response> {
+    if !req.path.starts_with('/') {
+        return None;
+    }
+
+    let Some(path) = normalize_path(&req.path) else {
+        return Some(Response::from_code(HttpCode::BadRequest, "Malformed request path"));
+    };
+
+    if CONFIG.redirect_non_canonical {
+        let canonical = encode_path(&path);
+        if canonical != req.path {
+            // Permanent redirect with method change allowed would turn other requests into `GET`
+            let code = if matches!(req.method, HttpMethod::GET | HttpMethod::HEAD) { HttpCode::MovedPermanently } else { HttpCode::PermanentRedirect };
+            let location = if req.query.is_empty() { canonical } else { format!("{canonical}?{}", req.query) };
+
+            let mut res = Response::from_status(code);
+            res.headers.set("location".to_owned(), location);
+            return Some(res);
+        }
+    }
+
+    req.path = path;
+    return None;
+}
+
+/// Reads body of the request, `multipart/form-data` one is parsed into fields and files when its route exists.
+/// Error gives response rejecting the request or `None` when the connection is broken.
+fn read_request_body<Connection: HttpConnection> (app: &App, connection: &mut Connection, req: &mut Request) -> Result<(), Option<Response>> {
+    let boundary = match app.router.match_route(req.method, &req.path, connection.get_transport().listener()) {
+        RouteMatch::Found(_, _) => req.headers.get("content-type").and_then(|content_type| multipart::boundary(&content_type)),
+        // Files aren't stored for requests that fail anyway
+        _ => None
+    };
+
+    // Parts go to upload sink or temp files as they arrive, so the body is limited by `uploads` instead of `limits.body`
+    let mut multipart = None;
+    if let Some(boundary) = &boundary {
+        multipart = connection.read_multipart(req, boundary, &mut |part| app.router.open_upload(req, part));
+    }
+
+    if multipart.is_none() {
+        match connection.read_body(req) {
+            Ok(()) => {}
+            // HTTP/2 stream error doesn't affect other streams
+            Err(ParsingResult::Error(res_code)) => return Err(Some(Response::from_status(res_code))),
+            Err(_) => return Err(None)
+        }
+
+        if let Err(res_code) = decompress_request(req) {
+            let mut res = Response::from_status(res_code);
+            if matches!(res.code, HttpCode::UnsupportedMediaType) {
+                res.headers.set("accept-encoding".to_owned(), REQUEST_ENCODINGS.to_owned());
+            }
+
+            return Err(Some(res));
+        }
+
+        // Body that couldn't be streamed is parsed from memory
+        if let Some(boundary) = &boundary {
+            multipart = Some(Multipart::read(req.body.as_slice(), boundary, &CONFIG.uploads, &mut |part| app.router.open_upload(req, part)));
+        }
+    }
+
+    match multipart {
+        Some(Ok(multipart)) => req.multipart = Some(Arc::new(multipart)),
+        Some(Err(error)) => return Err(Some(Response::from_status(error.status()))),
+        None => {}
+    }
+
+    return Ok(());
+}
+
+/// Decides whether client that sent `Expect` header may send the body, returns response to reject it with
+fn check_expectation<Connection: HttpConnection> (app: &App, connection: &Connection, req: &Request) -> Option<Response> {
+    let expect = req.headers.get("expect")?;
+    let cors = Cors::new(req);
+    let mut res;
+
+    if !expect.eq_ignore_ascii_case("100-continue") {
+        res = Response::from_status(HttpCode::ExpectationFailed);
+    } else if let HttpMethod::OPTIONS = req.method {
+        return None;
+    } else {
+        let listener = connection.get_transport().listener();
+        match app.router.match_route(req.method, &req.path, listener) {
+            RouteMatch::Found(_, _) => {
+                let (check, params) = app.router.match_expect(req.method, &req.path, listener)?;
+                let mut ctx = HttpContext::from(connection, req.clone(), params);
+                match (check.call)(&mut ctx) {
+                    ResponseRet::Replace(response) => res = response,
+                    _ if matches!(ctx.res.code, HttpCode::NotSent) => return None,
+                    _ => res = ctx.res
+                }
+            }
+            RouteMatch::MethodNotAllowed(methods) => res = method_not_allowed(methods),
+            RouteMatch::NotFound => res = not_found()
+        }
+    }
+
+    cors.apply_normal(&mut res);
+    return Some(res);
+}
+
+fn proceed_http<Connection: HttpConnection> (app: &App, connection: &mut Connection, req: Request) -> Result<(), Error> {
     let mut res;
     let cors = Cors::new(&req);
+    let accept_encoding = req.headers.get("accept-encoding");
 
     if let HttpMethod::OPTIONS = req.method {
-        res = Response::from_status(HttpCode::OK);
-        cors.apply_preflight(&mut res);
-    } else if let Some((endpoint, params)) = app.router.match_path(&req.path) {
-        let mut ctx = HttpContext::from(&connection, req, params);
-        match (endpoint.call)(&mut ctx) {
-            ResponseRet::Replace(response) => {
-                res = response;
-            },
-            _ => {
-                res = ctx.res;
-            }
+        let methods = app.router.allowed_methods(&req.path, connection.get_transport().listener());
+        if methods.is_empty() {
+            res = not_found();
+            cors.apply_normal(&mut res);
+        } else {
+            res = Response::from_status(HttpCode::OK);
+            res.headers.set("allow".to_owned(), methods.to_header());
+            cors.apply_preflight(&mut res, methods);
         }
-        cors.apply_normal(&mut res);
     } else {
-        res = Response::from_code(HttpCode::NotFound, "API endpoint not found");
+        match app.router.match_route(req.method, &req.path, connection.get_transport().listener()) {
+            RouteMatch::Found(endpoint, params) => {
+                let mut ctx = HttpContext::from(connection, req, params);
+                match (endpoint.call)(&mut ctx) {
+                    ResponseRet::Replace(response) => {
+                        res = response;
+                    },
+                    _ => {
+                        res = ctx.res;
+                    }
+                }
+            }
+            RouteMatch::MethodNotAllowed(methods) => {
+                res = method_not_allowed(methods);
+            }
+            RouteMatch::NotFound => {
+                res = not_found();
+            }
+        }
+
         cors.apply_normal(&mut res);
     }
 
-    connection.respond(res)?;
-    return connection.disconnect();
+    compress_response(accept_encoding.as_deref(), &mut res);
+    return connection.respond(res);
 }
 
-fn proceed_websocket<Connection: HttpConnection> (app: &App, mut connection: Connection, req: Request) {
+fn not_found () -> Response {
+    return Response::from_code(HttpCode::NotFound, "API endpoint not found");
+}
+
+fn method_not_allowed (methods: MethodSet) -> Response {
+    let mut res = Response::from_code(HttpCode::MethodNotAllowed, "Method not allowed");
+    res.headers.set("allow".to_owned(), methods.to_header());
+    return res;
+}
+
+fn proceed_websocket<Connection: HttpConnection<Transport = Socket>> (app: &'static App, mut connection: Connection, req: Request) -> Option<Box<dyn Idle>> {
     match websocket_handshake(app, &req) {
         HandshakeResult::Ok(endpoint_index, res) => {
             // todo: handle all `let _ = ...`
             let _ = connection.respond(res);
             let ctx = SocketContext::from::<Connection>(connection, req);
-            let _ = maintain_websocket(app, ctx, endpoint_index);
+            let mut session = WebSocketSession::new(ctx, endpoint_index);
+            // Messages sent right after the handshake may be already buffered, so they won't wake the event loop
+            session.receive(app).ok()?;
+            return Some(Box::new(IdleWebSocket { app, session }));
         }
         HandshakeResult::Err(res) => {
             let _ = connection.respond(res);
-            let _ = connection.disconnect();
+            close_connection(connection);
+            return None;
         }
     }
 }
 
 fn is_connection_upgrade (req: &Request) -> bool {
-    matches!(req.headers.get("connection"), Some(value) if value == "Upgrade")
+    req.headers.has_token("connection", "upgrade")
 }
 
 fn is_websocket_upgrade (req: &Request) -> bool {
-    matches!(req.headers.get("upgrade"), Some(value) if value == "websocket")
+    matches!(req.headers.get("upgrade"), Some(value) if value.eq_ignore_ascii_case("websocket"))
+}
+
+/// Decoded `HTTP2-Settings` of valid `Upgrade: h2c` request
+fn get_h2c_settings (req: &Request) -> Option<Vec<u8>> {
+    if !CONFIG.http2.enabled || !req.headers.has_token("upgrade", "h2c") || !req.headers.has_token("connection", "http2-settings") {
+        return None;
+    }
+
+    return base64::decode_config(req.headers.get("http2-settings")?.trim_end_matches('='), base64::URL_SAFE_NO_PAD).ok();
 }
@@ -0,0 +1,26 @@
+use crate::{app::server::LOAD, utils::reactor::LoadStats};
+
+#[no_mangle]
+pub extern "C" fn get_load_stats () -> *const LoadStats {
+	return &LOAD;
+}
+
+#[no_mangle]
+pub extern "C" fn load_stats_get_queued (stats: &LoadStats) -> usize {
+	return stats.queued();
+}
+
+#[no_mangle]
+pub extern "C" fn load_stats_get_active (stats: &LoadStats) -> usize {
+	return stats.active();
+}
+
+#[no_mangle]
+pub extern "C" fn load_stats_get_waiting (stats: &LoadStats) -> usize {
+	return stats.waiting();
+}
+
+#[no_mangle]
+pub extern "C" fn load_stats_get_rejected (stats: &LoadStats) -> u64 {
+	return stats.rejected();
+}
@@ -0,0 +1,299 @@
+use std::fs::{self, File, Metadata};
+use std::io::{self, ErrorKind, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+use httpdate::{fmt_http_date, parse_http_date};
+use crate::context::http::HttpContext;
+use crate::http::codes::HttpCode;
+use crate::http::entity::{Response, ResponseRet, ResponseStream, ResponseType};
+use crate::http::url::encode_path;
+use crate::utils::log::log_warning;
+
+/// Directory whose files are served as they are, with validators for caching and byte ranges
+pub struct StaticDir {
+    /// Canonical path, resolved files must stay inside of it
+    root: PathBuf,
+    index: Vec<String>,
+    fallback: Option<String>
+}
+
+enum Lookup {
+    File(PathBuf, Metadata),
+    Directory(PathBuf),
+    NotFound,
+    /// Path leads outside of the root
+    Forbidden
+}
+
+enum ByteRange {
+    /// No usable `Range` header, whole file is sent
+    Whole,
+    /// First and last byte, both included
+    Part(u64, u64),
+    Unsatisfiable
+}
+
+impl StaticDir {
+    /// Files up to this size are read into memory, so they can be compressed, larger ones are streamed
+    const PAYLOAD_LIMIT: u64 = 1024 * 1024;
+
+    pub fn new<P: AsRef<Path>> (root: P) -> Self {
+        let root = root.as_ref();
+        // Missing directory may be created later, it's resolved as is then
+        let root = fs::canonicalize(root).unwrap_or_else(|error| {
+            log_warning(&format!("Static directory {}: {error}", root.display()));
+            return root.to_path_buf();
+        });
+
+        return StaticDir { root, index: vec!["index.html".to_owned()], fallback: None };
+    }
+
+    /// Files served for requests of directories, the first existing one is taken
+    pub fn with_index (mut self, index: Vec<String>) -> Self {
+        self.index = index;
+        return self;
+    }
+
+    /// File relative to the root served instead of missing ones, e.g. `index.html` of single-page application
+    pub fn with_fallback (mut self, fallback: &str) -> Self {
+        self.fallback = Some(fallback.to_owned());
+        return self;
+    }
+
+    /// Responds with file at `path` relative to the root
+    pub fn serve (&self, ctx: &mut HttpContext, path: &str) -> ResponseRet {
+        let mut lookup = self.lookup(path);
+        if let Lookup::Directory(dir) = lookup {
+            // Relative links of index page are resolved against the directory only when its path ends with slash
+            if !ctx.req.path.ends_with('/') {
+                let query = if ctx.req.query.is_empty() { String::new() } else { format!("?{}", ctx.req.query) };
+                ctx.res.code = HttpCode::MovedPermanently;
+                ctx.res.headers.set("location".to_owned(), format!("{}/{query}", encode_path(&ctx.req.path)));
+                return ResponseRet::Return;
+            }
+
+            lookup = self.index.iter()
+                .map(|index| StaticDir::metadata(dir.join(index)))
+                .find(|lookup| matches!(lookup, Lookup::File(_, _)))
+                .unwrap_or(Lookup::NotFound);
+        }
+
+        if let (Lookup::NotFound | Lookup::Directory(_), Some(fallback)) = (&lookup, &self.fallback) {
+            lookup = self.lookup(fallback);
+        }
+
+        return match lookup {
+            Lookup::File(file, meta) => StaticDir::serve_file(ctx, &file, &meta),
+            Lookup::Forbidden => ResponseRet::Replace(Response::from_code(HttpCode::Forbidden, "Forbidden")),
+            Lookup::NotFound | Lookup::Directory(_) => ResponseRet::Replace(Response::from_code(HttpCode::NotFound, "File not found"))
+        };
+    }
+
+    fn lookup (&self, path: &str) -> Lookup {
+        let mut file = self.root.clone();
+        for segment in path.split('/') {
+            match segment {
+                "" | "." => continue,
+                ".." => return Lookup::Forbidden,
+                // Backslash separates paths on Windows, so it could climb up as well
+                _ if segment.contains(['\0', '\\']) => return Lookup::Forbidden,
+                _ => file.push(segment)
+            }
+        }
+
+        // Symbolic links are followed, but only within the root
+        let Ok(file) = fs::canonicalize(file) else {
+            return Lookup::NotFound;
+        };
+
+        if !file.starts_with(&self.root) {
+            return Lookup::Forbidden;
+        }
+
+        return StaticDir::metadata(file);
+    }
+
+    fn metadata (file: PathBuf) -> Lookup {
+        return match fs::metadata(&file) {
+            Ok(meta) if meta.is_dir() => Lookup::Directory(file),
+            Ok(meta) if meta.is_file() => Lookup::File(file, meta),
+            _ => Lookup::NotFound
+        };
+    }
+
+    fn serve_file (ctx: &mut HttpContext, file: &Path, meta: &Metadata) -> ResponseRet {
+        let size = meta.len();
+        let modified = meta.modified().ok().and_then(|time| time.duration_since(UNIX_EPOCH).ok()).map(|time| time.as_secs());
+        let etag = modified.map(|modified| format!("\"{modified:x}-{size:x}\""));
+
+        let headers = &mut ctx.res.headers;
+        headers.set("content-type".to_owned(), content_type(file).to_owned());
+        headers.set("accept-ranges".to_owned(), "bytes".to_owned());
+        if let (Some(etag), Some(modified)) = (&etag, modified) {
+            headers.set("etag".to_owned(), etag.clone());
+            headers.set("last-modified".to_owned(), fmt_http_date(UNIX_EPOCH + Duration::from_secs(modified)));
+        }
+
+        if is_not_modified(ctx, etag.as_deref(), modified) {
+            ctx.res.code = HttpCode::NotModified;
+            return ResponseRet::Return;
+        }
+
+        let range = match ctx.req.headers.get("range") {
+            Some(range) if is_range_current(ctx, etag.as_deref(), modified) => parse_range(&range, size),
+            _ => ByteRange::Whole
+        };
+
+        let (start, len) = match range {
+            ByteRange::Whole => {
+                ctx.res.code = HttpCode::OK;
+                (0, size)
+            }
+            ByteRange::Part(first, last) => {
+                ctx.res.code = HttpCode::PartialContent;
+                ctx.res.headers.set("content-range".to_owned(), format!("bytes {first}-{last}/{size}"));
+                (first, last - first + 1)
+            }
+            ByteRange::Unsatisfiable => {
+                let mut res = Response::from_code(HttpCode::RangeNotSatisfiable, "Range not satisfiable");
+                res.headers.set("content-range".to_owned(), format!("bytes */{size}"));
+                return ResponseRet::Replace(res);
+            }
+        };
+
+        match StaticDir::read_body(file, start, len) {
+            Ok(payload) => ctx.res.payload = payload,
+            Err(error) if error.kind() == ErrorKind::PermissionDenied => {
+                return ResponseRet::Replace(Response::from_code(HttpCode::Forbidden, "Forbidden"));
+            }
+            Err(_) => return ResponseRet::Replace(Response::from_code(HttpCode::NotFound, "File not found"))
+        }
+
+        return ResponseRet::Return;
+    }
+
+    /// `HEAD` gets the same body as `GET`, so compression gives it the same encoding and length, body isn't sent anyway
+    fn read_body (file: &Path, start: u64, len: u64) -> io::Result<ResponseType> {
+        let mut file = File::open(file)?;
+        file.seek(SeekFrom::Start(start))?;
+
+        if len <= StaticDir::PAYLOAD_LIMIT {
+            let mut payload = Vec::with_capacity(len as usize);
+            file.take(len).read_to_end(&mut payload)?;
+            return Ok(ResponseType::Payload(payload));
+        }
+
+        return Ok(ResponseType::Stream(ResponseStream::from_reader(file.take(len)).with_size(len)));
+    }
+}
+
+/// `If-None-Match` is compared weakly and takes precedence over `If-Modified-Since`
+fn is_not_modified (ctx: &HttpContext, etag: Option<&str>, modified: Option<u64>) -> bool {
+    if let Some(tags) = ctx.req.headers.get("if-none-match") {
+        let Some(etag) = etag else {
+            return false;
+        };
+
+        return tags.trim() == "*" || tags.split(',').any(|tag| tag.trim().trim_start_matches("W/") == etag);
+    }
+
+    let since = ctx.req.headers.get("if-modified-since").and_then(|date| parse_http_date(&date).ok());
+    return match (since, modified) {
+        (Some(since), Some(modified)) => since.duration_since(UNIX_EPOCH).is_ok_and(|since| modified <= since.as_secs()),
+        _ => false
+    };
+}
+
+/// `If-Range` allows the range only while file is the same, entity tag must match strongly and date exactly
+fn is_range_current (ctx: &HttpContext, etag: Option<&str>, modified: Option<u64>) -> bool {
+    let Some(condition) = ctx.req.headers.get("if-range") else {
+        return true;
+    };
+
+    if condition.starts_with('"') {
+        return etag == Some(condition.as_str());
+    }
+
+    let date = parse_http_date(&condition).ok().and_then(|date| date.duration_since(UNIX_EPOCH).ok());
+    return date.is_some_and(|date| modified == Some(date.as_secs()));
+}
+
+/// Single `bytes` range is supported, others are ignored and whole file is sent as the standard allows
+fn parse_range (header: &str, size: u64) -> ByteRange {
+    let Some((unit, spec)) = header.split_once('=') else {
+        return ByteRange::Whole;
+    };
+
+    if !unit.trim().eq_ignore_ascii_case("bytes") || spec.contains(',') {
+        return ByteRange::Whole;
+    }
+
+    let Some((first, last)) = spec.trim().split_once('-') else {
+        return ByteRange::Whole;
+    };
+
+    // Suffix range `-N` asks for the last N bytes
+    if first.is_empty() {
+        return match last.parse::<u64>() {
+            Ok(0) => ByteRange::Unsatisfiable,
+            Ok(_) if size == 0 => ByteRange::Unsatisfiable,
+            Ok(len) => ByteRange::Part(size - len.min(size), size - 1),
+            Err(_) => ByteRange::Whole
+        };
+    }
+
+    let Ok(first) = first.parse::<u64>() else {
+        return ByteRange::Whole;
+    };
+
+    let last = match last {
+        "" => u64::MAX,
+        last => match last.parse::<u64>() {
+            Ok(last) if last >= first => last,
+            _ => return ByteRange::Whole
+        }
+    };
+
+    if first >= size {
+        return ByteRange::Unsatisfiable;
+    }
+
+    return ByteRange::Part(first, last.min(size - 1));
+}
+
+/// Media type by file extension, unknown files are sent as arbitrary binary data
+pub fn content_type (file: &Path) -> &'static str {
+    let extension = file.extension().and_then(|extension| extension.to_str()).unwrap_or_default().to_ascii_lowercase();
+    return match extension.as_str() {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "js" | "mjs" => "text/javascript; charset=utf-8",
+        "json" | "map" => "application/json",
+        "webmanifest" => "application/manifest+json",
+        "txt" => "text/plain; charset=utf-8",
+        "csv" => "text/csv; charset=utf-8",
+        "md" => "text/markdown; charset=utf-8",
+        "xml" => "application/xml",
+        "wasm" => "application/wasm",
+        "pdf" => "application/pdf",
+        "zip" => "application/zip",
+        "gz" => "application/gzip",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "avif" => "image/avif",
+        "ico" => "image/x-icon",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "ttf" => "font/ttf",
+        "otf" => "font/otf",
+        "mp3" => "audio/mpeg",
+        "ogg" => "audio/ogg",
+        "wav" => "audio/wav",
+        "mp4" => "video/mp4",
+        "webm" => "video/webm",
+        _ => "application/octet-stream"
+    };
+}
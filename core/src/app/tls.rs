@@ -0,0 +1,248 @@
+use std::fs;
+use std::io::{self, ErrorKind, Read, Write};
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+use rustls::crypto::ring::default_provider;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::pki_types::pem::PemObject;
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+use rustls::{ServerConfig, ServerConnection};
+use crate::app::config::{CertificateConfig, TlsConfig, CONFIG};
+use crate::utils::log::{log_error_lines, log_info};
+use crate::utils::socket::{Socket, Stream};
+
+/// ALPN identifiers in order of server preference
+pub const ALPN_H2: &[u8] = b"h2";
+pub const ALPN_HTTP1: &[u8] = b"http/1.1";
+
+#[derive(Debug)]
+struct CertificateEntry {
+    cert_path: String,
+    key_path: String,
+    names: Vec<String>,
+    /// Latest modification time of both files when they were loaded
+    modified: Option<SystemTime>,
+    key: Arc<CertifiedKey>
+}
+
+impl CertificateEntry {
+    fn load (config: &CertificateConfig) -> Result<Self, String> {
+        let modified = CertificateEntry::modified(&config.cert, &config.key);
+        return Ok(CertificateEntry {
+            cert_path: config.cert.clone(),
+            key_path: config.key.clone(),
+            names: config.names.clone(),
+            modified,
+            key: Arc::new(CertificateEntry::read_key(&config.cert, &config.key)?)
+        });
+    }
+
+    fn read_key (cert_path: &str, key_path: &str) -> Result<CertifiedKey, String> {
+        let certs = CertificateDer::pem_file_iter(cert_path)
+            .and_then(|iter| iter.collect::<Result<Vec<_>, _>>())
+            .map_err(|err| format!("{cert_path}: {err}"))?;
+
+        if certs.is_empty() {
+            return Err(format!("{cert_path}: no certificates found"));
+        }
+
+        let key = PrivateKeyDer::from_pem_file(key_path).map_err(|err| format!("{key_path}: {err}"))?;
+        return CertifiedKey::from_der(certs, key, &default_provider()).map_err(|err| format!("{cert_path}: {err}"));
+    }
+
+    fn modified (cert_path: &str, key_path: &str) -> Option<SystemTime> {
+        let cert = fs::metadata(cert_path).and_then(|meta| meta.modified()).ok()?;
+        let key = fs::metadata(key_path).and_then(|meta| meta.modified()).ok()?;
+        return Some(cert.max(key));
+    }
+
+    /// Exact match or wildcard `*.example.com` covering exactly one label
+    fn matches (&self, server_name: &str) -> bool {
+        return self.names.iter().any(|name| {
+            if let Some(suffix) = name.strip_prefix("*.") {
+                return server_name.split_once('.').is_some_and(|(label, rest)| !label.is_empty() && rest == suffix);
+            }
+
+            return name == server_name;
+        });
+    }
+}
+
+/// Picks certificate by SNI server name, files can be swapped on disk and reloaded without restart
+#[derive(Debug)]
+pub struct CertResolver {
+    entries: RwLock<Vec<CertificateEntry>>
+}
+
+impl CertResolver {
+    pub fn load (certificates: &[CertificateConfig]) -> Result<Self, String> {
+        if certificates.is_empty() {
+            return Err("no certificates configured".to_owned());
+        }
+
+        let entries = certificates.iter().map(CertificateEntry::load).collect::<Result<Vec<_>, _>>()?;
+        return Ok(CertResolver { entries: RwLock::new(entries) });
+    }
+
+    /// Reloads certificates whose files have changed, the ones failing to load keep the previous version
+    pub fn reload (&self) {
+        let mut changed = Vec::new();
+        if let Ok(entries) = self.entries.read() {
+            for (index, entry) in entries.iter().enumerate() {
+                let modified = CertificateEntry::modified(&entry.cert_path, &entry.key_path);
+                if modified.is_some() && modified != entry.modified {
+                    changed.push((index, modified, CertificateEntry::read_key(&entry.cert_path, &entry.key_path)));
+                }
+            }
+        }
+
+        if changed.is_empty() {
+            return;
+        }
+
+        let Ok(mut entries) = self.entries.write() else {
+            return;
+        };
+
+        for (index, modified, key) in changed {
+            let entry = &mut entries[index];
+            // Failed attempt isn't repeated until files change again
+            entry.modified = modified;
+            match key {
+                Ok(key) => {
+                    entry.key = Arc::new(key);
+                    log_info(&format!("Reloaded TLS certificate {}", entry.cert_path));
+                }
+                Err(err) => log_error_lines("TLS certificate reload error", err)
+            }
+        }
+    }
+
+    /// Certificate for the server name, falls back to the first one without names or the first at all
+    pub fn find (&self, server_name: Option<&str>) -> Option<Arc<CertifiedKey>> {
+        let entries = self.entries.read().ok()?;
+        let server_name = server_name.map(str::to_ascii_lowercase);
+
+        let entry = server_name.and_then(|name| entries.iter().find(|entry| entry.matches(&name)))
+            .or_else(|| entries.iter().find(|entry| entry.names.is_empty()))
+            .or_else(|| entries.first())?;
+
+        return Some(entry.key.clone());
+    }
+}
+
+impl ResolvesServerCert for CertResolver {
+    fn resolve (&self, client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+        return self.find(client_hello.server_name());
+    }
+}
+
+/// Stream of handshake in progress, client sending or reading it byte by byte can't stretch it past the deadline
+struct HandshakeStream {
+    inner: Stream,
+    deadline: Option<Instant>
+}
+
+impl HandshakeStream {
+    /// Time left until the deadline, every read and write waits no longer than that
+    fn time_left (&self) -> io::Result<Option<Duration>> {
+        let Some(deadline) = self.deadline else {
+            return Ok(None);
+        };
+
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return Err(ErrorKind::TimedOut.into());
+        }
+
+        return Ok(Some(left));
+    }
+}
+
+impl Read for HandshakeStream {
+    fn read (&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.set_read_timeout(self.time_left()?)?;
+        return self.inner.read(buf);
+    }
+}
+
+impl Write for HandshakeStream {
+    fn write (&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.set_write_timeout(self.time_left()?)?;
+        return self.inner.write(buf);
+    }
+
+    #[inline]
+    fn flush (&mut self) -> io::Result<()> {
+        return self.inner.flush();
+    }
+}
+
+/// Server side of TLS listener, performs handshakes of accepted connections
+pub struct TlsAcceptor {
+    config: Arc<ServerConfig>,
+    resolver: Arc<CertResolver>
+}
+
+impl TlsAcceptor {
+    pub fn new (config: &TlsConfig) -> Result<Self, String> {
+        let resolver = Arc::new(CertResolver::load(&config.certificates)?);
+
+        let mut server_config = ServerConfig::builder_with_provider(Arc::new(default_provider()))
+            .with_safe_default_protocol_versions()
+            .map_err(|err| err.to_string())?
+            .with_no_client_auth()
+            .with_cert_resolver(resolver.clone());
+
+        if CONFIG.http2.enabled {
+            server_config.alpn_protocols.push(ALPN_H2.to_vec());
+        }
+        server_config.alpn_protocols.push(ALPN_HTTP1.to_vec());
+
+        return Ok(TlsAcceptor { config: Arc::new(server_config), resolver });
+    }
+
+    /// Completes the handshake within the header timeout
+    #[inline]
+    pub fn accept<S: Into<Stream>> (&self, stream: S) -> io::Result<Socket> {
+        return self.accept_within(stream, CONFIG.timeouts.header);
+    }
+
+    /// Completes the handshake, whole of which must take no longer than `timeout`, zero duration removes the limit
+    pub fn accept_within<S: Into<Stream>> (&self, stream: S, timeout: Duration) -> io::Result<Socket> {
+        let mut stream = HandshakeStream {
+            inner: stream.into(),
+            deadline: (!timeout.is_zero()).then(|| Instant::now() + timeout)
+        };
+
+        let mut connection = ServerConnection::new(self.config.clone()).map_err(io::Error::other)?;
+        while connection.is_handshaking() {
+            connection.complete_io(&mut stream)?;
+        }
+
+        let stream = stream.inner;
+        stream.set_read_timeout(None)?;
+        stream.set_write_timeout(None)?;
+        return Ok(Socket::with_tls(stream, connection));
+    }
+
+    #[inline]
+    pub fn reload (&self) {
+        self.resolver.reload();
+    }
+
+    /// Starts background thread checking certificate files for changes, zero interval does nothing
+    pub fn watch (self: &Arc<Self>, interval: Duration) {
+        if interval.is_zero() {
+            return;
+        }
+
+        let acceptor = self.clone();
+        thread::spawn(move || loop {
+            thread::sleep(interval);
+            acceptor.reload();
+        });
+    }
+}
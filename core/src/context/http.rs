@@ -1,31 +1,60 @@
-use std::{collections::HashMap, net::IpAddr};
+use std::{cell::OnceCell, collections::HashMap, net::IpAddr, str::FromStr};
 use json::{object, JsonValue};
-use crate::http::{codes::HttpCode, entity::{HttpConnection, HttpHeaders, Request, Response, ResponseRet, ResponseType}};
+use crate::app::config::CONFIG;
+use crate::http::{codes::HttpCode, entity::{HttpConnection, HttpHeaders, Request, Response, ResponseRet, ResponseStream, ResponseType}, forwarded::ClientInfo, multipart::{Multipart, UploadedFile, MULTIPART_FORM_DATA}, query::Query};
+use crate::utils::socket::Transport;
 use crate::utils::validator::*;
 
+const FORM_URLENCODED: &str = "application/x-www-form-urlencoded";
+
 
 #[derive(Debug)]
 pub struct HttpContext {
 	pub req: Request,
 	pub res: Response,
 	pub params: HashMap<String, String>,
-	pub address: IpAddr
+	/// Decoded parameters of the query string
+	pub query: Query,
+	/// Client address, behind trusted proxies it's taken from forwarding headers
+	pub address: IpAddr,
+	scheme: String,
+	host: Option<String>,
+	/// Fields of form body, parsed when asked for
+	form: OnceCell<Option<Query>>
 }
 
 impl HttpContext {
 	pub fn from<Connection: HttpConnection> (connection: &Connection, req: Request, params: HashMap<String, String>) -> Self {
+		let client = ClientInfo::resolve(connection.get_address(), connection.get_transport().is_tls(), &req.headers, &CONFIG.trusted_proxies);
 		HttpContext {
+			query: Query::parse(&req.query),
 			req,
 			res: Response {
 				code: HttpCode::NotSent,
 				headers: HttpHeaders::empty(),
-				payload: ResponseType::NoContent
+				payload: ResponseType::NoContent,
+				compress: true
 			},
 			params,
-			address: connection.get_address()
+			address: client.address,
+			scheme: client.scheme,
+			host: client.host,
+			form: OnceCell::new()
 		}
 	}
 
+	/// `http` or `https` as requested by client, it may differ from the connection one behind trusted proxy
+	#[inline]
+	pub fn get_scheme (&self) -> &str {
+		return &self.scheme;
+	}
+
+	/// Host requested by client without changes, port included if it was sent
+	#[inline]
+	pub fn get_host (&self) -> Option<&str> {
+		return self.host.as_deref();
+	}
+
 	#[inline]
 	pub fn get_header (&self, name: &str) -> Option<String> {
 		return self.req.headers.get(name);
@@ -41,6 +70,60 @@ impl HttpContext {
 		self.res.headers.set(name.to_string(), value);
 	}
 
+	/// Compares media type of the body ignoring its parameters, e.g. `charset`
+	pub fn has_content_type (&self, media_type: &str) -> bool {
+		return self.req.headers.get("content-type")
+			.is_some_and(|content_type| content_type.split(';').next().unwrap_or_default().trim().eq_ignore_ascii_case(media_type));
+	}
+
+	/// Decoded fields of `application/x-www-form-urlencoded` body or text fields of `multipart/form-data` one,
+	/// `None` for other content types
+	pub fn get_form (&self) -> Option<&Query> {
+		if let Some(multipart) = &self.req.multipart {
+			return Some(multipart.fields());
+		}
+
+		return self.form.get_or_init(|| {
+			return self.has_content_type(FORM_URLENCODED).then(|| Query::parse(&String::from_utf8_lossy(&self.req.body)));
+		}).as_ref();
+	}
+
+	/// Fields and files of `multipart/form-data` body, `None` for other content types
+	#[inline]
+	pub fn get_multipart (&self) -> Option<&Multipart> {
+		return self.req.multipart.as_deref();
+	}
+
+	/// First uploaded file of the field
+	pub fn get_file (&self, name: &str) -> Option<&UploadedFile> {
+		return self.get_multipart()?.file(name);
+	}
+
+	/// Fills the struct from form fields, numbers and bools are parsed from their text
+	pub fn validate_form<T: Validate + ValidateJson + Default> (&mut self) -> ResponseRet<T> {
+		let result = match self.get_form() {
+			Some(form) => validate_query::<T>(form),
+			None => return self.json_status(
+				object! { "type": "ValidationError", "message": format!("expected {FORM_URLENCODED} or {MULTIPART_FORM_DATA} body") },
+				HttpCode::UnsupportedMediaType
+			)
+		};
+
+		match result {
+			Ok(payload) => ResponseRet::Result(payload),
+			Err(error) => self.json_status(error.into_json(), HttpCode::BadRequest)
+		}
+	}
+
+	/// Validates form or JSON body depending on its content type, so the same struct serves both
+	pub fn validate_body<T: Validate + ValidateJson + Default> (&mut self) -> ResponseRet<T> {
+		if self.has_content_type(FORM_URLENCODED) || self.has_content_type(MULTIPART_FORM_DATA) {
+			return self.validate_form();
+		} else {
+			return self.validate_json();
+		}
+	}
+
 	pub fn validate_json<T: Validate + ValidateJson + Default> (&mut self) -> ResponseRet<T> {
 		let body_str = match str::from_utf8(self.req.body.as_slice()) {
 			Ok(value) => value,
@@ -59,6 +142,22 @@ impl HttpContext {
 		}
 	}
 
+	/// Fills the struct from query parameters, numbers and bools are parsed from their text
+	pub fn validate_query<T: Validate + ValidateJson + Default> (&mut self) -> ResponseRet<T> {
+		match validate_query::<T>(&self.query) {
+			Ok(payload) => ResponseRet::Result(payload),
+			Err(error) => self.json_status(error.into_json(), HttpCode::BadRequest)
+		}
+	}
+
+	/// Query parameter parsed as `T`, missing one is `None` and malformed one is answered with 400
+	pub fn get_query<T: FromStr> (&mut self, name: &str) -> ResponseRet<Option<T>> {
+		match self.query.get_parsed::<T>(name) {
+			Ok(value) => ResponseRet::Result(value),
+			Err(error) => self.json_status(error.into_json(), HttpCode::BadRequest)
+		}
+	}
+
 	#[inline]
 	pub fn json (&mut self, data: JsonValue) -> ResponseRet {
 		return self.json_status(data, HttpCode::OK);
@@ -87,6 +186,15 @@ impl HttpContext {
 		return ResponseRet::Return;
 	}
 
+	/// Responds with body produced by `stream`, useful for large or generated on the fly content
+	pub fn stream (&mut self, content_type: &str, stream: ResponseStream) -> ResponseRet {
+		self.res.code = HttpCode::OK;
+		self.res.headers.set_default("content-type".to_owned(), content_type.to_owned());
+		self.res.payload = ResponseType::Stream(stream);
+
+		return ResponseRet::Return;
+	}
+
 	pub fn redirect (&mut self, target: &str) -> ResponseRet {
 		self.res.code = HttpCode::TemporaryRedirect;
 		self.res.headers.set("location".to_string(), target.to_string());
@@ -1,5 +1,7 @@
+#![allow(clippy::missing_safety_doc)]
+
 use bindings::c::Slice;
-use crate::{c::c_unwrap, context::http::HttpContext, http::entity::Response};
+use crate::{c::{c_init_str, c_str, c_string, c_unwrap}, context::http::HttpContext, http::{entity::Response, multipart::UploadedFile, query::Query}};
 
 
 #[no_mangle]
@@ -7,6 +9,97 @@ pub extern "C" fn http_context_get_body_ref (ctx: &HttpContext) -> Slice<u8> {
 	return Slice::for_vec(&ctx.req.body);
 }
 
+/// Client IP address as string, should be freed with `str_drop`
+#[no_mangle]
+pub extern "C" fn http_context_get_address (ctx: &HttpContext) -> c_str {
+	return c_init_str(ctx.address.to_string());
+}
+
+/// `http` or `https`, should be freed with `str_drop`
+#[no_mangle]
+pub extern "C" fn http_context_get_scheme (ctx: &HttpContext) -> c_str {
+	return c_init_str(ctx.get_scheme());
+}
+
+/// Requested host or null if it's unknown, should be freed with `str_drop`.
+/// Host containing NUL is cut at it.
+#[no_mangle]
+pub extern "C" fn http_context_get_host (ctx: &HttpContext) -> c_str {
+	return match ctx.get_host() {
+		Some(host) => c_init_str(host.split('\0').next().unwrap_or_default()),
+		None => std::ptr::null()
+	};
+}
+
+/// First value of query parameter or null if there is none, should be freed with `str_drop`.
+/// Value containing NUL is cut at it.
+#[no_mangle]
+pub unsafe extern "C" fn http_context_get_query (ctx: &HttpContext, name: c_str) -> c_str {
+	return http_context_get_query_at(ctx, name, 0);
+}
+
+/// Number of values of query parameter, e.g. 2 for `?tag=a&tag=b`
+#[no_mangle]
+pub unsafe extern "C" fn http_context_get_query_count (ctx: &HttpContext, name: c_str) -> usize {
+	return ctx.query.get_all(&c_string(name)).len();
+}
+
+/// Value of repeated query parameter at `index` or null, should be freed with `str_drop`
+#[no_mangle]
+pub unsafe extern "C" fn http_context_get_query_at (ctx: &HttpContext, name: c_str, index: usize) -> c_str {
+	return value_at(Some(&ctx.query), name, index);
+}
+
+/// First value of field of `application/x-www-form-urlencoded` or `multipart/form-data` body or null if there is none,
+/// should be freed with `str_drop`. Value containing NUL is cut at it.
+#[no_mangle]
+pub unsafe extern "C" fn http_context_get_form (ctx: &HttpContext, name: c_str) -> c_str {
+	return http_context_get_form_at(ctx, name, 0);
+}
+
+/// Number of values of form field, zero when body isn't a form
+#[no_mangle]
+pub unsafe extern "C" fn http_context_get_form_count (ctx: &HttpContext, name: c_str) -> usize {
+	return ctx.get_form().map_or(0, |form| form.get_all(&c_string(name)).len());
+}
+
+/// Value of repeated form field at `index` or null, should be freed with `str_drop`
+#[no_mangle]
+pub unsafe extern "C" fn http_context_get_form_at (ctx: &HttpContext, name: c_str, index: usize) -> c_str {
+	return value_at(ctx.get_form(), name, index);
+}
+
+/// First uploaded file of the field or null, it's valid until the handler returns
+#[no_mangle]
+pub unsafe extern "C" fn http_context_get_file (ctx: &HttpContext, name: c_str) -> *const UploadedFile {
+	return match ctx.get_file(&c_string(name)) {
+		Some(file) => file,
+		None => std::ptr::null()
+	};
+}
+
+/// Number of files uploaded with `multipart/form-data` body
+#[no_mangle]
+pub extern "C" fn http_context_get_file_count (ctx: &HttpContext) -> usize {
+	return ctx.get_multipart().map_or(0, |multipart| multipart.files().len());
+}
+
+/// Uploaded file at `index` in order of the body or null, it's valid until the handler returns
+#[no_mangle]
+pub extern "C" fn http_context_get_file_at (ctx: &HttpContext, index: usize) -> *const UploadedFile {
+	return match ctx.get_multipart().and_then(|multipart| multipart.files().get(index)) {
+		Some(file) => file,
+		None => std::ptr::null()
+	};
+}
+
+unsafe fn value_at (params: Option<&Query>, name: c_str, index: usize) -> c_str {
+	return match params.and_then(|params| params.get_all(&c_string(name)).get(index).copied()) {
+		Some(value) => c_init_str(value.split('\0').next().unwrap_or_default()),
+		None => std::ptr::null()
+	};
+}
+
 #[no_mangle]
 pub extern "C" fn http_context_get_response (ctx: &mut HttpContext) -> *mut Response {
 	return &mut ctx.res;
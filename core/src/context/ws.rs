@@ -1,16 +1,16 @@
-use std::{collections::HashMap, net::TcpStream};
+use std::collections::HashMap;
 use bufstream::BufStream;
 use tungstenite::{WebSocket, protocol::Role};
-use crate::http::entity::{Request, HttpConnection};
+use crate::{http::entity::{HttpConnection, Request}, utils::socket::Socket};
 use super::http::HttpContext;
 
 pub struct SocketContext {
 	pub http: HttpContext,
-	pub stream: WebSocket<BufStream<TcpStream>>
+	pub stream: WebSocket<BufStream<Socket>>
 }
 
 impl SocketContext {
-	pub fn from<Connection: HttpConnection> (connection: Connection, req: Request) -> Self {
+	pub fn from<Connection: HttpConnection<Transport = Socket>> (connection: Connection, req: Request) -> Self {
 		let http = HttpContext::from(&connection, req, HashMap::new());
 		let stream = connection.into_stream();
 		let ws_stream = WebSocket::from_raw_socket(stream, Role::Server, None);
@@ -9,6 +9,7 @@ pub struct DatabaseConnections {
 }
 
 impl DatabaseConnections {
+	#[allow(clippy::new_without_default)]
 	pub fn new () -> Self {
 		DatabaseConnections { map: HashMap::new() }
 	}
@@ -17,11 +18,24 @@ impl DatabaseConnections {
 		self.map.insert(key, value);
 	}
 
+	#[allow(clippy::borrowed_box)]
 	pub fn find (&self, key: &str) -> Option<&Box<dyn DatabaseConnection>> {
 		return self.map.get(key);
 	}
+
+	pub fn close_all (&self) {
+		for connection in self.map.values() {
+			connection.close();
+		}
+	}
 }
 
 pub fn init_database_connections_store (map: DatabaseConnections) {
 	DB_CONNECTIONS.get_or_init(|| map);
 }
+
+pub fn close_database_connections () {
+	if let Some(connections) = DB_CONNECTIONS.get() {
+		connections.close_all();
+	}
+}
@@ -26,7 +26,7 @@ pub unsafe extern "C" fn db_connection_prepare_model (
 	let name = c_string(name);
 
 	// todo: track what module registered the model
-	let reg_msg = format!("registered model {} {{ init = {:p}, deinit = {:p} }}", &name, init, deinit);
+	let reg_msg = format!("registered model {} {{ init = {:p}, deinit = {:p} }}", name, init, deinit);
 	log_info(&reg_msg);
 
 	let model = ModelMeta { origin_module: None, name, init, deinit, fields: Vec::new() };
@@ -37,6 +37,7 @@ pub unsafe extern "C" fn db_connection_prepare_model (
 }
 
 #[no_mangle]
+#[allow(clippy::borrowed_box)]
 pub extern "C" fn db_connection_new_query (
 	conn: &Box<dyn DatabaseConnection>,
 	model: &Box<dyn ModelMetaImpl>
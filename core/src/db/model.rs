@@ -46,6 +46,7 @@ impl ModelMetaImpl for ModelMeta {
 }
 
 #[no_mangle]
+#[allow(clippy::missing_safety_doc)]
 pub unsafe extern "C" fn model_meta_add_field (
 	model: &mut Box<dyn ModelMetaImpl>,
 	name: c_str, meta: ModelFieldMeta,
@@ -20,6 +20,7 @@ pub extern "C" fn query_builder_debug (query: &mut Box<dyn QueryBuilder>) {
 }
 
 #[no_mangle]
+#[allow(clippy::borrowed_box)]
 pub unsafe extern "C" fn db_connection_exec_first (
 	conn: &mut Box<dyn DatabaseConnection>,
 	model: &Box<dyn ModelMetaImpl>,
@@ -39,6 +40,7 @@ pub unsafe extern "C" fn db_connection_exec_first (
 }
 
 #[no_mangle]
+#[allow(clippy::borrowed_box)]
 pub unsafe extern "C" fn db_connection_exec_all (
 	conn: &mut Box<dyn DatabaseConnection>,
 	model: &Box<dyn ModelMetaImpl>,
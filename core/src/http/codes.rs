@@ -69,6 +69,15 @@ pub enum HttpCode {
 }
 
 impl HttpCode {
+    /// Informational, `204 No Content` and `304 Not Modified` responses can't have a body
+    pub fn allows_body (&self) -> bool {
+        return !matches!(
+            self,
+            HttpCode::Continue | HttpCode::SwitchingProtocols | HttpCode::Processing | HttpCode::EarlyHints |
+            HttpCode::NoContent | HttpCode::NotModified
+        );
+    }
+
     pub fn get_description (&self) -> (&str, &str) {
         match self {
             HttpCode::NotSent => ("0", "Invalid"),
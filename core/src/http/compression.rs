@@ -0,0 +1,199 @@
+use std::io::{self, Read, Write};
+use brotli::CompressorWriter;
+use flate2::Compression;
+use flate2::read::{DeflateDecoder, MultiGzDecoder, ZlibDecoder};
+use flate2::write::{GzEncoder, ZlibEncoder};
+use crate::app::config::CONFIG;
+use crate::http::codes::HttpCode;
+use crate::http::entity::{Request, Response, ResponseType};
+
+/// Request body codings undone before handlers get the body, sent back in `Accept-Encoding` of 415 response
+pub const REQUEST_ENCODINGS: &str = "gzip, deflate";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentEncoding {
+    Brotli,
+    Gzip,
+    /// Zlib stream, which is what HTTP calls `deflate`
+    Deflate
+}
+
+impl ContentEncoding {
+    /// Server preference for encodings client weights equally
+    const PREFERRED: [ContentEncoding; 3] = [ContentEncoding::Brotli, ContentEncoding::Gzip, ContentEncoding::Deflate];
+    /// Brotli maximum is too slow for compressing on every response
+    const BROTLI_QUALITY: u32 = 5;
+    const BROTLI_WINDOW: u32 = 22;
+
+    pub fn token (self) -> &'static str {
+        match self {
+            ContentEncoding::Brotli => "br",
+            ContentEncoding::Gzip => "gzip",
+            ContentEncoding::Deflate => "deflate"
+        }
+    }
+
+    /// Picks encoding with the highest weight in `Accept-Encoding`, `*` covers encodings not listed
+    pub fn negotiate (accept_encoding: &str) -> Option<Self> {
+        let mut weights = Vec::new();
+        for item in accept_encoding.split(',') {
+            let mut params = item.split(';');
+            let token = params.next().unwrap_or_default().trim().to_ascii_lowercase();
+            let weight = params
+                .filter_map(|param| param.trim().strip_prefix("q=").or_else(|| param.trim().strip_prefix("Q=")))
+                .find_map(|weight| weight.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+
+            if !token.is_empty() {
+                weights.push((token, weight));
+            }
+        }
+
+        let weight_of = |token: &str| weights.iter().find(|(name, _)| name == token).map(|(_, weight)| *weight);
+        let mut best = None;
+        let mut best_weight = 0.0;
+        for encoding in ContentEncoding::PREFERRED {
+            let weight = match encoding {
+                ContentEncoding::Gzip => weight_of("gzip").or_else(|| weight_of("x-gzip")),
+                _ => weight_of(encoding.token())
+            };
+
+            let weight = weight.or_else(|| weight_of("*")).unwrap_or(0.0);
+            if weight > best_weight {
+                best = Some(encoding);
+                best_weight = weight;
+            }
+        }
+
+        return best;
+    }
+
+    pub fn encode (self, data: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            ContentEncoding::Brotli => {
+                let mut encoder = CompressorWriter::new(Vec::new(), 4096, ContentEncoding::BROTLI_QUALITY, ContentEncoding::BROTLI_WINDOW);
+                encoder.write_all(data)?;
+                encoder.flush()?;
+                return Ok(encoder.into_inner());
+            }
+            ContentEncoding::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(data)?;
+                return encoder.finish();
+            }
+            ContentEncoding::Deflate => {
+                let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(data)?;
+                return encoder.finish();
+            }
+        }
+    }
+}
+
+/// Compresses payload of the response if client accepts it and config allows its type and size.
+/// Responses already having `Content-Encoding` or opted out by `compress` flag are left as is.
+pub fn compress_response (accept_encoding: Option<&str>, res: &mut Response) {
+    let config = &CONFIG.compression;
+    if !config.enabled || !res.compress || matches!(res.code, HttpCode::PartialContent) || res.headers.get("content-encoding").is_some() {
+        return;
+    }
+
+    let ResponseType::Payload(payload) = &res.payload else {
+        return;
+    };
+
+    if payload.len() < config.min_size || !is_compressible(res.headers.get("content-type").as_deref()) {
+        return;
+    }
+
+    // Representation depends on `Accept-Encoding` even when this client gets it uncompressed
+    match res.headers.get("vary") {
+        None => res.headers.set("vary".to_owned(), "accept-encoding".to_owned()),
+        Some(vary) if vary.trim() == "*" || res.headers.has_token("vary", "accept-encoding") => {}
+        Some(vary) => res.headers.set("vary".to_owned(), format!("{vary}, accept-encoding"))
+    }
+
+    let Some(encoding) = accept_encoding.and_then(ContentEncoding::negotiate) else {
+        return;
+    };
+
+    let Ok(compressed) = encoding.encode(payload) else {
+        return;
+    };
+
+    if compressed.len() >= payload.len() {
+        return;
+    }
+
+    res.payload = ResponseType::Payload(compressed);
+    res.headers.set("content-encoding".to_owned(), encoding.token().to_owned());
+    // Compressed body isn't byte-for-byte the same representation anymore
+    if let Some(etag) = res.headers.get("etag").filter(|etag| !etag.starts_with("W/")) {
+        res.headers.set("etag".to_owned(), format!("W/{etag}"));
+    }
+}
+
+/// Replaces compressed body of the request with the decoded one, codings are undone in reverse order.
+/// Unsupported coding gives 415, corrupted data 400 and body growing over `limits.decompressed_body` 413.
+pub fn decompress_request (req: &mut Request) -> Result<(), HttpCode> {
+    let Some(encoding) = req.headers.get("content-encoding") else {
+        return Ok(());
+    };
+
+    let mut codings = Vec::new();
+    for coding in encoding.split(',').map(str::trim).filter(|coding| !coding.is_empty()) {
+        match coding.to_ascii_lowercase().as_str() {
+            "identity" => {}
+            "gzip" | "x-gzip" => codings.push(ContentEncoding::Gzip),
+            "deflate" => codings.push(ContentEncoding::Deflate),
+            _ => return Err(HttpCode::UnsupportedMediaType)
+        }
+    }
+
+    for coding in codings.into_iter().rev() {
+        req.body = decode(coding, &req.body, CONFIG.limits.decompressed_body)?;
+    }
+
+    req.headers.remove("content-encoding".to_owned());
+    req.headers.set("content-length".to_owned(), req.body.len().to_string());
+    return Ok(());
+}
+
+fn decode (encoding: ContentEncoding, data: &[u8], limit: usize) -> Result<Vec<u8>, HttpCode> {
+    let decoder: Box<dyn Read + '_> = match encoding {
+        ContentEncoding::Gzip => Box::new(MultiGzDecoder::new(data)),
+        ContentEncoding::Deflate if has_zlib_header(data) => Box::new(ZlibDecoder::new(data)),
+        // Some clients send raw deflate stream instead of zlib one
+        ContentEncoding::Deflate => Box::new(DeflateDecoder::new(data)),
+        ContentEncoding::Brotli => return Err(HttpCode::UnsupportedMediaType)
+    };
+
+    // One byte over the limit is enough to tell that the body is too large
+    let mut decoded = Vec::new();
+    if decoder.take(limit as u64 + 1).read_to_end(&mut decoded).is_err() {
+        return Err(HttpCode::BadRequest);
+    }
+
+    if decoded.len() > limit {
+        return Err(HttpCode::RequestEntityTooLarge);
+    }
+
+    return Ok(decoded);
+}
+
+/// Zlib stream starts with deflate method and a pair of bytes checksummed to a multiple of 31
+fn has_zlib_header (data: &[u8]) -> bool {
+    return data.len() >= 2 && data[0] & 0x0f == 8 && u16::from_be_bytes([data[0], data[1]]).is_multiple_of(31);
+}
+
+fn is_compressible (content_type: Option<&str>) -> bool {
+    let Some(content_type) = content_type else {
+        return false;
+    };
+
+    let media_type = content_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+    return CONFIG.compression.types.iter().any(|allowed| match allowed.strip_suffix('*') {
+        Some(prefix) => media_type.starts_with(prefix),
+        None => *allowed == media_type
+    });
+}
@@ -1,5 +1,5 @@
 use crate::app::config::CONFIG;
-use super::entity::{Request, Response};
+use super::entity::{MethodSet, Request, Response};
 
 
 pub struct Cors {
@@ -28,8 +28,9 @@ impl Cors {
 		self.apply_origin_check(res);
 	}
 
-	pub fn apply_preflight (self, res: &mut Response) {
-		res.headers.set("Access-Control-Allow-Methods".to_string(), CONFIG.cors.methods.clone());
+	/// `methods` are the ones of routes matching the requested path, `OPTIONS` included
+	pub fn apply_preflight (self, res: &mut Response, methods: MethodSet) {
+		res.headers.set("Access-Control-Allow-Methods".to_string(), methods.to_header());
 		res.headers.set("Access-Control-Allow-Headers".to_string(), CONFIG.cors.headers.clone());
 		res.headers.set("Access-Control-Max-Age".to_string(), CONFIG.cors.ttl.clone());
 		self.apply_origin_check(res);
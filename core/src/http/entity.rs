@@ -1,36 +1,156 @@
 use core::slice;
-use std::io::Error;
-use std::net::{SocketAddr, IpAddr, TcpStream};
-use std::ops::{ControlFlow, FromResidual, Try};
+use std::fmt;
+use std::io::{self, Error, Read};
+use std::net::{SocketAddr, IpAddr};
+use std::ops::{BitOr, ControlFlow, FromResidual, Residual, Try};
 use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
 use bufstream::BufStream;
 use crate::http::codes::HttpCode;
+use crate::http::multipart::{Multipart, MultipartError, PartSink};
+use crate::utils::socket::{Socket, Transport};
 
-#[derive(Debug)]
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum HttpMethod {
     GET,
+    HEAD,
     POST,
-    OPTIONS
+    PUT,
+    DELETE,
+    CONNECT,
+    OPTIONS,
+    TRACE,
+    PATCH
 }
 
 impl HttpMethod {
+    pub const ALL: [HttpMethod; 9] = [
+        HttpMethod::GET, HttpMethod::HEAD, HttpMethod::POST, HttpMethod::PUT, HttpMethod::DELETE,
+        HttpMethod::CONNECT, HttpMethod::OPTIONS, HttpMethod::TRACE, HttpMethod::PATCH
+    ];
+
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str (method: &str) -> Option<HttpMethod> {
         match method {
             "GET" => Some(HttpMethod::GET),
+            "HEAD" => Some(HttpMethod::HEAD),
             "POST" => Some(HttpMethod::POST),
+            "PUT" => Some(HttpMethod::PUT),
+            "DELETE" => Some(HttpMethod::DELETE),
+            "CONNECT" => Some(HttpMethod::CONNECT),
             "OPTIONS" => Some(HttpMethod::OPTIONS),
+            "TRACE" => Some(HttpMethod::TRACE),
+            "PATCH" => Some(HttpMethod::PATCH),
             _ => None
         }
     }
+
+    pub fn as_str (&self) -> &'static str {
+        match self {
+            HttpMethod::GET => "GET",
+            HttpMethod::HEAD => "HEAD",
+            HttpMethod::POST => "POST",
+            HttpMethod::PUT => "PUT",
+            HttpMethod::DELETE => "DELETE",
+            HttpMethod::CONNECT => "CONNECT",
+            HttpMethod::OPTIONS => "OPTIONS",
+            HttpMethod::TRACE => "TRACE",
+            HttpMethod::PATCH => "PATCH"
+        }
+    }
+
+    /// Methods which are meaningless without a body, so the body framing is required for them
+    pub fn expects_body (&self) -> bool {
+        return matches!(self, HttpMethod::POST | HttpMethod::PUT | HttpMethod::PATCH);
+    }
 }
 
-#[derive(Debug)]
+impl BitOr for HttpMethod {
+    type Output = MethodSet;
+
+    fn bitor (self, rhs: Self) -> MethodSet {
+        return MethodSet::from(self) | rhs;
+    }
+}
+
+/// Set of HTTP methods, `1 << method` bit marks presence of the method
+/// (`GET` is 0, then `HEAD`, `POST`, `PUT`, `DELETE`, `CONNECT`, `OPTIONS`, `TRACE` and `PATCH`)
+#[repr(transparent)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MethodSet(u16);
+
+impl MethodSet {
+    pub const EMPTY: MethodSet = MethodSet(0);
+    pub const ALL: MethodSet = MethodSet((1 << HttpMethod::ALL.len()) - 1);
+
+    pub const fn from_bits (bits: u16) -> Self {
+        MethodSet(bits & MethodSet::ALL.0)
+    }
+
+    #[inline]
+    pub const fn bits (&self) -> u16 {
+        return self.0;
+    }
+
+    #[inline]
+    pub fn contains (&self, method: HttpMethod) -> bool {
+        return self.0 & (1 << method as u8) != 0;
+    }
+
+    #[inline]
+    pub fn insert (&mut self, method: HttpMethod) {
+        self.0 |= 1 << method as u8;
+    }
+
+    #[inline]
+    pub fn is_empty (&self) -> bool {
+        return self.0 == 0;
+    }
+
+    pub fn iter (&self) -> impl Iterator<Item = HttpMethod> + '_ {
+        return HttpMethod::ALL.into_iter().filter(|method| self.contains(*method));
+    }
+
+    /// Formats set as `Allow` header value
+    pub fn to_header (&self) -> String {
+        return self.iter().map(|method| method.as_str()).collect::<Vec<_>>().join(", ");
+    }
+}
+
+impl From<HttpMethod> for MethodSet {
+    fn from (method: HttpMethod) -> Self {
+        let mut set = MethodSet::EMPTY;
+        set.insert(method);
+        return set;
+    }
+}
+
+impl BitOr for MethodSet {
+    type Output = MethodSet;
+
+    fn bitor (self, rhs: Self) -> MethodSet {
+        return MethodSet(self.0 | rhs.0);
+    }
+}
+
+impl BitOr<HttpMethod> for MethodSet {
+    type Output = MethodSet;
+
+    fn bitor (mut self, rhs: HttpMethod) -> MethodSet {
+        self.insert(rhs);
+        return self;
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct HttpHeader {
     pub name: String,
     pub value: String
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct HttpHeaders {
     contents: Vec<HttpHeader>
 }
@@ -63,6 +183,7 @@ impl HttpHeaders {
         self.set(name.to_ascii_lowercase(), value.trim_start().to_string());
     }
 
+    #[allow(clippy::redundant_pattern_matching)]
     pub fn set_default (&mut self, name: String, value: String) {
         if let None = self.contents.iter_mut().find(|h| h.name == name) {
             self.contents.push(HttpHeader { name, value });
@@ -85,6 +206,17 @@ impl HttpHeaders {
 
         return None;
     }
+
+    /// Checks whether comma-separated header value contains `token`, ignoring case
+    pub fn has_token (&self, name: &str, token: &str) -> bool {
+        for header in self {
+            if header.name == name && header.value.split(',').any(|item| item.trim().eq_ignore_ascii_case(token)) {
+                return true;
+            }
+        }
+
+        return false;
+    }
 }
 
 impl<'a> IntoIterator for &'a HttpHeaders {
@@ -97,32 +229,69 @@ impl<'a> IntoIterator for &'a HttpHeaders {
 }
 
 pub trait HttpConnection: Sized + Send + Sync {
+    type Transport: Transport;
+
     fn get_address (&self) -> IpAddr;
-    fn into_stream (self) -> BufStream<TcpStream>;
+    fn get_transport (&self) -> &Self::Transport;
+    fn into_stream (self) -> BufStream<Self::Transport>;
+
+    /// Whether connection can be reused for the next request after the last response
+    fn is_persistent (&self) -> bool;
+    /// Checks without blocking whether `parse_head` has anything to work on, closed stream counts too.
+    /// Connection without pending input can wait for it in the event loop instead of occupying a worker.
+    fn has_pending_input (&mut self) -> bool;
+    /// How long connection may wait for the next input before it's closed
+    fn idle_timeout (&self) -> Duration;
+
+    /// Reads request line and headers, body is left in the stream until `read_body` is called
+    fn parse_head (&mut self) -> ParsingResult;
+    /// Reads body of the request returned by `parse_head`, sending `100 Continue` first if client waits for it
+    fn read_body (&mut self, req: &mut Request) -> Result<(), ParsingResult>;
+    /// Reads `multipart/form-data` body instead of `read_body`, parts are parsed as they arrive, so files go
+    /// to disk or `sink` without holding the whole body. `None` means the body must be read with `read_body`.
+    fn read_multipart (&mut self, _req: &Request, _boundary: &str, _sink: &mut PartSink) -> Option<Result<Multipart, MultipartError>> {
+        return None;
+    }
+
+    fn parse (&mut self) -> ParsingResult {
+        return match self.parse_head() {
+            ParsingResult::Complete(mut req) => match self.read_body(&mut req) {
+                Ok(()) => ParsingResult::Complete(req),
+                Err(result) => result
+            },
+            result => result
+        };
+    }
 
-    fn parse (&mut self) -> ParsingResult;
     fn respond (&mut self, res: Response) -> Result<(), Error>;
-    fn disconnect (self) -> Result<(), Error>;
+    /// Shuts down writing side of the connection. Returned transport may still have unread input,
+    /// closing it right away would reset the connection, so it should be drained for a while first.
+    fn disconnect (self) -> Result<Self::Transport, Error>;
 }
 
 pub type BoxedHttpConnection = Box<dyn HttpConnection + Send>;
 
 pub trait HttpEngine<Connection: HttpConnection> {
-    fn handle_connection (socket: (TcpStream, SocketAddr)) -> Connection;
+    fn handle_connection (socket: (Socket, SocketAddr)) -> Connection;
 }
 
 pub enum ParsingResult {
     Complete(Request),
+    /// Request isn't received yet and connection has no input to wait for without blocking
     Partial,
     Error(HttpCode),
-    Invalid
+    Invalid,
+    /// Client started with HTTP/2 connection preface, the rest of the stream belongs to HTTP/2 engine
+    Http2
 }
 
 #[derive(Debug)]
 pub struct Response {
     pub code: HttpCode,
     pub headers: HttpHeaders,
-    pub payload: ResponseType
+    pub payload: ResponseType,
+    /// Whether payload may be compressed, e.g. already compressed files should opt out
+    pub compress: bool
 }
 
 pub enum ResponseRet<T = ()> {
@@ -150,6 +319,10 @@ impl<T> Try for ResponseRet<T> where ResponseRet<T>: FromResidual<ResponseRet<T>
     }
 }
 
+impl<T> Residual<T> for ResponseRet<T> {
+    type TryType = ResponseRet<T>;
+}
+
 // Any residuals can be safely re-casted, because `Result` will never stored it a residual
 impl<A, B> FromResidual<ResponseRet<B>> for ResponseRet<A> {
     fn from_residual (value: ResponseRet<B>) -> Self {
@@ -167,7 +340,8 @@ impl Response {
         Response {
             code: HttpCode::NotSent,
             headers: HttpHeaders::empty(),
-            payload: ResponseType::NoContent
+            payload: ResponseType::NoContent,
+            compress: true
         }
     }
 
@@ -175,7 +349,8 @@ impl Response {
         Response {
             code,
             headers: HttpHeaders::from_type("text/plain"),
-            payload: ResponseType::Payload(message.as_bytes().to_vec())
+            payload: ResponseType::Payload(message.as_bytes().to_vec()),
+            compress: true
         }
     }
 
@@ -183,7 +358,8 @@ impl Response {
         Response {
             code,
             headers: HttpHeaders::empty(),
-            payload: ResponseType::NoContent
+            payload: ResponseType::NoContent,
+            compress: true
         }
     }
 
@@ -191,18 +367,21 @@ impl Response {
         Response {
             code: HttpCode::GatewayTimeout,
             headers: HttpHeaders::empty(),
-            payload: ResponseType::Drop
+            payload: ResponseType::Drop,
+            compress: true
         }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Request {
     pub path: String,
     pub query: String,
     pub headers: HttpHeaders,
     pub method: HttpMethod,
-    pub body: Vec<u8>
+    pub body: Vec<u8>,
+    /// Fields and files of `multipart/form-data` body, streamed body isn't kept in `body`
+    pub multipart: Option<Arc<Multipart>>
 }
 
 impl Request {
@@ -217,12 +396,19 @@ impl Request {
             query,
             method,
             headers: HttpHeaders::empty(),
-            body: Vec::new()
+            body: Vec::new(),
+            multipart: None
         }
     }
 
     pub fn parse_content_length (&self) -> Option<usize> {
-        return self.headers.get("Content-Length").and_then(|len| usize::from_str(len.as_str()).ok());
+        // Integer parser also accepts sign, which isn't valid in the header
+        let len = self.headers.get("content-length")?;
+        if !len.bytes().all(|ch| ch.is_ascii_digit()) {
+            return None;
+        }
+
+        return usize::from_str(len.as_str()).ok();
     }
 }
 
@@ -230,6 +416,67 @@ impl Request {
 pub enum ResponseType {
     NoContent,
     Payload(Vec<u8>),
+    /// Body of unknown length, sent chunk by chunk as it is being produced
+    Stream(ResponseStream),
     Upgrade,
     Drop
 }
+
+/// Source of streamed response body chunks, error stops the response and closes the connection
+pub struct ResponseStream {
+    source: Box<dyn Iterator<Item = io::Result<Vec<u8>>> + Send>,
+    /// Length of the whole body when it's known beforehand, it's sent as `Content-Length` instead of chunking
+    size: Option<u64>
+}
+
+impl ResponseStream {
+    const READ_CHUNK_SIZE: usize = 16384;
+
+    pub fn new<I: Iterator<Item = io::Result<Vec<u8>>> + Send + 'static> (source: I) -> Self {
+        ResponseStream { source: Box::new(source), size: None }
+    }
+
+    pub fn from_chunks<I: IntoIterator<Item = Vec<u8>>> (chunks: I) -> Self where I::IntoIter: Send + 'static {
+        ResponseStream::new(chunks.into_iter().map(Ok))
+    }
+
+    pub fn from_reader<R: Read + Send + 'static> (mut reader: R) -> Self {
+        ResponseStream::new(std::iter::from_fn(move || {
+            let mut chunk = vec![0; Self::READ_CHUNK_SIZE];
+            return match reader.read(&mut chunk) {
+                Ok(0) => None,
+                Ok(len) => {
+                    chunk.truncate(len);
+                    Some(Ok(chunk))
+                }
+                Err(error) => Some(Err(error))
+            };
+        }))
+    }
+
+    /// Declares length of the body, source producing a different amount of bytes fails the response
+    pub fn with_size (mut self, size: u64) -> Self {
+        self.size = Some(size);
+        return self;
+    }
+
+    #[inline]
+    pub fn size (&self) -> Option<u64> {
+        return self.size;
+    }
+}
+
+impl Iterator for ResponseStream {
+    type Item = io::Result<Vec<u8>>;
+
+    #[inline]
+    fn next (&mut self) -> Option<Self::Item> {
+        return self.source.next();
+    }
+}
+
+impl fmt::Debug for ResponseStream {
+    fn fmt (&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return f.write_str("ResponseStream");
+    }
+}
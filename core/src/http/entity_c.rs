@@ -1,4 +1,52 @@
-use crate::{c::{c_deinit, c_init, c_str, c_string}, http::{codes::HttpCode, entity::{HttpHeaders, Response, ResponseType}}};
+#![allow(clippy::missing_safety_doc)]
+
+use std::{ffi::c_void, io, slice};
+use crate::{c::{c_deinit, c_init, c_str, c_string}, http::{codes::HttpCode, entity::{HttpHeaders, Response, ResponseStream, ResponseType}}};
+
+
+/// Called until it returns `false`, each call can write next body part using `stream_writer_write`
+pub type StreamPullFn = extern "C" fn (writer: *mut StreamWriter, state: *mut c_void) -> bool;
+/// Called once streaming is over or response is dropped, should release `state`
+pub type StreamDeinitFn = extern "C" fn (state: *mut c_void);
+
+pub struct StreamWriter {
+	chunk: Vec<u8>
+}
+
+struct CallbackStream {
+	pull: StreamPullFn,
+	deinit: Option<StreamDeinitFn>,
+	state: *mut c_void,
+	is_done: bool
+}
+
+// State is owned by the stream and only touched by the thread responding with it
+unsafe impl Send for CallbackStream {}
+
+impl Iterator for CallbackStream {
+	type Item = io::Result<Vec<u8>>;
+
+	fn next (&mut self) -> Option<Self::Item> {
+		while !self.is_done {
+			let mut writer = StreamWriter { chunk: Vec::new() };
+			self.is_done = !(self.pull)(&mut writer, self.state);
+
+			if !writer.chunk.is_empty() {
+				return Some(Ok(writer.chunk));
+			}
+		}
+
+		return None;
+	}
+}
+
+impl Drop for CallbackStream {
+	fn drop (&mut self) {
+		if let Some(deinit) = self.deinit {
+			deinit(self.state);
+		}
+	}
+}
 
 #[no_mangle]
 pub extern "C" fn response_new () -> *mut Response {
@@ -15,6 +63,12 @@ pub extern "C" fn response_headers (res: &mut Response) -> *mut HttpHeaders {
 	return &mut res.headers;
 }
 
+/// Disables compression of the payload, e.g. when it's already compressed
+#[no_mangle]
+pub extern "C" fn response_set_compress (res: &mut Response, compress: bool) {
+	res.compress = compress;
+}
+
 #[no_mangle]
 pub extern "C" fn response_set_drop (res: &mut Response) {
 	res.payload = ResponseType::Drop;
@@ -25,6 +79,25 @@ pub unsafe extern "C" fn response_set_payload (res: &mut Response, r_ptr: *mut u
 	res.payload = ResponseType::Payload(Vec::from_raw_parts(r_ptr, size, size));
 }
 
+/// Body is produced by `pull` while the response is sent, `deinit` is called after that or when response is dropped.
+/// Both get `state` on the worker thread sending the response, which may be another thread than the one creating it,
+/// so `state` must be safe to move between threads: no thread-local data and no use by other threads meanwhile.
+#[no_mangle]
+pub extern "C" fn response_set_stream (res: &mut Response, pull: StreamPullFn, deinit: Option<StreamDeinitFn>, state: *mut c_void) {
+	let stream = CallbackStream { pull, deinit, state, is_done: false };
+	res.payload = ResponseType::Stream(ResponseStream::new(stream));
+}
+
+/// Appends `size` bytes at `ptr` to the next body part, `ptr` may be null when `size` is 0
+#[no_mangle]
+pub unsafe extern "C" fn stream_writer_write (writer: &mut StreamWriter, ptr: *const u8, size: usize) {
+	if size == 0 {
+		return;
+	}
+
+	writer.chunk.extend_from_slice(slice::from_raw_parts(ptr, size));
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn http_headers_set (headers: &mut HttpHeaders, name: c_str, value: c_str) {
 	headers.set(c_string(name), c_string(value));
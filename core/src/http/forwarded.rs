@@ -0,0 +1,145 @@
+use std::net::{IpAddr, SocketAddr};
+use crate::http::entity::HttpHeaders;
+use crate::utils::proxy::{is_trusted, Cidr};
+
+/// Client as seen through trusted proxies
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientInfo {
+    pub address: IpAddr,
+    /// `http` or `https`
+    pub scheme: String,
+    pub host: Option<String>
+}
+
+/// One proxy hop from `Forwarded` or `X-Forwarded-*` headers, `address` is `None` for obfuscated or unknown ones
+#[derive(Default)]
+struct Hop {
+    address: Option<IpAddr>,
+    proto: Option<String>,
+    host: Option<String>
+}
+
+impl ClientInfo {
+    /// Takes client address, scheme and host from forwarding headers if connection came from trusted proxy.
+    /// Hops are walked from the nearest one, the first untrusted address is the client.
+    /// `Forwarded` is preferred, `X-Forwarded-For` with `X-Forwarded-Proto` and `X-Forwarded-Host` is the fallback.
+    pub fn resolve (peer: IpAddr, is_tls: bool, headers: &HttpHeaders, trusted: &[Cidr]) -> Self {
+        let mut client = ClientInfo {
+            address: peer.to_canonical(),
+            scheme: if is_tls { "https" } else { "http" }.to_owned(),
+            host: headers.get("host")
+        };
+
+        if !is_trusted(trusted, client.address) {
+            return client;
+        }
+
+        let hops = match headers.get("forwarded") {
+            Some(forwarded) => parse_forwarded(&forwarded),
+            None => parse_x_forwarded(headers)
+        };
+
+        let mut origin = None;
+        for hop in hops.iter().rev() {
+            origin = Some(hop);
+            match hop.address {
+                Some(address) => client.address = address,
+                // Nothing is known past unknown hop, so the last trusted address stays
+                None => break
+            }
+
+            if !is_trusted(trusted, client.address) {
+                break;
+            }
+        }
+
+        if let Some(hop) = origin {
+            if let Some(proto) = &hop.proto {
+                client.scheme = proto.clone();
+            }
+
+            if hop.host.is_some() {
+                client.host = hop.host.clone();
+            }
+        }
+
+        return client;
+    }
+}
+
+/// RFC 7239 list, e.g. `for=192.0.2.60;proto=https, for="[2001:db8::1]:4711"`
+fn parse_forwarded (value: &str) -> Vec<Hop> {
+    let mut hops = Vec::new();
+    for element in split_unquoted(value, ',') {
+        let mut hop = Hop::default();
+        for pair in split_unquoted(element, ';') {
+            let Some((key, value)) = pair.split_once('=') else {
+                continue;
+            };
+
+            let value = value.trim().trim_matches('"');
+            match key.trim().to_ascii_lowercase().as_str() {
+                "for" => hop.address = parse_node(value),
+                "proto" => hop.proto = parse_proto(value),
+                "host" if !value.is_empty() => hop.host = Some(value.to_owned()),
+                _ => {}
+            }
+        }
+
+        hops.push(hop);
+    }
+
+    return hops;
+}
+
+/// De facto headers, proto and host are set once by the proxy facing the client, so they apply to any hop
+fn parse_x_forwarded (headers: &HttpHeaders) -> Vec<Hop> {
+    let Some(forwarded_for) = headers.get("x-forwarded-for") else {
+        return Vec::new();
+    };
+
+    let first_value = |name| headers.get(name).and_then(|value| value.split(',').next().map(|value| value.trim().to_owned()));
+    let proto = first_value("x-forwarded-proto").and_then(|proto| parse_proto(&proto));
+    let host = first_value("x-forwarded-host").filter(|host| !host.is_empty());
+
+    return forwarded_for.split(',')
+        .map(|node| Hop { address: parse_node(node.trim()), proto: proto.clone(), host: host.clone() })
+        .collect();
+}
+
+/// Address with optional port, IPv6 may be in brackets, `unknown` and obfuscated identifiers give `None`
+fn parse_node (node: &str) -> Option<IpAddr> {
+    if let Ok(address) = node.parse::<IpAddr>() {
+        return Some(address.to_canonical());
+    }
+
+    if let Ok(address) = node.parse::<SocketAddr>() {
+        return Some(address.ip().to_canonical());
+    }
+
+    let address = node.strip_prefix('[')?.split_once(']')?.0;
+    return address.parse::<IpAddr>().ok().map(|address| address.to_canonical());
+}
+
+fn parse_proto (proto: &str) -> Option<String> {
+    let proto = proto.to_ascii_lowercase();
+    return if proto == "http" || proto == "https" { Some(proto) } else { None };
+}
+
+/// Splits by `separator` outside of double quotes
+fn split_unquoted (value: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut is_quoted = false;
+    let mut start = 0;
+    for (index, char) in value.char_indices() {
+        if char == '"' {
+            is_quoted = !is_quoted;
+        } else if char == separator && !is_quoted {
+            parts.push(&value[start..index]);
+            start = index + 1;
+        }
+    }
+
+    parts.push(&value[start..]);
+    return parts;
+}
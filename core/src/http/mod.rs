@@ -1,4 +1,10 @@
 pub mod codes;
+pub mod compression;
 pub mod cors;
 pub mod entity;
 pub mod entity_c;
+pub mod forwarded;
+pub mod multipart;
+pub mod multipart_c;
+pub mod query;
+pub mod url;
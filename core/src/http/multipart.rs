@@ -0,0 +1,420 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicU64, Ordering};
+use crate::app::config::UploadsConfig;
+use crate::http::codes::HttpCode;
+use crate::http::query::Query;
+use crate::utils::socket::is_timeout;
+
+pub const MULTIPART_FORM_DATA: &str = "multipart/form-data";
+
+/// Chooses where file of the part goes, `None` stores it in a temporary file
+pub type PartSink<'a> = dyn FnMut(&PartInfo) -> Option<Box<dyn Write>> + 'a;
+
+/// Headers of a single part
+#[derive(Debug, Clone, Default)]
+pub struct PartInfo {
+    /// Field name from `Content-Disposition`
+    pub name: String,
+    /// Present for file fields only, empty when no file was chosen
+    pub filename: Option<String>,
+    pub content_type: Option<String>
+}
+
+/// File field of the form, its temporary file is removed together with it
+#[derive(Debug)]
+pub struct UploadedFile {
+    pub name: String,
+    pub filename: String,
+    pub content_type: Option<String>,
+    pub size: u64,
+    /// `None` when the file went to a handler-provided sink
+    path: Option<PathBuf>
+}
+
+impl UploadedFile {
+    #[inline]
+    pub fn path (&self) -> Option<&Path> {
+        return self.path.as_deref();
+    }
+
+    /// Moves the file to `target`, so it outlives the request
+    pub fn persist<P: AsRef<Path>> (&self, target: P) -> io::Result<()> {
+        let Some(path) = &self.path else {
+            return Err(io::Error::new(ErrorKind::NotFound, "uploaded file went to a sink"));
+        };
+
+        // Rename doesn't work across file systems, then the temporary file is copied and removed on drop
+        if fs::rename(path, &target).is_err() {
+            fs::copy(path, &target)?;
+        }
+
+        return Ok(());
+    }
+}
+
+impl Drop for UploadedFile {
+    fn drop (&mut self) {
+        if let Some(path) = &self.path {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum MultipartError {
+    /// Body doesn't follow the format
+    Malformed,
+    /// Part or the whole body is over its limit
+    TooLarge,
+    /// Reading the body failed, e.g. client timed out or went away
+    Read(io::Error),
+    /// Storing the file failed
+    Write(io::Error)
+}
+
+impl MultipartError {
+    pub fn status (&self) -> HttpCode {
+        return match self {
+            MultipartError::Malformed => HttpCode::BadRequest,
+            MultipartError::TooLarge => HttpCode::RequestEntityTooLarge,
+            MultipartError::Read(error) if is_timeout(error) => HttpCode::RequestTimeout,
+            MultipartError::Read(_) => HttpCode::BadRequest,
+            MultipartError::Write(_) => HttpCode::InternalServerError
+        };
+    }
+}
+
+/// Parsed `multipart/form-data` body: text fields are kept in memory, files are stored as they arrive
+#[derive(Debug, Default)]
+pub struct Multipart {
+    fields: Query,
+    files: Vec<UploadedFile>
+}
+
+impl Multipart {
+    /// Parses body coming from `reader` part by part, so only a small buffer of it is held at once.
+    /// Files go to `sink` or, when it gives nothing, to temporary files in `config.dir`.
+    pub fn read<R: Read> (reader: R, boundary: &str, config: &UploadsConfig, sink: &mut PartSink) -> Result<Self, MultipartError> {
+        let mut reader = PartReader::new(reader, boundary, config.total_size);
+        let mut fields = Vec::new();
+        let mut files = Vec::new();
+
+        // Preamble before the first delimiter is ignored
+        reader.copy_until_delimiter(&mut |_| Ok(()))?;
+        while let Some(info) = reader.next_part()? {
+            if fields.len() + files.len() >= config.parts {
+                return Err(MultipartError::TooLarge);
+            }
+
+            match info.filename.clone() {
+                Some(filename) => files.push(Multipart::read_file(&mut reader, info, filename, config, sink)?),
+                None => {
+                    let mut value = Vec::new();
+                    reader.copy_until_delimiter(&mut |data| {
+                        if value.len() + data.len() > config.field_size {
+                            return Err(MultipartError::TooLarge);
+                        }
+
+                        value.extend_from_slice(data);
+                        return Ok(());
+                    })?;
+
+                    fields.push((info.name, String::from_utf8_lossy(&value).into_owned()));
+                }
+            }
+        }
+
+        return Ok(Multipart { fields: Query::from(fields), files });
+    }
+
+    fn read_file<R: Read> (reader: &mut PartReader<R>, info: PartInfo, filename: String, config: &UploadsConfig, sink: &mut PartSink) -> Result<UploadedFile, MultipartError> {
+        let (mut writer, path) = match sink(&info) {
+            Some(writer) => (writer, None),
+            None => {
+                let (file, path) = create_temp_file(&config.dir).map_err(MultipartError::Write)?;
+                (Box::new(BufWriter::new(file)) as Box<dyn Write>, Some(path))
+            }
+        };
+
+        // Created file is removed with this one if reading fails
+        let mut file = UploadedFile { name: info.name, filename, content_type: info.content_type, size: 0, path };
+        let result = reader.copy_until_delimiter(&mut |data| {
+            file.size += data.len() as u64;
+            if file.size > config.part_size {
+                return Err(MultipartError::TooLarge);
+            }
+
+            return writer.write_all(data).map_err(MultipartError::Write);
+        }).and_then(|_| writer.flush().map_err(MultipartError::Write));
+
+        // File is closed before it may be removed
+        drop(writer);
+        result?;
+        return Ok(file);
+    }
+
+    /// Text fields, the same way as fields of urlencoded form
+    #[inline]
+    pub fn fields (&self) -> &Query {
+        return &self.fields;
+    }
+
+    #[inline]
+    pub fn files (&self) -> &[UploadedFile] {
+        return &self.files;
+    }
+
+    /// First file of the field
+    pub fn file (&self, name: &str) -> Option<&UploadedFile> {
+        return self.files.iter().find(|file| file.name == name);
+    }
+}
+
+/// Boundary from `Content-Type` of `multipart/form-data` body, `None` for other types or invalid boundary
+pub fn boundary (content_type: &str) -> Option<String> {
+    let mut params = split_params(content_type).into_iter();
+    if !params.next()?.trim().eq_ignore_ascii_case(MULTIPART_FORM_DATA) {
+        return None;
+    }
+
+    let boundary = params.find_map(|param| {
+        let (name, value) = param.split_once('=')?;
+        return name.trim().eq_ignore_ascii_case("boundary").then(|| unquote(value.trim()));
+    })?;
+
+    if boundary.is_empty() || boundary.len() > 70 || boundary.ends_with(' ') {
+        return None;
+    }
+
+    return Some(boundary);
+}
+
+/// Buffered reader of the body that finds delimiters without holding more than its buffer
+struct PartReader<R: Read> {
+    reader: R,
+    buf: Box<[u8]>,
+    start: usize,
+    end: usize,
+    /// `\r\n--boundary`, line break is prepended to the body, so the first delimiter has it too
+    delimiter: Vec<u8>,
+    read: u64,
+    limit: u64
+}
+
+impl<R: Read> PartReader<R> {
+    const BUFFER_SIZE: usize = 64 * 1024;
+    /// Longest header line of a part, must be smaller than the buffer
+    const LINE_LIMIT: usize = 8192;
+    const HEADERS_LIMIT: usize = 32;
+
+    fn new (reader: R, boundary: &str, limit: u64) -> Self {
+        let mut buf = vec![0; Self::BUFFER_SIZE].into_boxed_slice();
+        buf[..2].copy_from_slice(b"\r\n");
+
+        return PartReader { reader, buf, start: 0, end: 2, delimiter: format!("\r\n--{boundary}").into_bytes(), read: 0, limit };
+    }
+
+    #[inline]
+    fn pending (&self) -> &[u8] {
+        return &self.buf[self.start..self.end];
+    }
+
+    /// Moves pending data to the beginning of the buffer and reads more after it, `false` means end of body
+    fn fill (&mut self) -> Result<bool, MultipartError> {
+        self.buf.copy_within(self.start..self.end, 0);
+        self.end -= self.start;
+        self.start = 0;
+
+        let count = loop {
+            match self.reader.read(&mut self.buf[self.end..]) {
+                Ok(count) => break count,
+                Err(error) if error.kind() == ErrorKind::Interrupted => continue,
+                Err(error) => return Err(MultipartError::Read(error))
+            }
+        };
+
+        self.read += count as u64;
+        if self.read > self.limit {
+            return Err(MultipartError::TooLarge);
+        }
+
+        self.end += count;
+        return Ok(count > 0);
+    }
+
+    /// Passes data up to the next delimiter to `write` and skips the delimiter
+    fn copy_until_delimiter (&mut self, write: &mut dyn FnMut(&[u8]) -> Result<(), MultipartError>) -> Result<(), MultipartError> {
+        loop {
+            if let Some(index) = find(self.pending(), &self.delimiter) {
+                write(&self.buf[self.start..self.start + index])?;
+                self.start += index + self.delimiter.len();
+                return Ok(());
+            }
+
+            // Tail may be the beginning of the delimiter, it waits for more data
+            let ready = self.pending().len().saturating_sub(self.delimiter.len() - 1);
+            write(&self.buf[self.start..self.start + ready])?;
+            self.start += ready;
+
+            if !self.fill()? {
+                return Err(MultipartError::Malformed);
+            }
+        }
+    }
+
+    fn read_line (&mut self) -> Result<Vec<u8>, MultipartError> {
+        loop {
+            if let Some(index) = find(self.pending(), b"\r\n") {
+                let line = self.pending()[..index].to_vec();
+                self.start += index + 2;
+                return Ok(line);
+            }
+
+            if self.pending().len() > Self::LINE_LIMIT || !self.fill()? {
+                return Err(MultipartError::Malformed);
+            }
+        }
+    }
+
+    /// Reads headers of the part following the delimiter, `None` means the closing delimiter
+    fn next_part (&mut self) -> Result<Option<PartInfo>, MultipartError> {
+        while self.pending().len() < 2 {
+            if !self.fill()? {
+                return Err(MultipartError::Malformed);
+            }
+        }
+
+        if self.pending().starts_with(b"--") {
+            // Epilogue is ignored, but still read, so the connection can take the next request
+            self.start = self.end;
+            while self.fill()? {
+                self.start = self.end;
+            }
+
+            return Ok(None);
+        }
+
+        // Delimiter line may end with whitespace
+        if !self.read_line()?.iter().all(|byte| matches!(byte, b' ' | b'\t')) {
+            return Err(MultipartError::Malformed);
+        }
+
+        let mut info = PartInfo::default();
+        let mut has_disposition = false;
+        for _ in 0..=Self::HEADERS_LIMIT {
+            let line = self.read_line()?;
+            if line.is_empty() {
+                if has_disposition {
+                    return Ok(Some(info));
+                } else {
+                    return Err(MultipartError::Malformed);
+                }
+            }
+
+            let line = String::from_utf8_lossy(&line);
+            let Some((name, value)) = line.split_once(':') else {
+                return Err(MultipartError::Malformed);
+            };
+
+            if name.trim().eq_ignore_ascii_case("content-disposition") {
+                parse_disposition(value, &mut info)?;
+                has_disposition = true;
+            } else if name.trim().eq_ignore_ascii_case("content-type") {
+                info.content_type = Some(value.trim().to_owned());
+            }
+        }
+
+        return Err(MultipartError::Malformed);
+    }
+}
+
+/// `form-data; name="field"; filename="file.txt"`
+fn parse_disposition (value: &str, info: &mut PartInfo) -> Result<(), MultipartError> {
+    let mut params = split_params(value).into_iter();
+    if !params.next().is_some_and(|kind| kind.trim().eq_ignore_ascii_case("form-data")) {
+        return Err(MultipartError::Malformed);
+    }
+
+    let mut name = None;
+    for param in params {
+        let Some((key, value)) = param.split_once('=') else {
+            continue;
+        };
+
+        match key.trim().to_ascii_lowercase().as_str() {
+            "name" => name = Some(unquote(value.trim())),
+            "filename" => info.filename = Some(unquote(value.trim())),
+            _ => {}
+        }
+    }
+
+    info.name = name.ok_or(MultipartError::Malformed)?;
+    return Ok(());
+}
+
+/// Splits header value by `;` outside of quoted strings
+fn split_params (value: &str) -> Vec<&str> {
+    let mut params = Vec::new();
+    let mut start = 0;
+    let mut in_quotes = false;
+    let mut escaped = false;
+    for (index, char) in value.char_indices() {
+        match char {
+            _ if escaped => escaped = false,
+            '\\' if in_quotes => escaped = true,
+            '"' => in_quotes = !in_quotes,
+            ';' if !in_quotes => {
+                params.push(&value[start..index]);
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+
+    params.push(&value[start..]);
+    return params;
+}
+
+fn unquote (value: &str) -> String {
+    let Some(quoted) = value.strip_prefix('"').and_then(|value| value.strip_suffix('"')) else {
+        return value.to_owned();
+    };
+
+    let mut unquoted = String::with_capacity(quoted.len());
+    let mut chars = quoted.chars();
+    while let Some(char) = chars.next() {
+        match char {
+            '\\' => unquoted.extend(chars.next()),
+            char => unquoted.push(char)
+        }
+    }
+
+    return unquoted;
+}
+
+fn find (haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    return haystack.windows(needle.len()).position(|window| window == needle);
+}
+
+/// File only the server user can read, named uniquely within the process
+fn create_temp_file (dir: &Path) -> io::Result<(File, PathBuf)> {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+    loop {
+        let path = dir.join(format!("photonyx-upload-{}-{}", process::id(), COUNTER.fetch_add(1, Ordering::Relaxed)));
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+        match options.open(&path) {
+            Ok(file) => return Ok((file, path)),
+            // Left by the previous process with the same id
+            Err(error) if error.kind() == ErrorKind::AlreadyExists => continue,
+            Err(error) => return Err(error)
+        }
+    }
+}
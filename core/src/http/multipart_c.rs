@@ -0,0 +1,52 @@
+#![allow(clippy::missing_safety_doc)]
+
+use std::ptr;
+use crate::{c::{c_init_str, c_str, c_string}, http::multipart::UploadedFile};
+
+
+/// Field name of the file, should be freed with `str_drop`
+#[no_mangle]
+pub extern "C" fn uploaded_file_get_name (file: &UploadedFile) -> c_str {
+	return c_init_str(without_nul(&file.name));
+}
+
+/// File name sent by client, empty when no file was chosen, should be freed with `str_drop`.
+/// It's untrusted and must not be used as a path as is.
+#[no_mangle]
+pub extern "C" fn uploaded_file_get_filename (file: &UploadedFile) -> c_str {
+	return c_init_str(without_nul(&file.filename));
+}
+
+/// Media type sent by client or null if there is none, should be freed with `str_drop`
+#[no_mangle]
+pub extern "C" fn uploaded_file_get_content_type (file: &UploadedFile) -> c_str {
+	return match &file.content_type {
+		Some(content_type) => c_init_str(without_nul(content_type)),
+		None => ptr::null()
+	};
+}
+
+#[no_mangle]
+pub extern "C" fn uploaded_file_get_size (file: &UploadedFile) -> u64 {
+	return file.size;
+}
+
+/// Path of the temporary file, which is removed after the request, or null if the file went to a sink.
+/// Should be freed with `str_drop`.
+#[no_mangle]
+pub extern "C" fn uploaded_file_get_path (file: &UploadedFile) -> c_str {
+	return match file.path().and_then(|path| path.to_str()) {
+		Some(path) => c_init_str(path),
+		None => ptr::null()
+	};
+}
+
+/// Moves the file to `target`, so it isn't removed after the request
+#[no_mangle]
+pub unsafe extern "C" fn uploaded_file_persist (file: &UploadedFile, target: c_str) -> bool {
+	return file.persist(c_string(target)).is_ok();
+}
+
+fn without_nul (value: &str) -> &str {
+	return value.split('\0').next().unwrap_or_default();
+}
@@ -0,0 +1,144 @@
+use std::str::FromStr;
+use json::JsonValue;
+use crate::http::url::decode_hex_pair;
+use crate::utils::validator::ValidationError;
+
+/// Parameters of `application/x-www-form-urlencoded` string, e.g. query of the request.
+/// Keys may repeat, order is kept.
+#[derive(Debug, Default, Clone)]
+pub struct Query {
+    pairs: Vec<(String, String)>
+}
+
+impl Query {
+    /// Decodes pairs the way browsers do: `+` is space, malformed `%` sequences are kept as they are
+    pub fn parse (raw: &str) -> Self {
+        let pairs = raw.split('&')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+                return (decode_component(name), decode_component(value));
+            })
+            .collect();
+
+        return Query { pairs };
+    }
+
+    /// First value of the parameter, `?flag` without value gives empty string
+    pub fn get (&self, name: &str) -> Option<&str> {
+        return self.pairs.iter().find(|(key, _)| key == name).map(|(_, value)| value.as_str());
+    }
+
+    /// All values of repeated parameter, e.g. `?tag=a&tag=b`
+    pub fn get_all (&self, name: &str) -> Vec<&str> {
+        return self.pairs.iter().filter(|(key, _)| key == name).map(|(_, value)| value.as_str()).collect();
+    }
+
+    #[inline]
+    pub fn has (&self, name: &str) -> bool {
+        return self.get(name).is_some();
+    }
+
+    /// First value parsed as `T`, missing parameter gives `None`
+    pub fn get_parsed<T: FromStr> (&self, name: &str) -> Result<Option<T>, ValidationError> {
+        let Some(value) = self.get(name) else {
+            return Ok(None);
+        };
+
+        return match value.trim().parse::<T>() {
+            Ok(value) => Ok(Some(value)),
+            Err(_) => Err(ValidationError {
+                message: format!("expected {}", short_type_name::<T>()),
+                path: vec![name.to_owned()]
+            })
+        };
+    }
+
+    /// Accepts `true`/`false`, `1`/`0`, `yes`/`no` and `on`/`off`, flag without value is `true`
+    pub fn get_bool (&self, name: &str) -> Result<Option<bool>, ValidationError> {
+        let Some(value) = self.get(name) else {
+            return Ok(None);
+        };
+
+        return match parse_bool(value) {
+            Some(value) => Ok(Some(value)),
+            None => Err(ValidationError { message: "expected bool".to_owned(), path: vec![name.to_owned()] })
+        };
+    }
+
+    #[inline]
+    pub fn len (&self) -> usize {
+        return self.pairs.len();
+    }
+
+    #[inline]
+    pub fn is_empty (&self) -> bool {
+        return self.pairs.is_empty();
+    }
+
+    pub fn iter (&self) -> impl Iterator<Item = (&str, &str)> {
+        return self.pairs.iter().map(|(name, value)| (name.as_str(), value.as_str()));
+    }
+
+    /// Object with string values, repeated parameters become arrays
+    pub fn to_json (&self) -> JsonValue {
+        let mut object = JsonValue::new_object();
+        for (name, value) in &self.pairs {
+            match &mut object[name.as_str()] {
+                JsonValue::Null => object[name.as_str()] = value.as_str().into(),
+                JsonValue::Array(values) => values.push(value.as_str().into()),
+                first => *first = JsonValue::Array(vec![first.take(), value.as_str().into()])
+            }
+        }
+
+        return object;
+    }
+}
+
+impl From<Vec<(String, String)>> for Query {
+    fn from (pairs: Vec<(String, String)>) -> Self {
+        return Query { pairs };
+    }
+}
+
+/// Bool given as text, empty one is a flag without value
+pub fn parse_bool (value: &str) -> Option<bool> {
+    return match value.trim().to_ascii_lowercase().as_str() {
+        "" | "true" | "1" | "yes" | "on" => Some(true),
+        "false" | "0" | "no" | "off" => Some(false),
+        _ => None
+    };
+}
+
+fn decode_component (raw: &str) -> String {
+    if !raw.contains(['+', '%']) {
+        return raw.to_owned();
+    }
+
+    let raw = raw.as_bytes();
+    let mut decoded = Vec::with_capacity(raw.len());
+    let mut i = 0;
+    while i < raw.len() {
+        match raw[i] {
+            b'%' if i + 2 < raw.len() => match decode_hex_pair(raw[i + 1], raw[i + 2]) {
+                Some(byte) => {
+                    decoded.push(byte);
+                    i += 2;
+                }
+                None => decoded.push(b'%')
+            },
+            b'+' => decoded.push(b' '),
+            byte => decoded.push(byte)
+        }
+
+        i += 1;
+    }
+
+    return String::from_utf8_lossy(&decoded).into_owned();
+}
+
+/// `i32` instead of `core::primitive::i32`, for error messages
+fn short_type_name<T> () -> &'static str {
+    let name = std::any::type_name::<T>();
+    return name.rsplit("::").next().unwrap_or(name);
+}
@@ -0,0 +1,101 @@
+/// Decodes `%XX` sequences, `None` means malformed sequence or result that isn't UTF-8
+pub fn percent_decode (input: &str) -> Option<String> {
+    if !input.contains('%') {
+        return Some(input.to_owned());
+    }
+
+    let mut decoded = Vec::with_capacity(input.len());
+    let mut bytes = input.bytes();
+    while let Some(byte) = bytes.next() {
+        if byte == b'%' {
+            decoded.push(decode_hex_pair(bytes.next()?, bytes.next()?)?);
+        } else {
+            decoded.push(byte);
+        }
+    }
+
+    return String::from_utf8(decoded).ok();
+}
+
+/// Canonical form of absolute path used for routing: segments are decoded, `.` and `..` resolved and empty ones removed.
+/// Encoded `/` and `%` stay encoded as `%2F` and `%25`, so decoding can't change how the path splits into segments.
+/// `None` means malformed encoding, encoded NUL or result that isn't UTF-8.
+pub fn normalize_path (raw: &str) -> Option<String> {
+    let mut segments = Vec::new();
+    // Path ending with empty or dot segment refers to a directory
+    let mut is_dir = false;
+
+    for segment in raw.strip_prefix('/')?.split('/') {
+        let segment = decode_segment(segment)?;
+        match segment.as_str() {
+            "" | "." => is_dir = true,
+            // Dot segments above the root are dropped, like browsers do
+            ".." => {
+                segments.pop();
+                is_dir = true;
+            }
+            _ => {
+                segments.push(segment);
+                is_dir = false;
+            }
+        }
+    }
+
+    let mut path = String::with_capacity(raw.len());
+    for segment in &segments {
+        path.push('/');
+        path.push_str(segment);
+    }
+
+    if is_dir || path.is_empty() {
+        path.push('/');
+    }
+
+    return Some(path);
+}
+
+/// Encodes normalized path to be sent back, e.g. in `Location`, it's the form canonical request would have
+pub fn encode_path (path: &str) -> String {
+    let mut encoded = String::with_capacity(path.len());
+    for byte in path.bytes() {
+        // Percent signs left in normalized path already start `%2F` and `%25`
+        if byte.is_ascii_alphanumeric() || b"/%-._~!$&'()*+,;=:@".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{byte:02X}"));
+        }
+    }
+
+    return encoded;
+}
+
+fn decode_segment (segment: &str) -> Option<String> {
+    if !segment.contains('%') {
+        return Some(segment.to_owned());
+    }
+
+    let mut decoded = Vec::with_capacity(segment.len());
+    let mut bytes = segment.bytes();
+    while let Some(byte) = bytes.next() {
+        if byte != b'%' {
+            decoded.push(byte);
+            continue;
+        }
+
+        match decode_hex_pair(bytes.next()?, bytes.next()?)? {
+            0 => return None,
+            b'/' => decoded.extend(b"%2F"),
+            b'%' => decoded.extend(b"%25"),
+            byte => decoded.push(byte)
+        }
+    }
+
+    return String::from_utf8(decoded).ok();
+}
+
+#[inline]
+pub(crate) fn decode_hex_pair (high: u8, low: u8) -> Option<u8> {
+    let high = (high as char).to_digit(16)?;
+    let low = (low as char).to_digit(16)?;
+    return Some((high * 16 + low) as u8);
+}
@@ -1,144 +1,504 @@
-use std::io::{Error, Read, Write};
-use std::net::{SocketAddr, IpAddr, TcpStream, Shutdown};
+use std::io::{Error, ErrorKind, Read, Write};
+use std::net::{SocketAddr, IpAddr};
+use std::time::{Duration, SystemTime};
 use byteorder::ReadBytesExt;
 use byteorder::WriteBytesExt;
 use bufstream::BufStream;
+use httpdate::fmt_http_date;
 use photonyx_macro::assert_stream;
+use crate::app::config::CONFIG;
+use crate::app::server::is_shutting_down;
 use crate::http::codes::HttpCode;
-use crate::http::entity::{HttpConnection, HttpEngine, HttpMethod, ParsingResult, Request, Response, ResponseType};
-use crate::utils::stream::StreamUtils;
+use crate::http::entity::{HttpConnection, HttpEngine, HttpHeaders, HttpMethod, ParsingResult, Request, Response, ResponseStream, ResponseType};
+use crate::http::multipart::{Multipart, MultipartError, PartSink};
+use crate::utils::socket::{has_input, is_timeout, Socket, Transport};
+use crate::utils::stream::{ReadError, StreamUtils};
 
 #[derive(Copy, Clone)]
 pub struct Http1Engine;
 
 impl HttpEngine<Http1Connection> for Http1Engine {
-    fn handle_connection (socket: (TcpStream, SocketAddr)) -> Http1Connection {
+    fn handle_connection (socket: (Socket, SocketAddr)) -> Http1Connection {
         Http1Connection::new(socket)
     }
 }
 
-pub struct Http1Connection {
-    stream: BufStream<TcpStream>,
+pub struct Http1Connection<T: Transport = Socket> {
+    stream: BufStream<T>,
     address: IpAddr,
-    version_minor: char
+    version_minor: char,
+    keep_alive: bool,
+    requests_count: usize,
+    /// Response to `HEAD` request has the same headers as `GET` one, but without body
+    is_head: bool,
+    /// Body of the current request is announced but not read yet, so response to it must close the connection
+    body_pending: bool
 }
 
 impl Http1Connection {
-    fn new (socket: (TcpStream, SocketAddr)) -> Self {
+    fn new (socket: (Socket, SocketAddr)) -> Self {
+        let socket_stream = socket.0;
+        // Client that doesn't read responses shouldn't occupy the worker forever
+        let _ = socket_stream.set_write_timeout(CONFIG.timeouts.write);
+
+        return Http1Connection::from_transport(socket_stream, socket.1.ip());
+    }
+}
+
+impl<T: Transport> Http1Connection<T> {
+    /// Longest known method is 7 bytes, anything longer is surely unsupported
+    const METHOD_LIMIT: usize = 16;
+
+    pub fn from_transport (transport: T, address: IpAddr) -> Self {
         Http1Connection {
-            stream: BufStream::new(socket.0),
-            address: socket.1.ip(),
-            version_minor: '\0'
+            stream: BufStream::new(transport),
+            address,
+            version_minor: '1',
+            keep_alive: false,
+            requests_count: 0,
+            is_head: false,
+            body_pending: false
         }
     }
 
-    fn read_body (&mut self, body: &mut Vec<u8>) {
-        let mut chunk = [0; 1024];
+    /// HTTP/1.1 connections are persistent unless `Connection: close` is sent,
+    /// HTTP/1.0 ones only when client explicitly asks for `Connection: keep-alive`
+    fn update_keep_alive (&mut self, req: &Request) {
+        self.requests_count += 1;
+        self.keep_alive = self.requests_count < CONFIG.keep_alive.max_requests && match self.version_minor {
+            '0' => req.headers.has_token("connection", "keep-alive"),
+            _ => !req.headers.has_token("connection", "close")
+        };
+    }
+
+    fn prepare_headers (&mut self, res: &mut Response) {
+        res.headers.set_default("date".to_owned(), fmt_http_date(SystemTime::now()));
+
+        match &res.payload {
+            ResponseType::Payload(payload) => {
+                res.headers.set("content-length".to_owned(), payload.len().to_string());
+            }
+            ResponseType::Stream(stream) => {
+                if let Some(size) = stream.size() {
+                    res.headers.set("content-length".to_owned(), size.to_string());
+                } else if self.version_minor == '0' {
+                    // HTTP/1.0 clients have no chunked coding, so end of the body is marked by closing connection
+                    self.keep_alive = false;
+                } else {
+                    res.headers.remove("content-length".to_owned());
+                    res.headers.set("transfer-encoding".to_owned(), "chunked".to_owned());
+                }
+            }
+            ResponseType::NoContent => {
+                if res.code.allows_body() {
+                    res.headers.set("content-length".to_owned(), "0".to_owned());
+                }
+            }
+            // Upgrade handshake manages `Connection` header by itself
+            ResponseType::Upgrade | ResponseType::Drop => return
+        }
+
+        // Requests running during shutdown are the last ones on their connections
+        if is_shutting_down() {
+            self.keep_alive = false;
+        }
+
+        if self.keep_alive {
+            res.headers.set("connection".to_owned(), "keep-alive".to_owned());
+            res.headers.set("keep-alive".to_owned(), format!("timeout={}", CONFIG.keep_alive.timeout.as_secs()));
+        } else {
+            res.headers.set("connection".to_owned(), "close".to_owned());
+        }
+    }
+
+    fn write_stream (&mut self, stream: ResponseStream) -> Result<(), Error> {
+        let size = stream.size();
+        let is_chunked = size.is_none() && self.version_minor != '0';
+        let mut written = 0u64;
+        for chunk in stream {
+            let chunk = match chunk {
+                Ok(chunk) => chunk,
+                Err(error) => {
+                    // Unterminated body tells client that response is incomplete
+                    self.keep_alive = false;
+                    return Err(error);
+                }
+            };
+
+            // Empty chunk would be treated as the last one
+            if chunk.is_empty() {
+                continue;
+            }
+
+            written += chunk.len() as u64;
+            if size.is_some_and(|size| written > size) {
+                self.keep_alive = false;
+                return Err(Error::other("stream is longer than its declared size"));
+            }
+
+            if is_chunked {
+                write!(self.stream, "{:x}\r\n", chunk.len())?;
+                self.stream.write_all(&chunk)?;
+                self.stream.write_all(b"\r\n")?;
+            } else {
+                self.stream.write_all(&chunk)?;
+            }
+
+            self.stream.flush()?;
+        }
+
+        if is_chunked {
+            self.stream.write_all(b"0\r\n\r\n")?;
+        } else if size.is_some_and(|size| written < size) {
+            // Client waits for the rest of the declared body, closing the connection tells it that response is incomplete
+            self.keep_alive = false;
+            return Err(Error::other("stream is shorter than its declared size"));
+        }
+
+        return self.stream.flush();
+    }
+
+    fn read_headers (&mut self, headers: &mut HttpHeaders) -> Result<(), ParsingResult> {
+        let limits = &CONFIG.limits;
+        let mut count = 0;
 
         loop {
-            let n = self.stream.read(&mut chunk).unwrap();
-            if n == 0 {
-                break;
+            // Limit of the line doesn't include CRLF
+            let mut line = match self.stream.read_string_before('\n', limits.header_line + 2) {
+                Ok(line) => line,
+                Err(error) => return Err(Self::read_error(error, HttpCode::RequestHeaderFieldsTooLarge))
+            };
+
+            if line.ends_with('\r') {
+                line.pop();
             }
 
-            body.extend_from_slice(&chunk[..n]);
-            if n < chunk.len() {
-                break;
+            if line.is_empty() {
+                return Ok(());
+            }
+
+            count += 1;
+            if count > limits.headers {
+                return Err(ParsingResult::Error(HttpCode::RequestHeaderFieldsTooLarge));
+            }
+
+            let Some((name, value)) = line.split_once(':') else {
+                return Err(ParsingResult::Error(HttpCode::BadRequest));
+            };
+
+            // Obsolete line folding and whitespace around the name let intermediaries see different headers
+            if !Self::is_token(name) {
+                return Err(ParsingResult::Error(HttpCode::BadRequest));
+            }
+
+            let name = name.to_ascii_lowercase();
+            let value = value.trim_matches([' ', '\t']);
+            match headers.get(&name) {
+                // Repeated length is allowed only as an exact copy, otherwise body boundary is ambiguous
+                Some(existing) if name == "content-length" => {
+                    if existing != value {
+                        return Err(ParsingResult::Error(HttpCode::BadRequest));
+                    }
+                }
+                // Request has one target host, which isn't a list
+                Some(_) if name == "host" => return Err(ParsingResult::Error(HttpCode::BadRequest)),
+                // Field lines with the same name are the same as one comma-separated line
+                Some(existing) => headers.set(name, format!("{existing}, {value}")),
+                None => headers.set(name, value.to_owned())
             }
         }
     }
-}
 
-impl HttpConnection for Http1Connection {
-    fn get_address (&self) -> IpAddr { self.address }
-    fn into_stream (self) -> BufStream<TcpStream> { self.stream }
+    /// Header name must be a non-empty token, so it has no whitespace or separators
+    fn is_token (name: &str) -> bool {
+        return !name.is_empty() && name.bytes().all(|ch| ch.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&ch));
+    }
 
-    fn parse (&mut self) -> ParsingResult {
-        let method = self.stream.read_string_before(' ');
-        if method.is_none() { return ParsingResult::Invalid; }
+    #[inline]
+    fn read_error (error: ReadError, too_long_code: HttpCode) -> ParsingResult {
+        return match error {
+            ReadError::Closed => ParsingResult::Invalid,
+            ReadError::TooLong => ParsingResult::Error(too_long_code),
+            ReadError::TimedOut => ParsingResult::Error(HttpCode::RequestTimeout)
+        };
+    }
 
-        let method = HttpMethod::from_str(method.unwrap().as_str());
-        if method.is_none() { return ParsingResult::Error(HttpCode::MethodNotAllowed) }
+    #[inline]
+    fn body_error (error: Error) -> ParsingResult {
+        if is_timeout(&error) {
+            return ParsingResult::Error(HttpCode::RequestTimeout);
+        } else {
+            return ParsingResult::Invalid;
+        }
+    }
 
-        let path = self.stream.read_string_before(' ');
-        if path.is_none() { return ParsingResult::Error(HttpCode::RequestEntityTooLarge); }
+    #[inline]
+    fn set_deadline (&mut self, timeout: Duration) -> Result<(), ParsingResult> {
+        return self.stream.get_mut().set_deadline(timeout).map_err(|_| ParsingResult::Invalid);
+    }
 
-        // let mut a = [0u8; 7];
-        // self.stream.read_exact(&mut a);
-        assert_stream!(self.stream, "HTTP/1.", ParsingResult::Invalid);
-        self.version_minor = self.stream.read_u8().unwrap() as char;
+    fn read_content (&mut self, req: &mut Request) -> Result<(), ParsingResult> {
+        if let Some(encoding) = req.headers.get("transfer-encoding") {
+            // `chunked` must be the final coding, other codings aren't supported
+            let mut codings = encoding.rsplit(',').map(str::trim);
+            if !codings.next().is_some_and(|last| last.eq_ignore_ascii_case("chunked")) {
+                return Err(ParsingResult::Error(HttpCode::BadRequest));
+            } else if codings.next().is_some() {
+                return Err(ParsingResult::Error(HttpCode::NotImplemented));
+            }
+
+            self.send_continue(req)?;
+            return self.read_chunked_body(req);
+        }
+
+        if req.headers.get("content-length").is_none() {
+            if req.method.expects_body() {
+                return Err(ParsingResult::Error(HttpCode::LengthRequired));
+            } else {
+                return Ok(());
+            }
+        }
+
+        match req.parse_content_length() {
+            Some(len) if len > CONFIG.limits.body => Err(ParsingResult::Error(HttpCode::RequestEntityTooLarge)),
+            Some(0) => Ok(()),
+            Some(len) => {
+                self.send_continue(req)?;
+                req.body = vec![0; len];
+                if let Err(error) = self.stream.read_exact(req.body.as_mut_slice()) {
+                    return Err(Self::body_error(error));
+                }
 
-        let mut req = Request::new(method.unwrap(), path.unwrap());
+                return Ok(());
+            }
+            None => Err(ParsingResult::Error(HttpCode::BadRequest))
+        }
+    }
+
+    /// Client that sent `Expect: 100-continue` waits for the interim response before sending the body
+    fn send_continue (&mut self, req: &Request) -> Result<(), ParsingResult> {
+        if self.version_minor == '0' || !req.headers.has_token("expect", "100-continue") {
+            return Ok(());
+        }
+
+        let result = self.stream.write_all(b"HTTP/1.1 100 Continue\r\n\r\n").and_then(|_| self.stream.flush());
+        return result.map_err(|_| ParsingResult::Invalid);
+    }
 
-        assert_stream!(self.stream, "\r", ParsingResult::Invalid);
+    fn read_chunked_body (&mut self, req: &mut Request) -> Result<(), ParsingResult> {
         loop {
-            assert_stream!(self.stream, "\n", ParsingResult::Invalid);
-            let mut header_name = Vec::new();
-            header_name.push(self.stream.read_u8().unwrap());
-            header_name.push(self.stream.read_u8().unwrap());
+            let line = match self.stream.read_string_before('\n', CONFIG.limits.header_line) {
+                Ok(line) => line,
+                Err(error) => return Err(Self::read_error(error, HttpCode::BadRequest))
+            };
 
-            if header_name[0] == '\r' as u8 && header_name[1] == '\n' as u8 {
+            // Chunk extensions (`size;name=value`) are allowed but have no meaning for us
+            let size_str = line.split(';').next().unwrap_or_default().trim();
+            if size_str.is_empty() || !size_str.bytes().all(|ch| ch.is_ascii_hexdigit()) {
+                return Err(ParsingResult::Error(HttpCode::BadRequest));
+            }
+
+            let size = match usize::from_str_radix(size_str, 16) {
+                Ok(size) => size,
+                Err(_) => return Err(ParsingResult::Error(HttpCode::RequestEntityTooLarge))
+            };
+
+            if size == 0 {
                 break;
             }
 
-            let header_read_result = self.stream.read_before(':' as u8, &mut header_name);
-            if header_read_result.is_none() { return ParsingResult::Error(HttpCode::RequestHeaderFieldsTooLarge) }
+            let offset = req.body.len();
+            if size > CONFIG.limits.body - offset {
+                return Err(ParsingResult::Error(HttpCode::RequestEntityTooLarge));
+            }
 
-            let header_value = self.stream.read_string_before('\r');
-            if header_value.is_none() { return ParsingResult::Error(HttpCode::RequestHeaderFieldsTooLarge) }
+            req.body.resize(offset + size, 0);
+            if let Err(error) = self.stream.read_exact(&mut req.body[offset..]) {
+                return Err(Self::body_error(error));
+            }
 
-            let header_name = String::from_utf8_lossy(&header_name).into_owned();
-            req.headers.set_normal(header_name, header_value.unwrap());
+            assert_stream!(self.stream, "\r\n", Err(ParsingResult::Invalid));
         }
 
-        if let HttpMethod::POST = req.method {
-            match req.parse_content_length() {
-                Some(len) => {
-                    req.body = Vec::with_capacity(len);
-                    unsafe { req.body.set_len(len); }
+        // Optional trailer section has the same format as header fields. It's only read to find the end of the message,
+        // merged into request it would replace headers that were already checked, e.g. the ones routing relied on.
+        return self.read_headers(&mut HttpHeaders::empty());
+    }
+}
 
-                    self.stream.read_exact(req.body.as_mut_slice()).unwrap();
-                }
-                None => {
-                    self.read_body(&mut req.body);
-                }
+impl<T: Transport> HttpConnection for Http1Connection<T> {
+    type Transport = T;
+
+    fn get_address (&self) -> IpAddr { self.address }
+    fn get_transport (&self) -> &T { self.stream.get_ref() }
+    fn into_stream (self) -> BufStream<T> { self.stream }
+    fn is_persistent (&self) -> bool { self.keep_alive }
+
+    #[inline]
+    fn has_pending_input (&mut self) -> bool {
+        return has_input(&mut self.stream);
+    }
+
+    fn idle_timeout (&self) -> Duration {
+        return if self.requests_count == 0 { CONFIG.timeouts.header } else { CONFIG.keep_alive.timeout };
+    }
+
+    fn parse_head (&mut self) -> ParsingResult {
+        self.keep_alive = false;
+        self.is_head = false;
+        self.body_pending = false;
+        // First request must arrive within the header timeout since accept,
+        // next ones may wait for idle timeout and then get the header timeout
+        let is_first = self.requests_count == 0;
+        if let Err(result) = self.set_deadline(self.idle_timeout()) {
+            return result;
+        }
+
+        let method = match self.stream.read_string_before(' ', Self::METHOD_LIMIT) {
+            Ok(method) => method,
+            // Nothing to answer to idle client, just close the connection
+            Err(ReadError::Closed | ReadError::TimedOut) => return ParsingResult::Invalid,
+            Err(ReadError::TooLong) => return ParsingResult::Error(HttpCode::NotImplemented)
+        };
+
+        if !is_first {
+            if let Err(result) = self.set_deadline(CONFIG.timeouts.header) {
+                return result;
             }
         }
 
+        // HTTP/2 with prior knowledge starts with `PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n`
+        if method == "PRI" && is_first && CONFIG.http2.enabled {
+            assert_stream!(self.stream, "* HTTP/2.0\r\n\r\nSM\r\n\r\n", ParsingResult::Invalid);
+            return ParsingResult::Http2;
+        }
+
+        let method = match HttpMethod::from_str(method.as_str()) {
+            Some(method) => method,
+            None => return ParsingResult::Error(HttpCode::NotImplemented)
+        };
+
+        let path = match self.stream.read_string_before(' ', CONFIG.limits.uri + 1) {
+            Ok(path) => path,
+            Err(error) => return Self::read_error(error, HttpCode::URITooLong)
+        };
+
+        assert_stream!(self.stream, "HTTP/1.", ParsingResult::Invalid);
+        self.version_minor = match self.stream.read_u8() {
+            Ok(version @ (b'0' | b'1')) => version as char,
+            Ok(_) => return ParsingResult::Error(HttpCode::HTTPVersionNotSupported),
+            Err(_) => return ParsingResult::Invalid
+        };
+
+        let mut req = Request::new(method, path);
+
+        assert_stream!(self.stream, "\r\n", ParsingResult::Invalid);
+        if let Err(result) = self.read_headers(&mut req.headers) {
+            return result;
+        }
+
+        // Message with both headers is a request smuggling attempt, intermediaries may disagree where its body ends
+        if req.headers.get("transfer-encoding").is_some() && req.headers.get("content-length").is_some() {
+            return ParsingResult::Error(HttpCode::BadRequest);
+        }
+
+        self.update_keep_alive(&req);
+
+        self.is_head = req.method == HttpMethod::HEAD;
+        self.body_pending = req.headers.get("transfer-encoding").is_some()
+            || req.headers.get("content-length").is_some_and(|len| len != "0");
+
+        // Request may be checked before its body is read, which takes any time
+        if let Err(result) = self.set_deadline(Duration::ZERO) {
+            return result;
+        }
+
         return ParsingResult::Complete(req);
     }
 
-    fn respond (&mut self, res: Response) -> Result<(), Error> {
+    fn read_body (&mut self, req: &mut Request) -> Result<(), ParsingResult> {
+        self.set_deadline(CONFIG.timeouts.body)?;
+        self.read_content(req)?;
+        self.body_pending = false;
+
+        // Handler may take any time, so there is nothing to limit until the next request
+        return self.set_deadline(Duration::ZERO);
+    }
+
+    fn read_multipart (&mut self, req: &Request, boundary: &str, sink: &mut PartSink) -> Option<Result<Multipart, MultipartError>> {
+        // Chunked body is read into memory, as well as compressed one which is inflated as a whole
+        if req.headers.get("transfer-encoding").is_some() || req.headers.get("content-encoding").is_some() {
+            return None;
+        }
+
+        let len = req.parse_content_length()? as u64;
+        if len > CONFIG.uploads.total_size {
+            return Some(Err(MultipartError::TooLarge));
+        }
+
+        if let Err(error) = self.stream.get_mut().set_deadline(CONFIG.timeouts.body) {
+            return Some(Err(MultipartError::Read(error)));
+        }
+
+        if self.send_continue(req).is_err() {
+            return Some(Err(MultipartError::Read(ErrorKind::BrokenPipe.into())));
+        }
+
+        let mut body = (&mut self.stream).take(len);
+        let result = Multipart::read(&mut body, boundary, &CONFIG.uploads, sink);
+        // Parser reads up to the end of body unless it fails, the rest of failed one is left unread
+        self.body_pending = body.limit() > 0;
+
+        let _ = self.stream.get_mut().set_deadline(Duration::ZERO);
+        return Some(result);
+    }
+
+    fn respond (&mut self, mut res: Response) -> Result<(), Error> {
         if let ResponseType::Drop = res.payload {
+            self.keep_alive = false;
             return Ok(());
         }
 
-        self.stream.write(b"HTTP/1.")?;
+        // Unread body would be parsed as the next request
+        if self.body_pending {
+            self.keep_alive = false;
+        }
+
+        self.prepare_headers(&mut res);
+
+        self.stream.write_all(b"HTTP/1.")?;
         self.stream.write_u8(self.version_minor as u8)?;
-        self.stream.write_u8(' ' as u8)?;
+        self.stream.write_u8(b' ')?;
         let (res_code, res_reason) = res.code.get_description();
 
-        self.stream.write(res_code.as_bytes())?;
-        self.stream.write_u8(' ' as u8)?;
-        self.stream.write(res_reason.as_bytes())?;
+        self.stream.write_all(res_code.as_bytes())?;
+        self.stream.write_u8(b' ')?;
+        self.stream.write_all(res_reason.as_bytes())?;
 
         for header in &res.headers {
-            self.stream.write(b"\r\n")?;
-            self.stream.write(header.name.as_bytes())?;
-            self.stream.write(b": ")?;
-            self.stream.write(header.value.as_bytes())?;
+            self.stream.write_all(b"\r\n")?;
+            self.stream.write_all(header.name.as_bytes())?;
+            self.stream.write_all(b": ")?;
+            self.stream.write_all(header.value.as_bytes())?;
+        }
+
+        self.stream.write_all(b"\r\n\r\n")?;
+        if self.is_head {
+            return self.stream.flush();
         }
 
-        self.stream.write(b"\r\n\r\n")?;
-        if let ResponseType::Payload(payload) = res.payload {
-            self.stream.write(&payload)?;
+        match res.payload {
+            ResponseType::Payload(payload) => self.stream.write_all(&payload)?,
+            ResponseType::Stream(stream) => return self.write_stream(stream),
+            _ => {}
         }
 
-        return Ok(());
+        return self.stream.flush();
     }
 
-    fn disconnect (self) -> Result<(), Error> {
-        let stream = self.stream.into_inner()?;
-        return stream.shutdown(Shutdown::Both);
+    fn disconnect (self) -> Result<T, Error> {
+        let mut transport = self.stream.into_inner()?;
+        transport.shutdown_write()?;
+        return Ok(transport);
     }
 }
@@ -0,0 +1,135 @@
+use std::io::{self, Read, Write};
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameKind {
+    Data = 0x0,
+    Headers = 0x1,
+    Priority = 0x2,
+    RstStream = 0x3,
+    Settings = 0x4,
+    PushPromise = 0x5,
+    Ping = 0x6,
+    GoAway = 0x7,
+    WindowUpdate = 0x8,
+    Continuation = 0x9
+}
+
+impl FrameKind {
+    /// Unknown frame types must be ignored, so they are not an error
+    pub fn from_u8 (kind: u8) -> Option<FrameKind> {
+        match kind {
+            0x0 => Some(FrameKind::Data),
+            0x1 => Some(FrameKind::Headers),
+            0x2 => Some(FrameKind::Priority),
+            0x3 => Some(FrameKind::RstStream),
+            0x4 => Some(FrameKind::Settings),
+            0x5 => Some(FrameKind::PushPromise),
+            0x6 => Some(FrameKind::Ping),
+            0x7 => Some(FrameKind::GoAway),
+            0x8 => Some(FrameKind::WindowUpdate),
+            0x9 => Some(FrameKind::Continuation),
+            _ => None
+        }
+    }
+}
+
+pub const FLAG_END_STREAM: u8 = 0x1;
+pub const FLAG_ACK: u8 = 0x1;
+pub const FLAG_END_HEADERS: u8 = 0x4;
+pub const FLAG_PADDED: u8 = 0x8;
+pub const FLAG_PRIORITY: u8 = 0x20;
+
+pub const SETTINGS_HEADER_TABLE_SIZE: u16 = 0x1;
+pub const SETTINGS_ENABLE_PUSH: u16 = 0x2;
+pub const SETTINGS_MAX_CONCURRENT_STREAMS: u16 = 0x3;
+pub const SETTINGS_INITIAL_WINDOW_SIZE: u16 = 0x4;
+pub const SETTINGS_MAX_FRAME_SIZE: u16 = 0x5;
+pub const SETTINGS_MAX_HEADER_LIST_SIZE: u16 = 0x6;
+
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    NoError = 0x0,
+    ProtocolError = 0x1,
+    InternalError = 0x2,
+    FlowControlError = 0x3,
+    StreamClosed = 0x5,
+    FrameSizeError = 0x6,
+    RefusedStream = 0x7,
+    Cancel = 0x8,
+    CompressionError = 0x9,
+    EnhanceYourCalm = 0xb
+}
+
+pub enum FrameError {
+    Io(io::Error),
+    /// Frame is larger than `SETTINGS_MAX_FRAME_SIZE` we announced
+    TooLarge
+}
+
+pub struct Frame {
+    /// Raw type, unknown ones are kept to be skipped by the caller
+    pub kind: u8,
+    pub flags: u8,
+    pub stream_id: u32,
+    pub payload: Vec<u8>
+}
+
+impl Frame {
+    pub const HEADER_SIZE: usize = 9;
+    /// Default and minimal `SETTINGS_MAX_FRAME_SIZE`
+    pub const DEFAULT_MAX_SIZE: usize = 16384;
+
+    pub fn read<R: Read> (stream: &mut R, max_size: usize) -> Result<Frame, FrameError> {
+        let mut header = [0u8; Frame::HEADER_SIZE];
+        stream.read_exact(&mut header).map_err(FrameError::Io)?;
+
+        let len = (header[0] as usize) << 16 | (header[1] as usize) << 8 | header[2] as usize;
+        if len > max_size {
+            return Err(FrameError::TooLarge);
+        }
+
+        let mut payload = vec![0; len];
+        stream.read_exact(&mut payload).map_err(FrameError::Io)?;
+
+        return Ok(Frame {
+            kind: header[3],
+            flags: header[4],
+            // Reserved bit must be ignored
+            stream_id: u32::from_be_bytes([header[5], header[6], header[7], header[8]]) & 0x7fffffff,
+            payload
+        });
+    }
+
+    pub fn write<W: Write> (stream: &mut W, kind: FrameKind, flags: u8, stream_id: u32, payload: &[u8]) -> io::Result<()> {
+        let len = payload.len() as u32;
+        stream.write_all(&len.to_be_bytes()[1..])?;
+        stream.write_u8(kind as u8)?;
+        stream.write_u8(flags)?;
+        stream.write_u32::<BigEndian>(stream_id)?;
+        return stream.write_all(payload);
+    }
+
+    #[inline]
+    pub fn has_flag (&self, flag: u8) -> bool {
+        return self.flags & flag != 0;
+    }
+
+    /// Payload of `DATA` or `HEADERS` frame without padding, `None` if padding is malformed
+    pub fn unpadded (&self) -> Option<&[u8]> {
+        if !self.has_flag(FLAG_PADDED) {
+            return Some(&self.payload);
+        }
+
+        let pad_len = *self.payload.first()? as usize;
+        return self.payload.len().checked_sub(pad_len + 1).map(|end| &self.payload[1..(end + 1)]);
+    }
+
+    /// Reads big-endian `u32` at `offset` of the payload
+    pub fn read_u32 (&self, offset: usize) -> Option<u32> {
+        let mut bytes = self.payload.get(offset..)?;
+        return bytes.read_u32::<BigEndian>().ok();
+    }
+}
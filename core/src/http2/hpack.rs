@@ -0,0 +1,356 @@
+use std::collections::VecDeque;
+use std::sync::LazyLock;
+
+/// Header compression error, always fatal for the connection because decoder state is lost
+#[derive(Debug)]
+pub struct HpackError;
+
+type HeaderField = (String, String);
+
+const STATIC_TABLE: [(&str, &str); 61] = [
+    (":authority", ""), (":method", "GET"), (":method", "POST"), (":path", "/"),
+    (":path", "/index.html"), (":scheme", "http"), (":scheme", "https"), (":status", "200"),
+    (":status", "204"), (":status", "206"), (":status", "304"), (":status", "400"),
+    (":status", "404"), (":status", "500"), ("accept-charset", ""), ("accept-encoding", "gzip, deflate"),
+    ("accept-language", ""), ("accept-ranges", ""), ("accept", ""), ("access-control-allow-origin", ""),
+    ("age", ""), ("allow", ""), ("authorization", ""), ("cache-control", ""),
+    ("content-disposition", ""), ("content-encoding", ""), ("content-language", ""), ("content-length", ""),
+    ("content-location", ""), ("content-range", ""), ("content-type", ""), ("cookie", ""),
+    ("date", ""), ("etag", ""), ("expect", ""), ("expires", ""),
+    ("from", ""), ("host", ""), ("if-match", ""), ("if-modified-since", ""),
+    ("if-none-match", ""), ("if-range", ""), ("if-unmodified-since", ""), ("last-modified", ""),
+    ("link", ""), ("location", ""), ("max-forwards", ""), ("proxy-authenticate", ""),
+    ("proxy-authorization", ""), ("range", ""), ("referer", ""), ("refresh", ""),
+    ("retry-after", ""), ("server", ""), ("set-cookie", ""), ("strict-transport-security", ""),
+    ("transfer-encoding", ""), ("user-agent", ""), ("vary", ""), ("via", ""),
+    ("www-authenticate", "")
+];
+
+/// Codes and bit lengths of each byte and EOS symbol, RFC 7541 Appendix B
+const HUFFMAN_CODES: [(u32, u8); 257] = [
+    (0x1ff8, 13), (0x7fffd8, 23), (0xfffffe2, 28), (0xfffffe3, 28),
+    (0xfffffe4, 28), (0xfffffe5, 28), (0xfffffe6, 28), (0xfffffe7, 28),
+    (0xfffffe8, 28), (0xffffea, 24), (0x3ffffffc, 30), (0xfffffe9, 28),
+    (0xfffffea, 28), (0x3ffffffd, 30), (0xfffffeb, 28), (0xfffffec, 28),
+    (0xfffffed, 28), (0xfffffee, 28), (0xfffffef, 28), (0xffffff0, 28),
+    (0xffffff1, 28), (0xffffff2, 28), (0x3ffffffe, 30), (0xffffff3, 28),
+    (0xffffff4, 28), (0xffffff5, 28), (0xffffff6, 28), (0xffffff7, 28),
+    (0xffffff8, 28), (0xffffff9, 28), (0xffffffa, 28), (0xffffffb, 28),
+    (0x14, 6), (0x3f8, 10), (0x3f9, 10), (0xffa, 12),
+    (0x1ff9, 13), (0x15, 6), (0xf8, 8), (0x7fa, 11),
+    (0x3fa, 10), (0x3fb, 10), (0xf9, 8), (0x7fb, 11),
+    (0xfa, 8), (0x16, 6), (0x17, 6), (0x18, 6),
+    (0x0, 5), (0x1, 5), (0x2, 5), (0x19, 6),
+    (0x1a, 6), (0x1b, 6), (0x1c, 6), (0x1d, 6),
+    (0x1e, 6), (0x1f, 6), (0x5c, 7), (0xfb, 8),
+    (0x7ffc, 15), (0x20, 6), (0xffb, 12), (0x3fc, 10),
+    (0x1ffa, 13), (0x21, 6), (0x5d, 7), (0x5e, 7),
+    (0x5f, 7), (0x60, 7), (0x61, 7), (0x62, 7),
+    (0x63, 7), (0x64, 7), (0x65, 7), (0x66, 7),
+    (0x67, 7), (0x68, 7), (0x69, 7), (0x6a, 7),
+    (0x6b, 7), (0x6c, 7), (0x6d, 7), (0x6e, 7),
+    (0x6f, 7), (0x70, 7), (0x71, 7), (0x72, 7),
+    (0xfc, 8), (0x73, 7), (0xfd, 8), (0x1ffb, 13),
+    (0x7fff0, 19), (0x1ffc, 13), (0x3ffc, 14), (0x22, 6),
+    (0x7ffd, 15), (0x3, 5), (0x23, 6), (0x4, 5),
+    (0x24, 6), (0x5, 5), (0x25, 6), (0x26, 6),
+    (0x27, 6), (0x6, 5), (0x74, 7), (0x75, 7),
+    (0x28, 6), (0x29, 6), (0x2a, 6), (0x7, 5),
+    (0x2b, 6), (0x76, 7), (0x2c, 6), (0x8, 5),
+    (0x9, 5), (0x2d, 6), (0x77, 7), (0x78, 7),
+    (0x79, 7), (0x7a, 7), (0x7b, 7), (0x7ffe, 15),
+    (0x7fc, 11), (0x3ffd, 14), (0x1ffd, 13), (0xffffffc, 28),
+    (0xfffe6, 20), (0x3fffd2, 22), (0xfffe7, 20), (0xfffe8, 20),
+    (0x3fffd3, 22), (0x3fffd4, 22), (0x3fffd5, 22), (0x7fffd9, 23),
+    (0x3fffd6, 22), (0x7fffda, 23), (0x7fffdb, 23), (0x7fffdc, 23),
+    (0x7fffdd, 23), (0x7fffde, 23), (0xffffeb, 24), (0x7fffdf, 23),
+    (0xffffec, 24), (0xffffed, 24), (0x3fffd7, 22), (0x7fffe0, 23),
+    (0xffffee, 24), (0x7fffe1, 23), (0x7fffe2, 23), (0x7fffe3, 23),
+    (0x7fffe4, 23), (0x1fffdc, 21), (0x3fffd8, 22), (0x7fffe5, 23),
+    (0x3fffd9, 22), (0x7fffe6, 23), (0x7fffe7, 23), (0xffffef, 24),
+    (0x3fffda, 22), (0x1fffdd, 21), (0xfffe9, 20), (0x3fffdb, 22),
+    (0x3fffdc, 22), (0x7fffe8, 23), (0x7fffe9, 23), (0x1fffde, 21),
+    (0x7fffea, 23), (0x3fffdd, 22), (0x3fffde, 22), (0xfffff0, 24),
+    (0x1fffdf, 21), (0x3fffdf, 22), (0x7fffeb, 23), (0x7fffec, 23),
+    (0x1fffe0, 21), (0x1fffe1, 21), (0x3fffe0, 22), (0x1fffe2, 21),
+    (0x7fffed, 23), (0x3fffe1, 22), (0x7fffee, 23), (0x7fffef, 23),
+    (0xfffea, 20), (0x3fffe2, 22), (0x3fffe3, 22), (0x3fffe4, 22),
+    (0x7ffff0, 23), (0x3fffe5, 22), (0x3fffe6, 22), (0x7ffff1, 23),
+    (0x3ffffe0, 26), (0x3ffffe1, 26), (0xfffeb, 20), (0x7fff1, 19),
+    (0x3fffe7, 22), (0x7ffff2, 23), (0x3fffe8, 22), (0x1ffffec, 25),
+    (0x3ffffe2, 26), (0x3ffffe3, 26), (0x3ffffe4, 26), (0x7ffffde, 27),
+    (0x7ffffdf, 27), (0x3ffffe5, 26), (0xfffff1, 24), (0x1ffffed, 25),
+    (0x7fff2, 19), (0x1fffe3, 21), (0x3ffffe6, 26), (0x7ffffe0, 27),
+    (0x7ffffe1, 27), (0x3ffffe7, 26), (0x7ffffe2, 27), (0xfffff2, 24),
+    (0x1fffe4, 21), (0x1fffe5, 21), (0x3ffffe8, 26), (0x3ffffe9, 26),
+    (0xffffffd, 28), (0x7ffffe3, 27), (0x7ffffe4, 27), (0x7ffffe5, 27),
+    (0xfffec, 20), (0xfffff3, 24), (0xfffed, 20), (0x1fffe6, 21),
+    (0x3fffe9, 22), (0x1fffe7, 21), (0x1fffe8, 21), (0x7ffff3, 23),
+    (0x3fffea, 22), (0x3fffeb, 22), (0x1ffffee, 25), (0x1ffffef, 25),
+    (0xfffff4, 24), (0xfffff5, 24), (0x3ffffea, 26), (0x7ffff4, 23),
+    (0x3ffffeb, 26), (0x7ffffe6, 27), (0x3ffffec, 26), (0x3ffffed, 26),
+    (0x7ffffe7, 27), (0x7ffffe8, 27), (0x7ffffe9, 27), (0x7ffffea, 27),
+    (0x7ffffeb, 27), (0xffffffe, 28), (0x7ffffec, 27), (0x7ffffed, 27),
+    (0x7ffffee, 27), (0x7ffffef, 27), (0x7fffff0, 27), (0x3ffffee, 26),
+    (0x3fffffff, 30),
+];
+
+/// Canonical Huffman code lookup: codes of the same length are sequential,
+/// so symbol is found by offset of the code from the first code of its length
+struct HuffmanTable {
+    first_code: [u32; 31],
+    count: [u32; 31],
+    offset: [usize; 31],
+    symbols: Vec<u16>
+}
+
+static HUFFMAN_TABLE: LazyLock<HuffmanTable> = LazyLock::new(|| {
+    let mut symbols: Vec<u16> = (0..HUFFMAN_CODES.len() as u16).collect();
+    symbols.sort_by_key(|&sym| {
+        let (code, len) = HUFFMAN_CODES[sym as usize];
+        return (len, code);
+    });
+
+    let mut table = HuffmanTable { first_code: [0; 31], count: [0; 31], offset: [0; 31], symbols };
+    for (i, &sym) in table.symbols.iter().enumerate() {
+        let (code, len) = HUFFMAN_CODES[sym as usize];
+        let len = len as usize;
+        if table.count[len] == 0 {
+            table.first_code[len] = code;
+            table.offset[len] = i;
+        }
+
+        table.count[len] += 1;
+    }
+
+    return table;
+});
+
+fn huffman_decode (input: &[u8]) -> Result<Vec<u8>, HpackError> {
+    let table = &*HUFFMAN_TABLE;
+    let mut output = Vec::with_capacity(input.len() * 8 / 5);
+    let mut code = 0u32;
+    let mut len = 0usize;
+
+    for byte in input {
+        for shift in (0..8).rev() {
+            code = (code << 1) | ((byte >> shift) & 1) as u32;
+            len += 1;
+            if len > 30 { return Err(HpackError) }
+
+            let index = code.wrapping_sub(table.first_code[len]);
+            if table.count[len] != 0 && code >= table.first_code[len] && index < table.count[len] {
+                let sym = table.symbols[table.offset[len] + index as usize];
+                // EOS must never appear in the string itself
+                if sym == 256 { return Err(HpackError) }
+
+                output.push(sym as u8);
+                code = 0;
+                len = 0;
+            }
+        }
+    }
+
+    // Padding is the most significant bits of EOS (all ones) and is shorter than a byte
+    if len > 7 || code != (1 << len) - 1 {
+        return Err(HpackError);
+    }
+
+    return Ok(output);
+}
+
+/// Reads HPACK integer with `prefix` bits in the first byte
+fn decode_integer (input: &[u8], pos: &mut usize, prefix: u8) -> Result<usize, HpackError> {
+    let max_prefix = (1usize << prefix) - 1;
+    let first = *input.get(*pos).ok_or(HpackError)? as usize & max_prefix;
+    *pos += 1;
+    if first < max_prefix {
+        return Ok(first);
+    }
+
+    let mut value = max_prefix;
+    let mut shift = 0u32;
+    loop {
+        let byte = *input.get(*pos).ok_or(HpackError)?;
+        *pos += 1;
+
+        // Anything above 32 bits is surely malicious, header block size is limited anyway
+        if shift > 28 { return Err(HpackError) }
+        value += ((byte & 0x7f) as usize) << shift;
+        shift += 7;
+
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+}
+
+fn decode_string (input: &[u8], pos: &mut usize) -> Result<String, HpackError> {
+    let is_huffman = *input.get(*pos).ok_or(HpackError)? & 0x80 != 0;
+    let len = decode_integer(input, pos, 7)?;
+    let end = pos.checked_add(len).filter(|&end| end <= input.len()).ok_or(HpackError)?;
+    let raw = &input[*pos..end];
+    *pos = end;
+
+    let bytes = if is_huffman { huffman_decode(raw)? } else { raw.to_vec() };
+    return String::from_utf8(bytes).map_err(|_| HpackError);
+}
+
+fn encode_integer (output: &mut Vec<u8>, flags: u8, prefix: u8, mut value: usize) {
+    let max_prefix = (1usize << prefix) - 1;
+    if value < max_prefix {
+        output.push(flags | value as u8);
+        return;
+    }
+
+    output.push(flags | max_prefix as u8);
+    value -= max_prefix;
+    while value >= 0x80 {
+        output.push((value & 0x7f) as u8 | 0x80);
+        value >>= 7;
+    }
+
+    output.push(value as u8);
+}
+
+fn encode_string (output: &mut Vec<u8>, value: &str) {
+    encode_integer(output, 0, 7, value.len());
+    output.extend_from_slice(value.as_bytes());
+}
+
+pub struct Decoder {
+    dynamic_table: VecDeque<HeaderField>,
+    table_size: usize,
+    max_table_size: usize,
+    /// Limit of decoded header list size, small block referencing large table entries can expand a lot
+    max_list_size: usize
+}
+
+impl Default for Decoder {
+    fn default () -> Self {
+        return Decoder::new();
+    }
+}
+
+impl Decoder {
+    /// Default `SETTINGS_HEADER_TABLE_SIZE`, we never announce another one
+    const MAX_TABLE_SIZE: usize = 4096;
+
+    pub fn new () -> Self {
+        Decoder {
+            dynamic_table: VecDeque::new(),
+            table_size: 0,
+            max_table_size: Decoder::MAX_TABLE_SIZE,
+            max_list_size: usize::MAX
+        }
+    }
+
+    /// Decoder failing blocks whose header list is larger than `max_list_size`, as `SETTINGS_MAX_HEADER_LIST_SIZE` counts it
+    pub fn with_list_limit (max_list_size: usize) -> Self {
+        Decoder { max_list_size, ..Decoder::new() }
+    }
+
+    fn get (&self, index: usize) -> Result<HeaderField, HpackError> {
+        if index == 0 {
+            return Err(HpackError);
+        } else if index <= STATIC_TABLE.len() {
+            let (name, value) = STATIC_TABLE[index - 1];
+            return Ok((name.to_owned(), value.to_owned()));
+        } else {
+            return self.dynamic_table.get(index - STATIC_TABLE.len() - 1).cloned().ok_or(HpackError);
+        }
+    }
+
+    fn insert (&mut self, field: HeaderField) {
+        // Every entry has 32 bytes of overhead
+        let size = field.0.len() + field.1.len() + 32;
+        self.table_size += size;
+        self.dynamic_table.push_front(field);
+        // Entry larger than the table evicts everything including itself
+        self.evict();
+    }
+
+    fn evict (&mut self) {
+        while self.table_size > self.max_table_size {
+            match self.dynamic_table.pop_back() {
+                Some((name, value)) => self.table_size -= name.len() + value.len() + 32,
+                None => break
+            }
+        }
+    }
+
+    fn add_field (&self, fields: &mut Vec<HeaderField>, list_size: &mut usize, field: HeaderField) -> Result<(), HpackError> {
+        // Like table entries, every field has 32 bytes of overhead
+        *list_size += field.0.len() + field.1.len() + 32;
+        if *list_size > self.max_list_size {
+            return Err(HpackError);
+        }
+
+        fields.push(field);
+        return Ok(());
+    }
+
+    /// Decodes complete header block, every block must be decoded to keep the table in sync with the peer
+    pub fn decode (&mut self, block: &[u8]) -> Result<Vec<HeaderField>, HpackError> {
+        let mut fields = Vec::new();
+        let mut list_size = 0;
+        let mut pos = 0;
+
+        while pos < block.len() {
+            let first = block[pos];
+            if first & 0x80 != 0 {
+                // Indexed header field
+                let index = decode_integer(block, &mut pos, 7)?;
+                let field = self.get(index)?;
+                self.add_field(&mut fields, &mut list_size, field)?;
+            } else if first & 0xe0 == 0x20 {
+                // Dynamic table size update
+                let size = decode_integer(block, &mut pos, 5)?;
+                if size > Decoder::MAX_TABLE_SIZE { return Err(HpackError) }
+                self.max_table_size = size;
+                self.evict();
+            } else {
+                // Literal, with incremental indexing (01), without indexing (0000) or never indexed (0001)
+                let is_indexed = first & 0xc0 == 0x40;
+                let prefix = if is_indexed { 6 } else { 4 };
+                let name_index = decode_integer(block, &mut pos, prefix)?;
+                let name = if name_index == 0 { decode_string(block, &mut pos)? } else { self.get(name_index)?.0 };
+                let value = decode_string(block, &mut pos)?;
+
+                if is_indexed {
+                    self.insert((name.clone(), value.clone()));
+                }
+
+                self.add_field(&mut fields, &mut list_size, (name, value))?;
+            }
+        }
+
+        return Ok(fields);
+    }
+}
+
+/// Stateless encoder, which never touches the dynamic table, so peer settings of its size don't matter
+pub struct Encoder;
+
+impl Encoder {
+    /// Field names are lowercased, as HTTP/2 requires, because handlers may set them in any case
+    pub fn encode<'a, I: IntoIterator<Item = (&'a str, &'a str)>> (fields: I) -> Vec<u8> {
+        let mut block = Vec::new();
+        for (name, value) in fields {
+            let name = name.to_ascii_lowercase();
+            let name_index = STATIC_TABLE.iter().position(|(static_name, _)| *static_name == name);
+            // Literal header field without indexing
+            match name_index {
+                Some(index) => encode_integer(&mut block, 0, 4, index + 1),
+                None => {
+                    block.push(0);
+                    encode_string(&mut block, &name);
+                }
+            }
+
+            encode_string(&mut block, value);
+        }
+
+        return block;
+    }
+}
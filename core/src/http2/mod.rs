@@ -0,0 +1,889 @@
+pub mod frame;
+pub mod hpack;
+
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, Error, Read, Write};
+use std::mem;
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, SystemTime};
+use bufstream::BufStream;
+use httpdate::fmt_http_date;
+use crate::app::config::CONFIG;
+use crate::http::codes::HttpCode;
+use crate::http::entity::{HttpConnection, HttpEngine, HttpMethod, ParsingResult, Request, Response, ResponseType};
+use crate::utils::socket::{has_input, Socket, Transport};
+use frame::*;
+use hpack::{Decoder, Encoder};
+
+#[derive(Copy, Clone)]
+pub struct Http2Engine;
+
+impl HttpEngine<Http2Connection> for Http2Engine {
+    fn handle_connection (socket: (Socket, SocketAddr)) -> Http2Connection {
+        let socket_stream = socket.0;
+        let _ = socket_stream.set_write_timeout(CONFIG.timeouts.write);
+
+        return Http2Connection::from_stream(BufStream::new(socket_stream), socket.1.ip(), true);
+    }
+}
+
+/// Stream opened by the client, it lives until the response is sent or it gets reset
+struct StreamState {
+    /// Taken out when request is given to the handler
+    req: Option<Request>,
+    body: Vec<u8>,
+    /// Client won't send anything more on this stream
+    end_stream: bool,
+    /// Flow control window for the response
+    send_window: i64,
+    /// Flow control window for the request body, client may send only that much before we give it back
+    recv_window: i64,
+    /// Received body bytes, which aren't given back to client windows yet
+    held: u32,
+    /// Handler reads the body, so received data is given back right away
+    is_read: bool,
+    is_head: bool
+}
+
+/// Header block split into `HEADERS` and following `CONTINUATION` frames
+struct PendingHeaders {
+    stream_id: u32,
+    block: Vec<u8>,
+    end_stream: bool
+}
+
+/// Connection is closed, `GOAWAY` is already sent if it was needed
+struct Closed;
+
+pub struct Http2Connection<T: Transport = Socket> {
+    stream: BufStream<T>,
+    address: IpAddr,
+    /// Client preface must be read before the first frame
+    preface_pending: bool,
+    settings_sent: bool,
+    closed: bool,
+    decoder: Decoder,
+    streams: HashMap<u32, StreamState>,
+    /// Streams, which requests can be given to the handler, in the order of arrival
+    ready: VecDeque<u32>,
+    /// Stream being answered. Streams are received concurrently, but their requests are handled
+    /// one at a time, so the others wait with bodies limited by their flow control windows.
+    current: Option<u32>,
+    pending_headers: Option<PendingHeaders>,
+    last_stream_id: u32,
+    /// Client sent `GOAWAY`, so no new streams will come
+    goaway_received: bool,
+    send_window: i64,
+    peer_initial_window: i64,
+    peer_max_frame_size: usize
+}
+
+impl<T: Transport> Http2Connection<T> {
+    const PREFACE: &'static [u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+    const DEFAULT_WINDOW: i64 = 65535;
+    const MAX_WINDOW: i64 = 0x7fffffff;
+
+    /// `preface_pending` is false when HTTP/1 parser has already consumed the client preface
+    pub fn from_stream (stream: BufStream<T>, address: IpAddr, preface_pending: bool) -> Self {
+        Http2Connection {
+            stream,
+            address,
+            preface_pending,
+            settings_sent: false,
+            closed: false,
+            decoder: Decoder::with_list_limit(Self::max_header_list_size()),
+            streams: HashMap::new(),
+            ready: VecDeque::new(),
+            current: None,
+            pending_headers: None,
+            last_stream_id: 0,
+            goaway_received: false,
+            send_window: Self::DEFAULT_WINDOW,
+            peer_initial_window: Self::DEFAULT_WINDOW,
+            peer_max_frame_size: Frame::DEFAULT_MAX_SIZE
+        }
+    }
+
+    /// Continues connection upgraded with `Upgrade: h2c`, the upgrade request becomes stream 1
+    /// and `HTTP2-Settings` payload is applied as client settings
+    pub fn from_upgrade (stream: BufStream<T>, address: IpAddr, mut req: Request, settings: &[u8]) -> Self {
+        let mut connection = Http2Connection::from_stream(stream, address, true);
+        let _ = connection.apply_settings(settings);
+
+        // Upgrade is done, HTTP/2 request must look like it came in a regular stream
+        for name in ["connection", "upgrade", "http2-settings"] {
+            req.headers.remove(name.to_owned());
+        }
+
+        // Body was read by HTTP/1 parser, but it's taken from the stream like the others
+        let body = mem::take(&mut req.body);
+        connection.last_stream_id = 1;
+        connection.open_stream(1, req, true);
+        if let Some(state) = connection.streams.get_mut(&1) {
+            state.body = body;
+        }
+
+        connection.ready.push_back(1);
+        return connection;
+    }
+
+    /// Header block can't be skipped because of compression state, so its size is limited by the connection
+    fn max_header_list_size () -> usize {
+        return CONFIG.limits.header_line * CONFIG.limits.headers;
+    }
+
+    fn open_stream (&mut self, id: u32, req: Request, end_stream: bool) {
+        let is_head = req.method == HttpMethod::HEAD;
+        self.streams.insert(id, StreamState {
+            req: Some(req),
+            body: Vec::new(),
+            end_stream,
+            send_window: self.peer_initial_window,
+            recv_window: Self::DEFAULT_WINDOW,
+            held: 0,
+            is_read: false,
+            is_head
+        });
+    }
+
+    fn set_deadline (&mut self, timeout: Duration) -> Result<(), Closed> {
+        return self.stream.get_mut().set_deadline(timeout).map_err(|_| self.close());
+    }
+
+    fn close (&mut self) -> Closed {
+        self.closed = true;
+        return Closed;
+    }
+
+    /// Sends `GOAWAY` and closes the connection
+    fn fail (&mut self, code: ErrorCode) -> Closed {
+        if !self.closed {
+            let mut payload = self.last_stream_id.to_be_bytes().to_vec();
+            payload.extend((code as u32).to_be_bytes());
+            let _ = Frame::write(&mut self.stream, FrameKind::GoAway, 0, 0, &payload);
+            let _ = self.stream.flush();
+        }
+
+        return self.close();
+    }
+
+    fn write_frame (&mut self, kind: FrameKind, flags: u8, stream_id: u32, payload: &[u8]) -> Result<(), Closed> {
+        return Frame::write(&mut self.stream, kind, flags, stream_id, payload).map_err(|_| self.close());
+    }
+
+    fn reset_stream (&mut self, stream_id: u32, code: ErrorCode) -> Result<(), Closed> {
+        self.remove_stream(stream_id)?;
+        return self.write_frame(FrameKind::RstStream, 0, stream_id, &(code as u32).to_be_bytes());
+    }
+
+    /// Forgets the stream, data it holds is dropped, so its share of the connection window is given back
+    fn remove_stream (&mut self, stream_id: u32) -> Result<(), Closed> {
+        return match self.streams.remove(&stream_id) {
+            Some(state) => self.return_window(0, state.held),
+            None => Ok(())
+        };
+    }
+
+    /// Gives received bytes back to the connection window and, when it's not 0, to the window of the stream
+    fn return_window (&mut self, stream_id: u32, size: u32) -> Result<(), Closed> {
+        if size == 0 {
+            return Ok(());
+        }
+
+        self.write_frame(FrameKind::WindowUpdate, 0, 0, &size.to_be_bytes())?;
+        if stream_id != 0 {
+            self.write_frame(FrameKind::WindowUpdate, 0, stream_id, &size.to_be_bytes())?;
+        }
+
+        return Ok(());
+    }
+
+    /// Gives back the data which handler has taken, the stream window only while more data may come
+    fn release_held (&mut self, stream_id: u32) -> Result<(), Closed> {
+        let Some(state) = self.streams.get_mut(&stream_id) else {
+            return Ok(());
+        };
+
+        let held = mem::take(&mut state.held);
+        if state.end_stream {
+            return self.return_window(0, held);
+        }
+
+        state.recv_window += held as i64;
+        return self.return_window(stream_id, held);
+    }
+
+    fn start (&mut self) -> Result<(), Closed> {
+        if self.preface_pending {
+            self.set_deadline(CONFIG.timeouts.header)?;
+
+            let mut preface = [0u8; 24];
+            if self.stream.read_exact(&mut preface).is_err() || preface != Self::PREFACE {
+                return Err(self.close());
+            }
+
+            self.preface_pending = false;
+        }
+
+        if !self.settings_sent {
+            let mut payload = Vec::new();
+            payload.extend(SETTINGS_MAX_CONCURRENT_STREAMS.to_be_bytes());
+            payload.extend(CONFIG.http2.max_concurrent_streams.to_be_bytes());
+            payload.extend(SETTINGS_ENABLE_PUSH.to_be_bytes());
+            payload.extend(0u32.to_be_bytes());
+            payload.extend(SETTINGS_MAX_HEADER_LIST_SIZE.to_be_bytes());
+            payload.extend((Self::max_header_list_size().min(u32::MAX as usize) as u32).to_be_bytes());
+
+            self.write_frame(FrameKind::Settings, 0, 0, &payload)?;
+
+            // Streams waiting for the handler hold up to the initial window each,
+            // the connection window fits all of them besides the one being read
+            let increment = (CONFIG.http2.max_concurrent_streams as i64 * Self::DEFAULT_WINDOW).min(Self::MAX_WINDOW - Self::DEFAULT_WINDOW);
+            if increment > 0 {
+                self.write_frame(FrameKind::WindowUpdate, 0, 0, &(increment as u32).to_be_bytes())?;
+            }
+
+            self.stream.flush().map_err(|_| self.close())?;
+            self.settings_sent = true;
+        }
+
+        return Ok(());
+    }
+
+    /// Reads and processes one frame, everything written before is sent first
+    fn read_frame (&mut self) -> Result<(), Closed> {
+        self.stream.flush().map_err(|_| self.close())?;
+
+        let is_idle = self.streams.values().all(|state| state.end_stream) && self.pending_headers.is_none();
+        self.set_deadline(self.idle_timeout())?;
+
+        let frame = match Frame::read(&mut self.stream, Frame::DEFAULT_MAX_SIZE) {
+            Ok(frame) => frame,
+            Err(FrameError::TooLarge) => return Err(self.fail(ErrorCode::FrameSizeError)),
+            // Idle client is politely told that connection is over
+            Err(FrameError::Io(_)) if is_idle => return Err(self.fail(ErrorCode::NoError)),
+            Err(FrameError::Io(_)) => return Err(self.close())
+        };
+
+        let kind = FrameKind::from_u8(frame.kind);
+        if let Some(ref pending) = self.pending_headers {
+            if kind != Some(FrameKind::Continuation) || frame.stream_id != pending.stream_id {
+                return Err(self.fail(ErrorCode::ProtocolError));
+            }
+        }
+
+        match kind {
+            Some(FrameKind::Data) => self.on_data(frame),
+            Some(FrameKind::Headers) => self.on_headers(frame),
+            Some(FrameKind::Continuation) => self.on_continuation(frame),
+            Some(FrameKind::Priority) => {
+                if frame.stream_id == 0 || frame.payload.len() != 5 {
+                    return Err(self.fail(ErrorCode::ProtocolError));
+                }
+
+                return Ok(());
+            }
+            Some(FrameKind::RstStream) => {
+                if frame.stream_id == 0 || frame.payload.len() != 4 {
+                    return Err(self.fail(ErrorCode::ProtocolError));
+                }
+
+                return self.remove_stream(frame.stream_id);
+            }
+            Some(FrameKind::Settings) => {
+                if frame.stream_id != 0 {
+                    return Err(self.fail(ErrorCode::ProtocolError));
+                } else if frame.has_flag(FLAG_ACK) {
+                    return Ok(());
+                }
+
+                if let Err(code) = self.apply_settings(&frame.payload) {
+                    return Err(self.fail(code));
+                }
+
+                return self.write_frame(FrameKind::Settings, FLAG_ACK, 0, &[]);
+            }
+            Some(FrameKind::PushPromise) => Err(self.fail(ErrorCode::ProtocolError)),
+            Some(FrameKind::Ping) => {
+                if frame.stream_id != 0 || frame.payload.len() != 8 {
+                    return Err(self.fail(ErrorCode::ProtocolError));
+                } else if frame.has_flag(FLAG_ACK) {
+                    return Ok(());
+                }
+
+                return self.write_frame(FrameKind::Ping, FLAG_ACK, 0, &frame.payload);
+            }
+            Some(FrameKind::GoAway) => {
+                self.goaway_received = true;
+                return Ok(());
+            }
+            Some(FrameKind::WindowUpdate) => self.on_window_update(frame),
+            None => Ok(())
+        }
+    }
+
+    fn apply_settings (&mut self, payload: &[u8]) -> Result<(), ErrorCode> {
+        if !payload.len().is_multiple_of(6) {
+            return Err(ErrorCode::FrameSizeError);
+        }
+
+        for setting in payload.chunks_exact(6) {
+            let id = u16::from_be_bytes([setting[0], setting[1]]);
+            let value = u32::from_be_bytes([setting[2], setting[3], setting[4], setting[5]]);
+
+            match id {
+                SETTINGS_ENABLE_PUSH if value > 1 => return Err(ErrorCode::ProtocolError),
+                SETTINGS_INITIAL_WINDOW_SIZE => {
+                    let value = value as i64;
+                    if value > Self::MAX_WINDOW {
+                        return Err(ErrorCode::FlowControlError);
+                    }
+
+                    // Change applies to windows of all open streams
+                    let delta = value - self.peer_initial_window;
+                    for state in self.streams.values_mut() {
+                        state.send_window += delta;
+                    }
+
+                    self.peer_initial_window = value;
+                }
+                SETTINGS_MAX_FRAME_SIZE => {
+                    if !(Frame::DEFAULT_MAX_SIZE..=0xffffff).contains(&(value as usize)) {
+                        return Err(ErrorCode::ProtocolError);
+                    }
+
+                    self.peer_max_frame_size = value as usize;
+                }
+                // Encoder never uses dynamic table, so `SETTINGS_HEADER_TABLE_SIZE` doesn't matter
+                _ => {}
+            }
+        }
+
+        return Ok(());
+    }
+
+    fn on_data (&mut self, frame: Frame) -> Result<(), Closed> {
+        if frame.stream_id == 0 {
+            return Err(self.fail(ErrorCode::ProtocolError));
+        }
+
+        let data = match frame.unpadded() {
+            Some(data) => data,
+            None => return Err(self.fail(ErrorCode::ProtocolError))
+        };
+
+        // Padding counts against flow control too. Dropped data only gives back the connection window,
+        // so client can't keep sending to the stream that is reset or rejected.
+        let received = frame.payload.len() as u32;
+        let end_stream = frame.has_flag(FLAG_END_STREAM);
+        let state = match self.streams.get_mut(&frame.stream_id) {
+            Some(state) if !state.end_stream => state,
+            Some(_) => {
+                self.return_window(0, received)?;
+                return self.reset_stream(frame.stream_id, ErrorCode::StreamClosed);
+            }
+            // Data may still come to the stream we have reset or answered
+            None if frame.stream_id <= self.last_stream_id => return self.return_window(0, received),
+            None => return Err(self.fail(ErrorCode::ProtocolError))
+        };
+
+        if received as i64 > state.recv_window {
+            self.return_window(0, received)?;
+            return self.reset_stream(frame.stream_id, ErrorCode::FlowControlError);
+        }
+
+        if state.body.len() + data.len() > CONFIG.limits.body {
+            self.return_window(0, received)?;
+            return self.reject_stream(frame.stream_id, HttpCode::RequestEntityTooLarge, false);
+        }
+
+        state.body.extend_from_slice(data);
+        state.end_stream = end_stream;
+        state.recv_window -= received as i64;
+        state.held += received;
+
+        // Data is given back when handler takes it, so streams waiting for their turn hold at most the initial window
+        if state.is_read {
+            return self.release_held(frame.stream_id);
+        }
+
+        // Request given early waits in `read_body`, others become ready when they are complete
+        // or when the body can't go further until it's read
+        if (end_stream || state.recv_window <= 0) && state.req.is_some() {
+            self.ready.push_back(frame.stream_id);
+        }
+
+        return Ok(());
+    }
+
+    fn on_headers (&mut self, frame: Frame) -> Result<(), Closed> {
+        if frame.stream_id.is_multiple_of(2) {
+            return Err(self.fail(ErrorCode::ProtocolError));
+        }
+
+        let mut block = match frame.unpadded() {
+            Some(block) => block,
+            None => return Err(self.fail(ErrorCode::ProtocolError))
+        };
+
+        if frame.has_flag(FLAG_PRIORITY) {
+            block = match block.get(5..) {
+                Some(block) => block,
+                None => return Err(self.fail(ErrorCode::ProtocolError))
+            };
+        }
+
+        let pending = PendingHeaders {
+            stream_id: frame.stream_id,
+            block: block.to_vec(),
+            end_stream: frame.has_flag(FLAG_END_STREAM)
+        };
+
+        if frame.has_flag(FLAG_END_HEADERS) {
+            return self.on_header_block(pending);
+        } else {
+            self.pending_headers = Some(pending);
+            return Ok(());
+        }
+    }
+
+    fn on_continuation (&mut self, frame: Frame) -> Result<(), Closed> {
+        let mut pending = match self.pending_headers.take() {
+            Some(pending) => pending,
+            None => return Err(self.fail(ErrorCode::ProtocolError))
+        };
+
+        pending.block.extend_from_slice(&frame.payload);
+        if pending.block.len() > Self::max_header_list_size() {
+            return Err(self.fail(ErrorCode::EnhanceYourCalm));
+        }
+
+        if frame.has_flag(FLAG_END_HEADERS) {
+            return self.on_header_block(pending);
+        } else {
+            self.pending_headers = Some(pending);
+            return Ok(());
+        }
+    }
+
+    fn on_header_block (&mut self, pending: PendingHeaders) -> Result<(), Closed> {
+        let fields = match self.decoder.decode(&pending.block) {
+            Ok(fields) => fields,
+            Err(_) => return Err(self.fail(ErrorCode::CompressionError))
+        };
+
+        let id = pending.stream_id;
+        if let Some(state) = self.streams.get_mut(&id) {
+            // Trailers, which must end the stream
+            if state.end_stream || !pending.end_stream {
+                return Err(self.fail(ErrorCode::ProtocolError));
+            }
+
+            state.end_stream = true;
+            if let Some(ref mut req) = state.req {
+                for (name, value) in fields {
+                    if !name.starts_with(':') {
+                        req.headers.set_normal(name, value);
+                    }
+                }
+
+                self.ready.push_back(id);
+            }
+
+            return Ok(());
+        }
+
+        if id <= self.last_stream_id {
+            return Err(self.fail(ErrorCode::ProtocolError));
+        }
+
+        self.last_stream_id = id;
+        if self.streams.len() >= CONFIG.http2.max_concurrent_streams as usize {
+            return self.reset_stream(id, ErrorCode::RefusedStream);
+        }
+
+        let req = match build_request(fields) {
+            Ok(req) => req,
+            Err(code) => return self.reject_stream(id, code, pending.end_stream)
+        };
+
+        // Client waiting for `100 Continue` gets it when the handler reads the body
+        let is_early = !pending.end_stream && req.headers.has_token("expect", "100-continue");
+        self.open_stream(id, req, pending.end_stream);
+        if pending.end_stream || is_early {
+            self.ready.push_back(id);
+        }
+
+        return Ok(());
+    }
+
+    fn on_window_update (&mut self, frame: Frame) -> Result<(), Closed> {
+        let increment = match frame.read_u32(0) {
+            Some(increment) if frame.payload.len() == 4 => (increment & 0x7fffffff) as i64,
+            _ => return Err(self.fail(ErrorCode::FrameSizeError))
+        };
+
+        if frame.stream_id == 0 {
+            if increment == 0 {
+                return Err(self.fail(ErrorCode::ProtocolError));
+            }
+
+            self.send_window += increment;
+            if self.send_window > Self::MAX_WINDOW {
+                return Err(self.fail(ErrorCode::FlowControlError));
+            }
+        } else if let Some(state) = self.streams.get_mut(&frame.stream_id) {
+            state.send_window += increment;
+            if increment == 0 {
+                return self.reset_stream(frame.stream_id, ErrorCode::ProtocolError);
+            } else if state.send_window > Self::MAX_WINDOW {
+                return self.reset_stream(frame.stream_id, ErrorCode::FlowControlError);
+            }
+        }
+
+        return Ok(());
+    }
+
+    /// Answers malformed or oversized request with bodyless response, stream is closed after it
+    fn reject_stream (&mut self, stream_id: u32, code: HttpCode, is_complete: bool) -> Result<(), Closed> {
+        let block = Encoder::encode([(":status", code.get_description().0), ("content-length", "0")]);
+        self.write_headers(stream_id, &block, true)?;
+
+        // Client that is still sending the body is told to stop
+        if is_complete {
+            return self.remove_stream(stream_id);
+        } else {
+            return self.reset_stream(stream_id, ErrorCode::NoError);
+        }
+    }
+
+    /// Sends header block in `HEADERS` frame followed by as many `CONTINUATION` ones as needed
+    fn write_headers (&mut self, stream_id: u32, block: &[u8], end_stream: bool) -> Result<(), Closed> {
+        let chunks: Vec<&[u8]> = if block.is_empty() { vec![block] } else { block.chunks(self.peer_max_frame_size).collect() };
+        for (i, chunk) in chunks.iter().enumerate() {
+            let mut flags = 0;
+            if i == 0 && end_stream {
+                flags |= FLAG_END_STREAM;
+            }
+
+            if i == chunks.len() - 1 {
+                flags |= FLAG_END_HEADERS;
+            }
+
+            let kind = if i == 0 { FrameKind::Headers } else { FrameKind::Continuation };
+            self.write_frame(kind, flags, stream_id, chunk)?;
+        }
+
+        return Ok(());
+    }
+
+    /// Sends body data, waiting for window updates when flow control doesn't allow more.
+    /// Returns `false` if stream was reset meanwhile.
+    fn write_data (&mut self, stream_id: u32, mut data: &[u8], end_stream: bool) -> Result<bool, Closed> {
+        loop {
+            let stream_window = match self.streams.get(&stream_id) {
+                Some(state) => state.send_window,
+                None => return Ok(false)
+            };
+
+            let available = stream_window.min(self.send_window).min(self.peer_max_frame_size as i64);
+            if available <= 0 && !data.is_empty() {
+                self.stream.flush().map_err(|_| self.close())?;
+                self.read_frame()?;
+                continue;
+            }
+
+            let size = data.len().min(available.max(0) as usize);
+            let (chunk, rest) = data.split_at(size);
+            let is_last = rest.is_empty();
+            let flags = if is_last && end_stream { FLAG_END_STREAM } else { 0 };
+            self.write_frame(FrameKind::Data, flags, stream_id, chunk)?;
+
+            self.send_window -= size as i64;
+            if let Some(state) = self.streams.get_mut(&stream_id) {
+                state.send_window -= size as i64;
+            }
+
+            if is_last {
+                return Ok(true);
+            }
+
+            data = rest;
+        }
+    }
+
+    fn send_response (&mut self, stream_id: u32, mut res: Response) -> Result<(), Closed> {
+        let is_head = match self.streams.get(&stream_id) {
+            Some(state) => state.is_head,
+            None => return Ok(())
+        };
+
+        if let ResponseType::Drop | ResponseType::Upgrade = res.payload {
+            self.reset_stream(stream_id, ErrorCode::Cancel)?;
+            return self.stream.flush().map_err(|_| self.close());
+        }
+
+        prepare_headers(&mut res);
+        let status = [(":status", res.code.get_description().0)];
+        let block = Encoder::encode(status.into_iter().chain(res.headers.into_iter().map(|header| (header.name.as_str(), header.value.as_str()))));
+
+        let has_body = !is_head && match &res.payload {
+            ResponseType::Payload(payload) => !payload.is_empty(),
+            ResponseType::Stream(_) => true,
+            _ => false
+        };
+
+        self.write_headers(stream_id, &block, !has_body)?;
+        if has_body {
+            match mem::replace(&mut res.payload, ResponseType::NoContent) {
+                ResponseType::Payload(payload) => {
+                    self.write_data(stream_id, &payload, true)?;
+                }
+                ResponseType::Stream(chunks) => {
+                    let size = chunks.size();
+                    let mut written = 0u64;
+                    for chunk in chunks.map(Some).chain([None]) {
+                        let is_sent = match chunk {
+                            Some(Ok(chunk)) if chunk.is_empty() => true,
+                            Some(Ok(chunk)) => {
+                                written += chunk.len() as u64;
+                                size.is_none_or(|size| written <= size) && self.write_data(stream_id, &chunk, false)?
+                            }
+                            Some(Err(_)) => false,
+                            // Declared `content-length` has to be matched exactly
+                            None => size.is_none_or(|size| written == size)
+                        };
+
+                        if !is_sent {
+                            // Response can't be finished, so client must know it's incomplete
+                            self.reset_stream(stream_id, ErrorCode::InternalError)?;
+                            return self.stream.flush().map_err(|_| self.close());
+                        }
+
+                        self.stream.flush().map_err(|_| self.close())?;
+                    }
+
+                    self.write_data(stream_id, &[], true)?;
+                }
+                _ => {}
+            }
+        }
+
+        // Client waiting for `100 Continue` got the final response instead, so it won't send the body,
+        // and whatever it has already sent is ignored as data of a closed stream
+        self.remove_stream(stream_id)?;
+
+        return self.stream.flush().map_err(|_| self.close());
+    }
+}
+
+impl<T: Transport> HttpConnection for Http2Connection<T> {
+    type Transport = T;
+
+    fn get_address (&self) -> IpAddr { self.address }
+    fn get_transport (&self) -> &T { self.stream.get_ref() }
+    fn into_stream (self) -> BufStream<T> { self.stream }
+
+    fn is_persistent (&self) -> bool {
+        return !self.closed;
+    }
+
+    fn has_pending_input (&mut self) -> bool {
+        // Acknowledgements written while reading frames mustn't wait for the next input
+        if self.stream.flush().is_err() {
+            return true;
+        }
+
+        return !self.ready.is_empty() || has_input(&mut self.stream);
+    }
+
+    /// Idle connection waits as long as HTTP/1 keep-alive one, incomplete requests are limited by body timeout
+    fn idle_timeout (&self) -> Duration {
+        if self.preface_pending {
+            return CONFIG.timeouts.header;
+        }
+
+        let is_idle = self.streams.values().all(|state| state.end_stream) && self.pending_headers.is_none();
+        return if is_idle { CONFIG.keep_alive.timeout } else { CONFIG.timeouts.body };
+    }
+
+    fn parse_head (&mut self) -> ParsingResult {
+        if self.closed || self.start().is_err() {
+            return ParsingResult::Invalid;
+        }
+
+        loop {
+            if let Some(id) = self.ready.pop_front() {
+                // Stream might be reset while waiting in the queue
+                if let Some(req) = self.streams.get_mut(&id).and_then(|state| state.req.take()) {
+                    self.current = Some(id);
+                    return ParsingResult::Complete(req);
+                }
+
+                continue;
+            }
+
+            if self.goaway_received && self.streams.is_empty() {
+                self.fail(ErrorCode::NoError);
+                return ParsingResult::Invalid;
+            }
+
+            // Worker isn't held by a client that sends nothing, connection waits for input in the event loop.
+            // Frame that is already started is read to its end within the deadline.
+            if !has_input(&mut self.stream) {
+                if self.stream.flush().is_err() {
+                    return ParsingResult::Invalid;
+                }
+
+                return ParsingResult::Partial;
+            }
+
+            if self.read_frame().is_err() {
+                return ParsingResult::Invalid;
+            }
+        }
+    }
+
+    fn read_body (&mut self, req: &mut Request) -> Result<(), ParsingResult> {
+        let id = match self.current {
+            Some(id) => id,
+            None => return Ok(())
+        };
+
+        let is_waiting = match self.streams.get_mut(&id) {
+            Some(state) => {
+                state.is_read = true;
+                !state.end_stream
+            }
+            None => return Ok(())
+        };
+
+        if is_waiting {
+            let block = Encoder::encode([(":status", "100")]);
+            if self.write_headers(id, &block, false).is_err() {
+                return Err(ParsingResult::Invalid);
+            }
+        }
+
+        // Data received before its turn is taken now, so client may send the rest
+        if self.release_held(id).is_err() || self.stream.flush().is_err() {
+            return Err(ParsingResult::Invalid);
+        }
+
+        loop {
+            match self.streams.get_mut(&id) {
+                Some(state) if state.end_stream => {
+                    req.body = mem::take(&mut state.body);
+                    return Ok(());
+                }
+                Some(_) => {}
+                // Stream is reset by client or rejected as too large, so there is nobody to answer
+                None => {
+                    self.current = None;
+                    return Err(ParsingResult::Error(HttpCode::RequestEntityTooLarge));
+                }
+            }
+
+            if self.read_frame().is_err() {
+                return Err(ParsingResult::Invalid);
+            }
+        }
+    }
+
+    fn respond (&mut self, res: Response) -> Result<(), Error> {
+        let id = match self.current.take() {
+            Some(id) => id,
+            None => return Ok(())
+        };
+
+        return self.send_response(id, res).map_err(|_| io::ErrorKind::ConnectionAborted.into());
+    }
+
+    fn disconnect (mut self) -> Result<T, Error> {
+        self.fail(ErrorCode::NoError);
+
+        let mut transport = self.stream.into_inner()?;
+        transport.shutdown_write()?;
+        return Ok(transport);
+    }
+}
+
+fn build_request (fields: Vec<(String, String)>) -> Result<Request, HttpCode> {
+    let mut method = None;
+    let mut path = None;
+    let mut authority = None;
+    let mut headers = Vec::new();
+
+    for (name, value) in fields {
+        match name.as_str() {
+            // Pseudo-headers must precede regular ones
+            _ if name.starts_with(':') && !headers.is_empty() => return Err(HttpCode::BadRequest),
+            ":method" => method = Some(value),
+            ":path" => path = Some(value),
+            ":authority" => authority = Some(value),
+            ":scheme" => {}
+            _ if name.starts_with(':') => return Err(HttpCode::BadRequest),
+            // Connection-specific headers are forbidden, everything is managed by frames
+            "connection" | "keep-alive" | "proxy-connection" | "transfer-encoding" | "upgrade" => {
+                return Err(HttpCode::BadRequest);
+            }
+            "te" if value != "trailers" => return Err(HttpCode::BadRequest),
+            _ if name.bytes().any(|ch| ch.is_ascii_uppercase()) => return Err(HttpCode::BadRequest),
+            _ => headers.push((name, value))
+        }
+    }
+
+    let method = match method.as_deref().map(HttpMethod::from_str) {
+        Some(Some(method)) => method,
+        Some(None) => return Err(HttpCode::NotImplemented),
+        None => return Err(HttpCode::BadRequest)
+    };
+
+    let path = match path {
+        Some(path) if path.len() > CONFIG.limits.uri => return Err(HttpCode::URITooLong),
+        Some(path) if !path.is_empty() => path,
+        _ => return Err(HttpCode::BadRequest)
+    };
+
+    if headers.len() > CONFIG.limits.headers {
+        return Err(HttpCode::RequestHeaderFieldsTooLarge);
+    }
+
+    let mut req = Request::new(method, path);
+    for (name, value) in headers {
+        match req.headers.get(&name) {
+            // Cookie may be split into several fields for better compression
+            Some(cookie) if name == "cookie" => req.headers.set(name, format!("{cookie}; {value}")),
+            // Repeated fields are joined the same way as HTTP/1 parser does
+            Some(existing) => req.headers.set(name, format!("{existing}, {}", value.trim_start())),
+            None => req.headers.set_normal(name, value)
+        }
+    }
+
+    if let Some(authority) = authority {
+        req.headers.set_default("host".to_owned(), authority);
+    }
+
+    return Ok(req);
+}
+
+fn prepare_headers (res: &mut Response) {
+    res.headers.set_default("date".to_owned(), fmt_http_date(SystemTime::now()));
+    match &res.payload {
+        ResponseType::Payload(payload) => {
+            res.headers.set("content-length".to_owned(), payload.len().to_string());
+        }
+        ResponseType::NoContent if res.code.allows_body() => {
+            res.headers.set("content-length".to_owned(), "0".to_owned());
+        }
+        ResponseType::Stream(stream) => match stream.size() {
+            Some(size) => res.headers.set("content-length".to_owned(), size.to_string()),
+            None => res.headers.remove("content-length".to_owned())
+        }
+        _ => {}
+    }
+
+    for name in ["connection", "keep-alive", "transfer-encoding", "upgrade"] {
+        res.headers.remove(name.to_owned());
+    }
+}
@@ -0,0 +1,79 @@
+#![feature(try_trait_v2)]
+#![feature(try_trait_v2_residual)]
+
+use std::process;
+use app::App;
+use crate::{app::{config::CONFIG, modules::load_modules, static_files::StaticDir}, db::connection::{close_database_connections, init_database_connections_store, DatabaseConnections}, utils::log::{log_error, log_info, log_warning}};
+
+pub mod app;
+pub mod http;
+pub mod http1;
+pub mod http2;
+pub mod websocket;
+pub mod context;
+pub mod db;
+pub mod utils;
+pub(crate) mod c;
+
+pub extern crate photonyx_macro;
+
+pub fn main () {
+    let app = Box::leak(Box::new(App::new()));
+
+    if let Err(error) = load_modules(&mut app.modules, "modules") {
+        log_error(&format!("Failed to load modules: {error}"));
+        process::exit(-1);
+    }
+
+    log_info(&format!("Loaded modules: {}", app.modules.len()));
+
+    // stage 1 - loading database providers
+    let mut db_connections = DatabaseConnections::new();
+
+    for module in &app.modules {
+        if let Some(database) = module.provide_database() {
+            let cfg = &CONFIG["db"]["primary"];
+            match database.connect(cfg) {
+                Err(error) => {
+                    println!("failed to create connection: {}", error);
+                }
+                Ok(conn) => {
+                    // todo! un-hardcode     VVVVVVV
+                    db_connections.register("primary".to_owned(), conn);
+                }
+            }
+        }
+    }
+
+    init_database_connections_store(db_connections);
+
+    // stage 2 - loading controllers
+    for module in &app.modules {
+        module.provide_models();
+        module.provide_routes(&mut app.router);
+    }
+
+    // Static directories go last, so the one mounted at root doesn't shadow module routes
+    for entry in &CONFIG.static_dirs {
+        let mut dir = StaticDir::new(&entry.dir).with_index(entry.index.clone());
+        if let Some(fallback) = &entry.fallback {
+            dir = dir.with_fallback(fallback);
+        }
+
+        app.router.serve_dir(&entry.path, dir);
+    }
+
+    // Requests still running may use modules and database connections, so they are left to the process exit
+    if !app::server::start_server(app) {
+        log_warning("Server stopped without deinitializing modules");
+        process::exit(-1);
+    }
+
+    // Modules are deinitialized in reverse order, as later ones may depend on earlier
+    for module in app.modules.iter().rev() {
+        module.deinit();
+    }
+
+    close_database_connections();
+    log_info("Server stopped");
+}
@@ -1,3 +1,5 @@
+#![allow(clippy::missing_safety_doc)]
+
 use json::JsonValue;
 use crate::c::{c_deinit, c_init, c_init_str, c_str, c_string, c_unwrap};
 
@@ -2,7 +2,9 @@ use std::env;
 
 use crate::utils::sync::{AppStatic, LazyInit};
 
+#[allow(clippy::redundant_static_lifetimes)]
 const RESET: &'static str = "\x1B[0m";
+#[allow(clippy::redundant_static_lifetimes)]
 const BOLD: &'static str = "\x1B[1m";
 static THEME: AppStatic<ShellTheme> = AppStatic::new();
 
@@ -79,12 +81,14 @@ enum ShellColor { Info, Ok, Warning, Error }
 impl ShellColor {
 	/// Get foreground color sequence
 	#[inline]
+	#[allow(clippy::wrong_self_convention)]
 	pub fn as_fg (self) -> String {
 		return (THEME.calc_color)(self, 30);
 	}
 
 	/// Get background color sequence
 	#[inline]
+	#[allow(clippy::wrong_self_convention)]
 	pub fn as_bg (self) -> String {
 		return (THEME.calc_color)(self, 40);
 	}
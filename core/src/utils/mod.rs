@@ -5,6 +5,9 @@ use crate::utils::log::log_error;
 pub mod json_c;
 pub mod log;
 pub mod macros;
+pub mod proxy;
+pub mod reactor;
+pub mod socket;
 pub mod stream;
 pub mod sync;
 pub mod validator;
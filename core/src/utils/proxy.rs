@@ -0,0 +1,145 @@
+use std::io::{self, ErrorKind, Read};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::str::FromStr;
+
+const V1_PREFIX: &[u8] = b"PROXY ";
+const V2_SIGNATURE: &[u8] = b"\r\n\r\n\0\r\nQUIT\n";
+/// Longest v1 header including `\r\n`, as defined by the spec
+const V1_MAX_LENGTH: usize = 107;
+
+/// Reads PROXY protocol v1 or v2 header sent by load balancer before any connection data,
+/// returns original client address, which is `None` for health checks and unknown protocols.
+/// Exactly the header is consumed, so the stream can be passed to TLS or HTTP right after.
+pub fn read_proxy_header<R: Read> (stream: &mut R) -> io::Result<Option<SocketAddr>> {
+    let mut prefix = [0u8; 6];
+    stream.read_exact(&mut prefix)?;
+
+    if prefix == V1_PREFIX {
+        return read_v1(stream);
+    } else if prefix == V2_SIGNATURE[..6] {
+        return read_v2(stream);
+    } else {
+        return Err(invalid("missing PROXY protocol header"));
+    }
+}
+
+/// Text header, e.g. `PROXY TCP4 192.0.2.1 198.51.100.1 56324 443\r\n`
+fn read_v1<R: Read> (stream: &mut R) -> io::Result<Option<SocketAddr>> {
+    let mut line = Vec::with_capacity(V1_MAX_LENGTH);
+    // Header length isn't known beforehand, so it's read bytewise to leave the following data in the stream
+    while !line.ends_with(b"\r\n") {
+        if line.len() + V1_PREFIX.len() >= V1_MAX_LENGTH {
+            return Err(invalid("PROXY header is too long"));
+        }
+
+        let mut byte = [0u8];
+        stream.read_exact(&mut byte)?;
+        line.push(byte[0]);
+    }
+
+    let line = str::from_utf8(&line[..line.len() - 2]).map_err(|_| invalid("PROXY header isn't ASCII"))?;
+    let mut parts = line.split(' ');
+    match parts.next() {
+        Some("TCP4" | "TCP6") => {}
+        Some("UNKNOWN") => return Ok(None),
+        _ => return Err(invalid("unsupported PROXY protocol family"))
+    }
+
+    let source = parts.next().and_then(|address| address.parse::<IpAddr>().ok());
+    let _destination = parts.next();
+    let port = parts.next().and_then(|port| port.parse::<u16>().ok());
+    match (source, port) {
+        (Some(source), Some(port)) => return Ok(Some(SocketAddr::new(source, port))),
+        _ => return Err(invalid("malformed PROXY header"))
+    }
+}
+
+/// Binary header: signature, version with command, family, length and addresses followed by optional TLVs
+fn read_v2<R: Read> (stream: &mut R) -> io::Result<Option<SocketAddr>> {
+    let mut head = [0u8; 10];
+    stream.read_exact(&mut head)?;
+    if head[..6] != V2_SIGNATURE[6..] {
+        return Err(invalid("missing PROXY protocol header"));
+    }
+
+    let (version_command, family) = (head[6], head[7]);
+    if version_command >> 4 != 2 {
+        return Err(invalid("unsupported PROXY protocol version"));
+    }
+
+    let mut payload = vec![0u8; u16::from_be_bytes([head[8], head[9]]) as usize];
+    stream.read_exact(&mut payload)?;
+
+    // LOCAL command is sent by balancer itself, e.g. for health checks
+    if version_command & 0x0F == 0 {
+        return Ok(None);
+    }
+
+    // High nibble is address family, low one is transport, which doesn't matter here
+    match family >> 4 {
+        1 if payload.len() >= 12 => {
+            let address = Ipv4Addr::from(<[u8; 4]>::try_from(&payload[..4]).unwrap());
+            return Ok(Some(SocketAddr::new(address.into(), u16::from_be_bytes([payload[8], payload[9]]))));
+        }
+        2 if payload.len() >= 36 => {
+            let address = Ipv6Addr::from(<[u8; 16]>::try_from(&payload[..16]).unwrap());
+            return Ok(Some(SocketAddr::new(address.into(), u16::from_be_bytes([payload[32], payload[33]]))));
+        }
+        0 | 3 => return Ok(None),
+        _ => return Err(invalid("malformed PROXY header"))
+    }
+}
+
+#[inline]
+fn invalid (message: &str) -> io::Error {
+    return io::Error::new(ErrorKind::InvalidData, message);
+}
+
+/// IP network in CIDR notation, e.g. `10.0.0.0/8`, single address without prefix is accepted too
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cidr {
+    network: IpAddr,
+    prefix: u8
+}
+
+impl Cidr {
+    /// IPv4 addresses mapped into IPv6 by dual-stack sockets are matched as IPv4
+    pub fn contains (&self, address: IpAddr) -> bool {
+        match (self.network, address.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(address)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                return u32::from(network) & mask == u32::from(address) & mask;
+            }
+            (IpAddr::V6(network), IpAddr::V6(address)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                return u128::from(network) & mask == u128::from(address) & mask;
+            }
+            _ => return false
+        }
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = String;
+
+    fn from_str (value: &str) -> Result<Self, Self::Err> {
+        let (address, prefix) = match value.split_once('/') {
+            Some((address, prefix)) => (address, Some(prefix)),
+            None => (value, None)
+        };
+
+        let network = address.parse::<IpAddr>().map_err(|_| format!("invalid network address: {value}"))?.to_canonical();
+        let max_prefix = if network.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix.parse::<u8>().ok().filter(|prefix| *prefix <= max_prefix).ok_or_else(|| format!("invalid network prefix: {value}"))?,
+            None => max_prefix
+        };
+
+        return Ok(Cidr { network, prefix });
+    }
+}
+
+/// Whether `address` belongs to any of `networks`
+pub fn is_trusted (networks: &[Cidr], address: IpAddr) -> bool {
+    return networks.iter().any(|network| network.contains(address));
+}
@@ -0,0 +1,349 @@
+use std::collections::HashMap;
+use std::io::{self, ErrorKind, Read};
+#[cfg(unix)]
+use std::os::fd::{AsFd, BorrowedFd};
+#[cfg(windows)]
+use std::os::windows::io::{AsSocket, BorrowedSocket};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use polling::{Event, Events, Poller};
+use threadpool::ThreadPool;
+use crate::utils::log::log_warning;
+use crate::utils::socket::{SocketListener, Stream};
+
+/// Connection waiting for input without occupying a worker
+pub trait Idle: Send {
+    fn socket (&self) -> &Stream;
+    /// How long to wait for input before `expire` is called, zero duration waits forever
+    fn timeout (&self) -> Duration;
+    /// Serves input that has arrived, returns connection back when it has to wait again
+    fn resume (self: Box<Self>) -> Option<Box<dyn Idle>>;
+    /// Handles the end of the timeout, usually by closing the connection
+    fn expire (self: Box<Self>) -> Option<Box<dyn Idle>>;
+    /// Politely closes connection because server is shutting down
+    fn close (self: Box<Self>) {}
+}
+
+/// Listening socket served by the event loop thread
+pub trait Listener: Send {
+    fn socket (&self) -> &SocketListener;
+    /// Accepts all pending connections, it runs on the event loop thread, so it mustn't block
+    fn accept (&mut self, reactor: &Reactor);
+}
+
+/// Counters showing how loaded the server is
+pub struct LoadStats {
+    queued: AtomicUsize,
+    active: AtomicUsize,
+    waiting: AtomicUsize,
+    rejected: AtomicU64
+}
+
+impl Default for LoadStats {
+    fn default () -> Self {
+        return LoadStats::new();
+    }
+}
+
+impl LoadStats {
+    pub const fn new () -> Self {
+        LoadStats {
+            queued: AtomicUsize::new(0),
+            active: AtomicUsize::new(0),
+            waiting: AtomicUsize::new(0),
+            rejected: AtomicU64::new(0)
+        }
+    }
+
+    /// Connections ready to be served, but waiting for a free worker
+    #[inline]
+    pub fn queued (&self) -> usize {
+        return self.queued.load(Ordering::Relaxed);
+    }
+
+    /// Connections being served by workers
+    #[inline]
+    pub fn active (&self) -> usize {
+        return self.active.load(Ordering::Relaxed);
+    }
+
+    /// Idle connections waiting for input in the event loop
+    #[inline]
+    pub fn waiting (&self) -> usize {
+        return self.waiting.load(Ordering::Relaxed);
+    }
+
+    /// New connections refused because the queue was full
+    #[inline]
+    pub fn rejected (&self) -> u64 {
+        return self.rejected.load(Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub fn add_rejected (&self) {
+        self.rejected.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Keeps `active` counter right even if handler panics
+struct ActiveGuard(&'static LoadStats);
+
+impl Drop for ActiveGuard {
+    fn drop (&mut self) {
+        self.0.active.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+enum Parked {
+    Connection(Box<dyn Idle>),
+    /// Closed connection whose input is discarded, so unread data doesn't reset the last response
+    Lingering(Stream),
+    Listener(Box<dyn Listener>)
+}
+
+impl Parked {
+    #[cfg(unix)]
+    fn source (&self) -> BorrowedFd<'_> {
+        match self {
+            Parked::Connection(connection) => connection.socket().as_fd(),
+            Parked::Lingering(stream) => stream.as_fd(),
+            Parked::Listener(listener) => listener.socket().as_fd()
+        }
+    }
+
+    #[cfg(windows)]
+    fn source (&self) -> BorrowedSocket<'_> {
+        match self {
+            Parked::Connection(connection) => connection.socket().as_socket(),
+            Parked::Lingering(stream) => stream.as_socket(),
+            Parked::Listener(listener) => listener.socket().as_socket()
+        }
+    }
+}
+
+struct Waiting {
+    parked: Parked,
+    deadline: Option<Instant>
+}
+
+/// Event loop multiplexing idle connections, ready ones are handed to the worker pool.
+/// Handlers stay blocking, workers are only taken while there is something to read.
+pub struct Reactor {
+    poller: Poller,
+    waiting: Mutex<HashMap<usize, Waiting>>,
+    next_key: AtomicUsize,
+    pool: ThreadPool,
+    stats: &'static LoadStats,
+    /// Loop is stopped, so everything parked from now on gets closed
+    is_closing: AtomicBool
+}
+
+impl Reactor {
+    /// How often deadlines are checked, timeouts are counted in seconds so it's precise enough
+    const TICK: Duration = Duration::from_millis(250);
+    const LINGER_TIMEOUT: Duration = Duration::from_secs(2);
+
+    pub fn new (pool: ThreadPool, stats: &'static LoadStats) -> io::Result<Self> {
+        return Ok(Reactor {
+            poller: Poller::new()?,
+            waiting: Mutex::new(HashMap::new()),
+            next_key: AtomicUsize::new(0),
+            pool,
+            stats,
+            is_closing: AtomicBool::new(false)
+        });
+    }
+
+    /// Makes connection wait until its socket becomes readable or the timeout ends
+    pub fn park (&self, connection: Box<dyn Idle>) {
+        let timeout = connection.timeout();
+        let deadline = if timeout.is_zero() { None } else { Some(Instant::now() + timeout) };
+        self.insert(Waiting { parked: Parked::Connection(connection), deadline });
+    }
+
+    /// Discards input of the socket for a while before closing it, writing side should be already shut down
+    pub fn linger (&self, stream: Stream) {
+        if stream.set_nonblocking(true).is_ok() {
+            self.insert(Waiting { parked: Parked::Lingering(stream), deadline: Some(Instant::now() + Reactor::LINGER_TIMEOUT) });
+        }
+    }
+
+    /// Serves connections of the listener until the loop is stopped
+    pub fn listen (&self, listener: Box<dyn Listener>) {
+        if listener.socket().set_nonblocking(true).is_ok() {
+            self.insert(Waiting { parked: Parked::Listener(listener), deadline: None });
+        }
+    }
+
+    fn insert (&self, entry: Waiting) {
+        let key = self.next_key.fetch_add(1, Ordering::Relaxed);
+        let mut waiting = self.waiting.lock().unwrap_or_else(|error| error.into_inner());
+        if self.is_closing.load(Ordering::Relaxed) {
+            drop(waiting);
+            return self.close(entry.parked);
+        }
+
+        // Safety: socket is deleted from the poller whenever it leaves `waiting`, so it's never closed while registered
+        if let Err(error) = unsafe { self.poller.add(&entry.parked.source(), Event::readable(key)) } {
+            log_warning(&format!("Event loop registration error: {error}"));
+            return;
+        }
+
+        waiting.insert(key, entry);
+        self.stats.waiting.store(waiting.len(), Ordering::Relaxed);
+    }
+
+    /// Runs the loop on the current thread until `stop` is set, then closes listeners and all idle connections
+    pub fn run (self: &Arc<Self>, stop: &AtomicBool) {
+        let mut events = Events::new();
+        let mut last_check = Instant::now();
+
+        while !stop.load(Ordering::Relaxed) {
+            events.clear();
+            if let Err(error) = self.poller.wait(&mut events, Some(Reactor::TICK)) {
+                if error.kind() != ErrorKind::Interrupted {
+                    log_warning(&format!("Event loop error: {error}"));
+                }
+
+                continue;
+            }
+
+            let mut ready = Vec::new();
+            let mut expired = Vec::new();
+            let mut listeners = Vec::new();
+            {
+                let mut waiting = self.waiting.lock().unwrap_or_else(|error| error.into_inner());
+                for event in events.iter() {
+                    // Connection might have expired right before its event
+                    let Some(entry) = waiting.remove(&event.key) else {
+                        continue;
+                    };
+
+                    match entry.parked {
+                        Parked::Connection(connection) => {
+                            let _ = self.poller.delete(connection.socket());
+                            ready.push(connection);
+                        }
+                        Parked::Lingering(stream) => {
+                            if Reactor::drain(&stream) && self.poller.modify(&stream, Event::readable(event.key)).is_ok() {
+                                waiting.insert(event.key, Waiting { parked: Parked::Lingering(stream), deadline: entry.deadline });
+                            } else {
+                                let _ = self.poller.delete(&stream);
+                            }
+                        }
+                        // Accepted connections are parked, so listener is served without holding the lock
+                        Parked::Listener(listener) => listeners.push((event.key, listener))
+                    }
+                }
+
+                let now = Instant::now();
+                if now.duration_since(last_check) >= Reactor::TICK {
+                    last_check = now;
+
+                    let keys = waiting.iter()
+                        .filter(|(_, entry)| entry.deadline.is_some_and(|deadline| deadline <= now))
+                        .map(|(key, _)| *key)
+                        .collect::<Vec<_>>();
+
+                    for key in keys {
+                        if let Some(entry) = waiting.remove(&key) {
+                            let _ = self.poller.delete(entry.parked.source());
+                            if let Parked::Connection(connection) = entry.parked {
+                                expired.push(connection);
+                            }
+                        }
+                    }
+                }
+
+                self.stats.waiting.store(waiting.len(), Ordering::Relaxed);
+            }
+
+            for (key, mut listener) in listeners {
+                listener.accept(self);
+                let mut waiting = self.waiting.lock().unwrap_or_else(|error| error.into_inner());
+                if self.poller.modify(listener.socket(), Event::readable(key)).is_ok() {
+                    waiting.insert(key, Waiting { parked: Parked::Listener(listener), deadline: None });
+                } else {
+                    let _ = self.poller.delete(listener.socket());
+                }
+            }
+
+            for connection in ready {
+                self.dispatch(connection, false);
+            }
+
+            for connection in expired {
+                self.dispatch(connection, true);
+            }
+        }
+
+        let parked = {
+            let mut waiting = self.waiting.lock().unwrap_or_else(|error| error.into_inner());
+            self.is_closing.store(true, Ordering::Relaxed);
+            self.stats.waiting.store(0, Ordering::Relaxed);
+            waiting.drain().map(|(_, entry)| entry.parked).collect::<Vec<_>>()
+        };
+
+        for parked in parked {
+            let _ = self.poller.delete(parked.source());
+            self.close(parked);
+        }
+    }
+
+    /// Waits until workers finish everything given to them, returns false if `timeout` ends first
+    pub fn wait_idle (&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        while self.stats.queued() + self.stats.active() > 0 {
+            if Instant::now() >= deadline {
+                return false;
+            }
+
+            thread::sleep(Duration::from_millis(50));
+        }
+
+        return true;
+    }
+
+    /// Listeners and lingering sockets are just dropped, connections are closed by workers
+    fn close (&self, parked: Parked) {
+        if let Parked::Connection(connection) = parked {
+            let stats = self.stats;
+            stats.queued.fetch_add(1, Ordering::Relaxed);
+            self.pool.execute(move || {
+                stats.queued.fetch_sub(1, Ordering::Relaxed);
+                stats.active.fetch_add(1, Ordering::Relaxed);
+                let _guard = ActiveGuard(stats);
+                connection.close();
+            });
+        }
+    }
+
+    /// Reads everything available without blocking, returns whether socket should be kept lingering
+    fn drain (mut stream: &Stream) -> bool {
+        let mut buf = [0u8; 4096];
+        loop {
+            match stream.read(&mut buf) {
+                Ok(0) => return false,
+                Ok(_) => continue,
+                Err(error) => return error.kind() == ErrorKind::WouldBlock
+            }
+        }
+    }
+
+    fn dispatch (self: &Arc<Self>, connection: Box<dyn Idle>, is_expired: bool) {
+        let reactor = self.clone();
+        self.stats.queued.fetch_add(1, Ordering::Relaxed);
+        self.pool.execute(move || {
+            reactor.stats.queued.fetch_sub(1, Ordering::Relaxed);
+            reactor.stats.active.fetch_add(1, Ordering::Relaxed);
+            let _guard = ActiveGuard(reactor.stats);
+
+            let next = if is_expired { connection.expire() } else { connection.resume() };
+            if let Some(connection) = next {
+                reactor.park(connection);
+            }
+        });
+    }
+}
@@ -0,0 +1,421 @@
+use std::io::{self, BufRead, Cursor, ErrorKind, Read, Write};
+use std::net::{Ipv4Addr, Shutdown, SocketAddr, TcpListener, TcpStream};
+#[cfg(unix)]
+use std::os::fd::{AsFd, BorrowedFd};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+#[cfg(windows)]
+use std::os::windows::io::{AsSocket, BorrowedSocket};
+#[cfg(unix)]
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use bufstream::BufStream;
+use rustls::ServerConnection;
+
+/// Connected socket of TCP or Unix domain listener
+pub enum Stream {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream)
+}
+
+impl Stream {
+    pub fn set_nonblocking (&self, nonblocking: bool) -> io::Result<()> {
+        match self {
+            Stream::Tcp(stream) => stream.set_nonblocking(nonblocking),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.set_nonblocking(nonblocking)
+        }
+    }
+
+    pub fn set_read_timeout (&self, timeout: Option<Duration>) -> io::Result<()> {
+        match self {
+            Stream::Tcp(stream) => stream.set_read_timeout(timeout),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.set_read_timeout(timeout)
+        }
+    }
+
+    pub fn set_write_timeout (&self, timeout: Option<Duration>) -> io::Result<()> {
+        match self {
+            Stream::Tcp(stream) => stream.set_write_timeout(timeout),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.set_write_timeout(timeout)
+        }
+    }
+
+    pub fn shutdown (&self, how: Shutdown) -> io::Result<()> {
+        match self {
+            Stream::Tcp(stream) => stream.shutdown(how),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.shutdown(how)
+        }
+    }
+}
+
+impl From<TcpStream> for Stream {
+    fn from (stream: TcpStream) -> Self {
+        Stream::Tcp(stream)
+    }
+}
+
+#[cfg(unix)]
+impl From<UnixStream> for Stream {
+    fn from (stream: UnixStream) -> Self {
+        Stream::Unix(stream)
+    }
+}
+
+impl Read for &Stream {
+    #[inline]
+    fn read (&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Stream::Tcp(stream) => (&*stream).read(buf),
+            #[cfg(unix)]
+            Stream::Unix(stream) => (&*stream).read(buf)
+        }
+    }
+}
+
+impl Write for &Stream {
+    #[inline]
+    fn write (&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Stream::Tcp(stream) => (&*stream).write(buf),
+            #[cfg(unix)]
+            Stream::Unix(stream) => (&*stream).write(buf)
+        }
+    }
+
+    #[inline]
+    fn flush (&mut self) -> io::Result<()> {
+        match self {
+            Stream::Tcp(stream) => (&*stream).flush(),
+            #[cfg(unix)]
+            Stream::Unix(stream) => (&*stream).flush()
+        }
+    }
+}
+
+impl Read for Stream {
+    #[inline]
+    fn read (&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        return (&*self).read(buf);
+    }
+}
+
+impl Write for Stream {
+    #[inline]
+    fn write (&mut self, buf: &[u8]) -> io::Result<usize> {
+        return (&*self).write(buf);
+    }
+
+    #[inline]
+    fn flush (&mut self) -> io::Result<()> {
+        return (&*self).flush();
+    }
+}
+
+#[cfg(unix)]
+impl AsFd for Stream {
+    fn as_fd (&self) -> BorrowedFd<'_> {
+        match self {
+            Stream::Tcp(stream) => stream.as_fd(),
+            Stream::Unix(stream) => stream.as_fd()
+        }
+    }
+}
+
+#[cfg(windows)]
+impl AsSocket for Stream {
+    fn as_socket (&self) -> BorrowedSocket<'_> {
+        match self {
+            Stream::Tcp(stream) => stream.as_socket()
+        }
+    }
+}
+
+/// Listening TCP or Unix domain socket
+pub enum SocketListener {
+    Tcp(TcpListener),
+    /// Socket file created by the server is removed when listener is dropped
+    #[cfg(unix)]
+    Unix(UnixListener, Option<PathBuf>)
+}
+
+impl SocketListener {
+    /// Unix domain peers have no IP address, they are reported as loopback ones
+    pub fn accept (&self) -> io::Result<(Stream, SocketAddr)> {
+        match self {
+            SocketListener::Tcp(listener) => {
+                let (stream, address) = listener.accept()?;
+                return Ok((Stream::Tcp(stream), address));
+            }
+            #[cfg(unix)]
+            SocketListener::Unix(listener, _) => {
+                let (stream, _) = listener.accept()?;
+                return Ok((Stream::Unix(stream), SocketAddr::from((Ipv4Addr::LOCALHOST, 0))));
+            }
+        }
+    }
+
+    pub fn set_nonblocking (&self, nonblocking: bool) -> io::Result<()> {
+        match self {
+            SocketListener::Tcp(listener) => listener.set_nonblocking(nonblocking),
+            #[cfg(unix)]
+            SocketListener::Unix(listener, _) => listener.set_nonblocking(nonblocking)
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for SocketListener {
+    fn drop (&mut self) {
+        if let SocketListener::Unix(_, Some(path)) = self {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+#[cfg(unix)]
+impl AsFd for SocketListener {
+    fn as_fd (&self) -> BorrowedFd<'_> {
+        match self {
+            SocketListener::Tcp(listener) => listener.as_fd(),
+            SocketListener::Unix(listener, _) => listener.as_fd()
+        }
+    }
+}
+
+#[cfg(windows)]
+impl AsSocket for SocketListener {
+    fn as_socket (&self) -> BorrowedSocket<'_> {
+        match self {
+            SocketListener::Tcp(listener) => listener.as_socket()
+        }
+    }
+}
+
+/// Byte stream carrying HTTP connection
+pub trait Transport: Read + Write + Send + Sync {
+    /// All reads after this call must complete within `timeout`, zero duration removes the limit
+    fn set_deadline (&mut self, timeout: Duration) -> io::Result<()>;
+    /// Stops sending, reading side stays open until transport is dropped
+    fn shutdown_write (&mut self) -> io::Result<()>;
+    /// Non-blocking reads fail with `WouldBlock` instead of waiting for data
+    fn set_nonblocking (&self, nonblocking: bool) -> io::Result<()>;
+    /// Name of the listener connection came through, empty for unnamed ones
+    fn listener (&self) -> &str;
+    /// Whether data is encrypted by TLS listener
+    fn is_tls (&self) -> bool;
+}
+
+/// Accepted client socket, which can limit total time of the following reads
+pub struct Socket {
+    inner: Stream,
+    /// Session of connection accepted by TLS listener, data goes through it
+    tls: Option<Box<ServerConnection>>,
+    deadline: Option<Instant>,
+    listener: Arc<str>
+}
+
+impl Socket {
+    pub fn new<S: Into<Stream>> (inner: S) -> Self {
+        Socket { inner: inner.into(), tls: None, deadline: None, listener: Arc::from("") }
+    }
+
+    /// Socket over TLS session, handshake may be already done or will be done by the first read
+    pub fn with_tls<S: Into<Stream>> (inner: S, tls: ServerConnection) -> Self {
+        Socket { inner: inner.into(), tls: Some(Box::new(tls)), deadline: None, listener: Arc::from("") }
+    }
+
+    #[inline]
+    pub fn set_listener (&mut self, name: Arc<str>) {
+        self.listener = name;
+    }
+
+    /// Underlying socket, e.g. for registering it in the event loop
+    #[inline]
+    pub fn get_stream (&self) -> &Stream {
+        return &self.inner;
+    }
+
+    /// Underlying socket without TLS session, e.g. for draining it after the session is closed
+    #[inline]
+    pub fn into_stream (self) -> Stream {
+        return self.inner;
+    }
+
+    /// Protocol negotiated by TLS ALPN extension
+    pub fn alpn_protocol (&self) -> Option<&[u8]> {
+        return self.tls.as_ref()?.alpn_protocol();
+    }
+
+    /// Server name sent by client in TLS SNI extension
+    pub fn server_name (&self) -> Option<&str> {
+        return self.tls.as_ref()?.server_name();
+    }
+
+    /// Limits time of each read separately, zero duration removes the limit
+    #[inline]
+    pub fn set_read_timeout (&mut self, timeout: Duration) -> io::Result<()> {
+        self.deadline = None;
+        return self.inner.set_read_timeout(Some(timeout).filter(|t| !t.is_zero()));
+    }
+
+    #[inline]
+    pub fn set_write_timeout (&self, timeout: Duration) -> io::Result<()> {
+        return self.inner.set_write_timeout(Some(timeout).filter(|t| !t.is_zero()));
+    }
+
+}
+
+impl Transport for Socket {
+    /// Unlike read timeout, deadline also stops data trickled byte by byte
+    fn set_deadline (&mut self, timeout: Duration) -> io::Result<()> {
+        if timeout.is_zero() {
+            self.deadline = None;
+            return self.inner.set_read_timeout(None);
+        } else {
+            self.deadline = Some(Instant::now() + timeout);
+            return Ok(());
+        }
+    }
+
+    fn shutdown_write (&mut self) -> io::Result<()> {
+        if let Some(tls) = &mut self.tls {
+            tls.send_close_notify();
+            while tls.wants_write() {
+                tls.write_tls(&mut self.inner)?;
+            }
+        }
+
+        return self.inner.shutdown(Shutdown::Write);
+    }
+
+    #[inline]
+    fn set_nonblocking (&self, nonblocking: bool) -> io::Result<()> {
+        return self.inner.set_nonblocking(nonblocking);
+    }
+
+    #[inline]
+    fn listener (&self) -> &str {
+        return &self.listener;
+    }
+
+    #[inline]
+    fn is_tls (&self) -> bool {
+        return self.tls.is_some();
+    }
+}
+
+impl Read for Socket {
+    fn read (&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if let Some(deadline) = self.deadline {
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return Err(ErrorKind::TimedOut.into());
+            }
+
+            self.inner.set_read_timeout(Some(left))?;
+        }
+
+        if let Some(tls) = &mut self.tls {
+            return rustls::Stream::new(tls.as_mut(), &mut self.inner).read(buf);
+        }
+
+        return self.inner.read(buf);
+    }
+}
+
+impl Write for Socket {
+    #[inline]
+    fn write (&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Some(tls) = &mut self.tls {
+            return rustls::Stream::new(tls.as_mut(), &mut self.inner).write(buf);
+        }
+
+        return self.inner.write(buf);
+    }
+
+    #[inline]
+    fn flush (&mut self) -> io::Result<()> {
+        if let Some(tls) = &mut self.tls {
+            return rustls::Stream::new(tls.as_mut(), &mut self.inner).flush();
+        }
+
+        return self.inner.flush();
+    }
+}
+
+/// In-memory transport for running connections over prepared input, e.g. in tests
+#[derive(Debug)]
+pub struct MemoryStream {
+    input: Cursor<Vec<u8>>,
+    pub output: Vec<u8>
+}
+
+impl MemoryStream {
+    pub fn new (input: Vec<u8>) -> Self {
+        MemoryStream { input: Cursor::new(input), output: Vec::new() }
+    }
+}
+
+impl Read for MemoryStream {
+    #[inline]
+    fn read (&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        return self.input.read(buf);
+    }
+}
+
+impl Write for MemoryStream {
+    #[inline]
+    fn write (&mut self, buf: &[u8]) -> io::Result<usize> {
+        return self.output.write(buf);
+    }
+
+    #[inline]
+    fn flush (&mut self) -> io::Result<()> {
+        return Ok(());
+    }
+}
+
+impl Transport for MemoryStream {
+    fn set_deadline (&mut self, _timeout: Duration) -> io::Result<()> {
+        return Ok(());
+    }
+
+    fn shutdown_write (&mut self) -> io::Result<()> {
+        return Ok(());
+    }
+
+    fn set_nonblocking (&self, _nonblocking: bool) -> io::Result<()> {
+        return Ok(());
+    }
+
+    fn listener (&self) -> &str {
+        return "";
+    }
+
+    fn is_tls (&self) -> bool {
+        return false;
+    }
+}
+
+/// Checks without waiting whether stream has buffered or incoming data, end of stream counts as data too
+pub fn has_input<T: Transport> (stream: &mut BufStream<T>) -> bool {
+    if stream.get_ref().set_nonblocking(true).is_err() {
+        return true;
+    }
+
+    let result = stream.fill_buf().map(|_| ());
+    // Failure here will show up on the next read
+    let _ = stream.get_ref().set_nonblocking(false);
+    return !matches!(result, Err(error) if error.kind() == ErrorKind::WouldBlock);
+}
+
+/// Socket read timeout is reported as `WouldBlock` on Unix and as `TimedOut` on Windows
+pub fn is_timeout (error: &io::Error) -> bool {
+    return matches!(error.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut);
+}
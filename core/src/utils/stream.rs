@@ -1,35 +1,40 @@
-use std::{net::TcpStream, io::BufRead};
-use bufstream::BufStream;
+use std::io::{BufRead, Read};
+use crate::utils::socket::is_timeout;
+
+pub enum ReadError {
+    /// Stream is closed or failed before the needle was found
+    Closed,
+    /// Needle wasn't found within the limit
+    TooLong,
+    /// Read deadline or timeout has passed
+    TimedOut
+}
 
 pub trait StreamUtils {
-    fn read_before (&mut self, needle: u8, cb: &mut Vec<u8>) -> Option<usize>;
-    fn read_string_before (&mut self, needle: char) -> Option<String>;
+    fn read_before (&mut self, needle: u8, cb: &mut Vec<u8>, limit: usize) -> Result<usize, ReadError>;
+    fn read_string_before (&mut self, needle: char, limit: usize) -> Result<String, ReadError>;
 }
 
-impl StreamUtils for BufStream<TcpStream> {
+impl<S: BufRead> StreamUtils for S {
+    /// Appends bytes before `needle` to `buffer` and consumes the needle itself,
+    /// at most `limit` bytes (including needle) are read from the stream
     #[inline]
-    fn read_before (&mut self, needle: u8, buffer: &mut Vec<u8>) -> Option<usize> {
-        let offset = buffer.len();
-        match self.read_until(needle as u8, buffer) {
-            Ok(mut len) => {
-                if len == 0 { None }
-                else {
-                    len = len + offset - 1;
-                    buffer.truncate(len);
-                    Some(len)
-                }
+    fn read_before (&mut self, needle: u8, buffer: &mut Vec<u8>, limit: usize) -> Result<usize, ReadError> {
+        match self.by_ref().take(limit as u64).read_until(needle, buffer) {
+            Ok(len) if len != 0 && buffer.last() == Some(&needle) => {
+                buffer.pop();
+                Ok(buffer.len())
             }
-            Err(_) => None
+            Ok(len) if len == limit => Err(ReadError::TooLong),
+            Err(error) if is_timeout(&error) => Err(ReadError::TimedOut),
+            _ => Err(ReadError::Closed)
         }
     }
 
     #[inline]
-    fn read_string_before (&mut self, needle: char) -> Option<String> {
+    fn read_string_before (&mut self, needle: char, limit: usize) -> Result<String, ReadError> {
         let mut buffer = Vec::new();
-        if let Some(_) = self.read_before(needle as u8, &mut buffer) {
-            return Some(String::from_utf8_lossy(&buffer).into_owned());
-        } else {
-            return None;
-        }
+        self.read_before(needle as u8, &mut buffer, limit)?;
+        return Ok(String::from_utf8_lossy(&buffer).into_owned());
     }
 }
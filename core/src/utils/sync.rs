@@ -9,6 +9,7 @@ pub trait LazyInit {
 ///
 /// Example:
 /// ```
+/// # use photonyx::utils::sync::{AppStatic, LazyInit};
 /// struct MyStatic {
 ///     pub a: u32
 /// }
@@ -30,6 +31,7 @@ pub struct AppStatic<T> {
 
 impl<T: LazyInit> AppStatic<T> {
     #[inline]
+    #[allow(clippy::new_without_default)]
     pub const fn new () -> Self {
         AppStatic { lock: LazyLock::new(T::init) }
     }
@@ -1,4 +1,5 @@
 use json::{object, JsonValue};
+use crate::http::query::Query;
 
 #[macro_export]
 macro_rules! validator_struct {
@@ -113,6 +114,54 @@ macro_rules! json_parse_impl {
 
 				return Ok(());
 			}
+
+			fn assign_coerced (&mut self, parsed: &json::JsonValue) -> Result<(), ValidationError> {
+				$($(
+					let name = stringify!($field);
+					$crate::json_coerce_impl!(name, self.$field, parsed[stringify!($field)], $type, $method);
+				)?)*
+
+				return Ok(());
+			}
+		}
+	};
+}
+
+/// Same as `json_parse_impl`, but bools and numbers may be given as strings
+#[macro_export]
+macro_rules! json_coerce_impl {
+	($name:ident, $value:expr, $input:expr, bool, $method:ident) => {
+		match $input.as_bool().or_else(|| $input.as_str().and_then($crate::http::query::parse_bool)) {
+			Some(value) => $value = value,
+			None => return Err(ValidationError {
+				message: "expected bool".to_owned(),
+				path: vec![$name.to_owned()]
+			})
+		}
+	};
+	($name:ident, $value:expr, $input:expr, String, $method:ident) => {
+		if let Some(value) = $input.as_str() {
+			$value = value.to_owned();
+		} else {
+			return Err(ValidationError {
+				message: "expected string".to_owned(),
+				path: vec![$name.to_owned()]
+			});
+		}
+	};
+	($name:ident, $value:expr, $input:expr, i32, $method:ident) => {
+		match $input.as_i32().or_else(|| $input.as_str().and_then(|value| value.trim().parse::<i32>().ok())) {
+			Some(value) => $value = value,
+			None => return Err(ValidationError {
+				message: "expected i32".to_owned(),
+				path: vec![$name.to_owned()]
+			})
+		}
+	};
+	($name:ident, $value:expr, $input:expr, $type:ty, nested) => {
+		if let Err(mut error) = $value.assign_coerced(&$input) {
+			error.path.insert(0, $name.to_owned());
+			return Err(error);
 		}
 	};
 }
@@ -139,6 +188,11 @@ impl ValidationError {
 pub trait ValidateJson {
 	fn parse_json (&mut self, raw: &str) -> Result<(), ValidationError>;
 	fn assign_json (&mut self, parsed: &json::JsonValue) -> Result<(), ValidationError>;
+	/// Like `assign_json`, but bools and numbers may be strings, as parameters of query are.
+	/// Types implementing the trait by hand get strict `assign_json` by default.
+	fn assign_coerced (&mut self, parsed: &json::JsonValue) -> Result<(), ValidationError> {
+		return self.assign_json(parsed);
+	}
 }
 
 pub trait Validate {
@@ -152,3 +206,12 @@ pub fn validate_json<T: Validate + ValidateJson + Default> (raw: &str) -> Result
 
 	return Ok(payload);
 }
+
+/// Validates parameters of query string or form body
+pub fn validate_query<T: Validate + ValidateJson + Default> (query: &Query) -> Result<T, ValidationError> {
+	let mut payload: T = Default::default();
+	payload.assign_coerced(&query.to_json())?;
+	payload.validate()?;
+
+	return Ok(payload);
+}
@@ -1,6 +1,10 @@
+use std::io::{self, ErrorKind};
+use std::time::Duration;
 use sha1::{Sha1, Digest};
+use tungstenite::protocol::frame::coding::CloseCode;
+use tungstenite::protocol::CloseFrame;
 use tungstenite::{Message, Error};
-use crate::{http::{entity::{Response, HttpHeaders, ResponseType, Request}, codes::HttpCode}, app::App, context::ws::SocketContext};
+use crate::{http::{entity::{Response, HttpHeaders, ResponseType, Request}, codes::HttpCode}, app::App, context::ws::SocketContext, utils::socket::{Socket, Transport}};
 
 type EventCallerType = dyn Fn(&mut SocketContext) + Sync + Send + 'static;
 
@@ -21,7 +25,8 @@ impl HandshakeResult {
             Response {
                 code: HttpCode::SwitchingProtocols,
                 headers: res_headers,
-                payload: ResponseType::Upgrade
+                payload: ResponseType::Upgrade,
+                compress: false
             }
         )
     }
@@ -56,27 +61,86 @@ pub fn websocket_handshake (app: &App, req: &Request) -> HandshakeResult {
     return HandshakeResult::ok(endpoint, res_headers);
 }
 
-pub fn maintain_websocket (app: &App, mut ctx: SocketContext, endpoint_index: usize) -> Result<(), ()> {
-    loop {
-        match ctx.stream.read_message() {
-            Ok(msg) => {
-                dispatch_websocket_message(app, &mut ctx, msg, endpoint_index);
-            },
-            Err(err) => {
-                if let Error::ConnectionClosed = err {
+/// Established WebSocket connection, messages are read only when they have arrived,
+/// so idle connection can wait in the event loop
+pub struct WebSocketSession {
+    pub ctx: SocketContext,
+    endpoint_index: usize,
+    is_ping_sent: bool
+}
+
+impl WebSocketSession {
+    const CLOSE_TIMEOUT: Duration = Duration::from_secs(1);
+
+    pub fn new (ctx: SocketContext, endpoint_index: usize) -> Self {
+        WebSocketSession { ctx, endpoint_index, is_ping_sent: false }
+    }
+
+    #[inline]
+    fn socket (&mut self) -> &mut Socket {
+        return self.ctx.stream.get_mut().get_mut();
+    }
+
+    /// Dispatches all messages that can be read without waiting, `Err` means connection is over
+    pub fn receive (&mut self, app: &App) -> io::Result<()> {
+        loop {
+            self.socket().set_nonblocking(true)?;
+            let result = self.ctx.stream.read_message();
+            // Handlers write their messages in blocking mode
+            self.socket().set_nonblocking(false)?;
+
+            match result {
+                Ok(msg) => {
+                    self.is_ping_sent = false;
+                    dispatch_websocket_message(app, &mut self.ctx, msg, self.endpoint_index);
+                }
+                Err(Error::Io(err)) if err.kind() == ErrorKind::WouldBlock => {
+                    // Automatic replies, e.g. pong, might be left unsent
+                    return self.ctx.stream.write_pending().map_err(into_io_error);
+                }
+                Err(Error::ConnectionClosed) => {
                     // todo: fire "close" event
-                    break;
+                    return Err(ErrorKind::ConnectionAborted.into());
+                }
+                Err(err) => {
+                    // todo: fire "error" event
+                    println!("WebSocket error! {:?}", err);
+                    return Err(into_io_error(err));
                 }
-
-                // todo: fire "error" event
-                println!("WebSocket error! {:?}", err);
             }
         }
     }
 
-    return Ok(());
+    /// Peer was silent for the whole timeout, so check if it's still alive,
+    /// and reap the connection if ping is left unanswered too
+    pub fn ping (&mut self) -> io::Result<()> {
+        if self.is_ping_sent {
+            // todo: fire "close" event
+            return Err(io::Error::new(ErrorKind::TimedOut, "ping left unanswered"));
+        }
+
+        self.ctx.stream.write_message(Message::Ping(Vec::new())).map_err(into_io_error)?;
+        self.is_ping_sent = true;
+        return Ok(());
+    }
+
+    /// Sends close frame and waits a bit for the peer to answer it, used when server is shutting down
+    pub fn close (&mut self) {
+        let frame = CloseFrame { code: CloseCode::Away, reason: "Server is shutting down".into() };
+        if self.ctx.stream.close(Some(frame)).is_err() || self.ctx.stream.write_pending().is_err() {
+            return;
+        }
+
+        if self.socket().get_stream().set_read_timeout(Some(WebSocketSession::CLOSE_TIMEOUT)).is_err() {
+            return;
+        }
+
+        // Messages sent before the peer noticed the close frame are dropped
+        while self.ctx.stream.read_message().is_ok() {}
+    }
 }
 
+#[allow(clippy::collapsible_match)]
 pub fn dispatch_websocket_message (app: &App, ctx: &mut SocketContext, msg: Message, endpoint_index: usize) {
     match msg {
         Message::Text(content) => {
@@ -101,6 +165,13 @@ pub fn dispatch_websocket_message (app: &App, ctx: &mut SocketContext, msg: Mess
     }
 }
 
+fn into_io_error (error: Error) -> io::Error {
+    return match error {
+        Error::Io(error) => error,
+        error => io::Error::other(error.to_string())
+    };
+}
+
 fn split_socket_message (payload: &str) -> (&str, &str) {
     if let Some(delim_index) = payload.find(':') {
         return (&payload[0..delim_index], &payload[delim_index+1..]);
@@ -113,6 +184,7 @@ pub struct WebSocketEndpoints(Vec<WebSocketEndpoint>);
 impl WebSocketEndpoints {
     pub const fn empty () -> Self { WebSocketEndpoints(Vec::new()) }
 
+    #[allow(clippy::manual_find)]
     pub fn get (&self, path: &str) -> Option<&WebSocketEndpoint> {
         for endpoint in &self.0 {
             if endpoint.path == path {
@@ -123,6 +195,7 @@ impl WebSocketEndpoints {
         return None;
     }
 
+    #[allow(clippy::manual_find)]
     pub fn get_pair (&self, path: &str) -> Option<(usize, &WebSocketEndpoint)> {
         for pair in self.0.iter().enumerate() {
             if pair.1.path == path {
@@ -167,6 +240,7 @@ impl WebSocketEndpoint {
 pub struct WebSocketHandlers(Vec<SocketEventHandler>);
 
 impl WebSocketHandlers {
+    #[allow(clippy::manual_find)]
     pub fn get (&self, name: &str) -> Option<&SocketEventHandler> {
         for handler in &self.0 {
             if handler.event == name {
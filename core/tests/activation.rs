@@ -0,0 +1,87 @@
+#![cfg(unix)]
+
+use std::fs;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::os::fd::OwnedFd;
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::Duration;
+use tempfile::TempDir;
+
+/// Server process with listener passed as fd 3, `pid` is put into `LISTEN_PID` as is, so `$$` makes it match
+fn spawn (dir: &TempDir, listener: TcpListener, pid: &str) -> Child {
+    let script = format!(
+        "exec 3<&0 0</dev/null; LISTEN_FDS=1 LISTEN_PID={pid} LISTEN_FDNAMES=admin exec {}",
+        env!("CARGO_BIN_EXE_photonyx")
+    );
+
+    return Command::new("sh")
+        .args(["-c", &script])
+        .current_dir(dir.path())
+        .stdin(Stdio::from(OwnedFd::from(listener)))
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+}
+
+/// Working directory with config listening on `address` and no modules
+fn workdir (address: SocketAddr) -> TempDir {
+    let dir = TempDir::new().unwrap();
+    fs::create_dir(dir.path().join("modules")).unwrap();
+    fs::write(
+        dir.path().join("config.json"),
+        format!(r#"{{ "listen": [{{ "address": "{address}", "name": "admin" }}], "workers": {{ "count": 1 }} }}"#)
+    ).unwrap();
+    return dir;
+}
+
+/// Sends request to unknown path, retrying while server is starting
+fn request (address: SocketAddr) -> String {
+    for _ in 0..50 {
+        let Ok(mut stream) = TcpStream::connect(address) else {
+            thread::sleep(Duration::from_millis(100));
+            continue;
+        };
+
+        stream.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+        stream.write_all(b"GET /missing HTTP/1.1\r\nHost: test\r\nConnection: close\r\n\r\n").unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        return response;
+    }
+
+    panic!("server isn't listening on {address}");
+}
+
+#[test]
+fn serves_inherited_listener () {
+    let inherited = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = inherited.local_addr().unwrap();
+    // Configured address is taken, so the server could only fail if it tried to bind it
+    let occupied = TcpListener::bind("127.0.0.1:0").unwrap();
+    let dir = workdir(occupied.local_addr().unwrap());
+
+    let mut server = spawn(&dir, inherited, "$$");
+    let response = request(address);
+    server.kill().unwrap();
+    server.wait().unwrap();
+
+    assert!(response.starts_with("HTTP/1.1 404"), "{response}");
+}
+
+#[test]
+fn falls_back_to_configured_bind () {
+    let inherited = TcpListener::bind("127.0.0.1:0").unwrap();
+    let free = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    let dir = workdir(free);
+
+    // Descriptors meant for another process are ignored
+    let mut server = spawn(&dir, inherited, "1");
+    let response = request(free);
+    server.kill().unwrap();
+    server.wait().unwrap();
+
+    assert!(response.starts_with("HTTP/1.1 404"), "{response}");
+}
@@ -0,0 +1,26 @@
+//! Helpers shared by integration tests, each test crate uses only a part of them
+#![allow(dead_code)]
+
+use std::net::{IpAddr, Ipv4Addr, TcpListener, TcpStream};
+use std::thread;
+use photonyx::http::entity::HttpConnection;
+use photonyx::http1::Http1Connection;
+use photonyx::utils::socket::{MemoryStream, Socket};
+
+/// HTTP/1 connection from local client, which has sent `data`
+pub fn http1<D: AsRef<[u8]>> (data: D) -> Http1Connection<MemoryStream> {
+    return Http1Connection::from_transport(MemoryStream::new(data.as_ref().to_vec()), IpAddr::V4(Ipv4Addr::LOCALHOST));
+}
+
+/// Everything written to the connection
+pub fn output (connection: Http1Connection<MemoryStream>) -> String {
+    return String::from_utf8(connection.into_stream().into_inner().unwrap().output).unwrap();
+}
+
+/// Accepts connection from client running `client` in another thread
+pub fn accept<C: FnOnce (TcpStream) + Send + 'static> (client: C) -> Socket {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    thread::spawn(move || client(TcpStream::connect(address).unwrap()));
+    return Socket::new(listener.accept().unwrap().0);
+}
@@ -0,0 +1,165 @@
+use std::io::{Read, Write};
+use flate2::Compression;
+use flate2::read::{GzDecoder, ZlibDecoder};
+use flate2::write::{DeflateEncoder, GzEncoder};
+use photonyx::app::config::CONFIG;
+use photonyx::http::codes::HttpCode;
+use photonyx::http::compression::{compress_response, decompress_request, ContentEncoding};
+use photonyx::http::entity::{HttpHeaders, HttpMethod, Request, Response, ResponseType};
+
+fn json_response (size: usize) -> Response {
+    let mut res = Response::from_status(HttpCode::OK);
+    res.headers = HttpHeaders::from_type("application/json; charset=utf-8");
+    res.payload = ResponseType::Payload(format!("[{}0]", "1,".repeat(size / 2)).into_bytes());
+    return res;
+}
+
+fn payload (res: &Response) -> &[u8] {
+    let ResponseType::Payload(payload) = &res.payload else {
+        panic!("payload expected");
+    };
+
+    return payload;
+}
+
+fn decode (encoding: &str, data: &[u8]) -> Vec<u8> {
+    let mut decoded = Vec::new();
+    match encoding {
+        "gzip" => GzDecoder::new(data).read_to_end(&mut decoded),
+        "deflate" => ZlibDecoder::new(data).read_to_end(&mut decoded),
+        "br" => brotli::Decompressor::new(data, 4096).read_to_end(&mut decoded),
+        _ => panic!("unknown encoding {encoding}")
+    }.unwrap();
+
+    return decoded;
+}
+
+#[test]
+fn negotiates_by_weight_and_preference () {
+    assert_eq!(ContentEncoding::negotiate("gzip, deflate, br"), Some(ContentEncoding::Brotli));
+    assert_eq!(ContentEncoding::negotiate("gzip;q=1.0, br;q=0.5"), Some(ContentEncoding::Gzip));
+    assert_eq!(ContentEncoding::negotiate("deflate"), Some(ContentEncoding::Deflate));
+    assert_eq!(ContentEncoding::negotiate("*;q=0.1, br;q=0"), Some(ContentEncoding::Gzip));
+    assert_eq!(ContentEncoding::negotiate("X-GZIP"), Some(ContentEncoding::Gzip));
+    assert_eq!(ContentEncoding::negotiate("identity"), None);
+    assert_eq!(ContentEncoding::negotiate("gzip;q=0"), None);
+    assert_eq!(ContentEncoding::negotiate(""), None);
+}
+
+#[test]
+fn compresses_allowed_payloads () {
+    for encoding in ["gzip", "deflate", "br"] {
+        let original = json_response(4096);
+        let mut res = json_response(4096);
+        compress_response(Some(encoding), &mut res);
+
+        assert_eq!(res.headers.get("content-encoding").as_deref(), Some(encoding));
+        assert_eq!(res.headers.get("vary").as_deref(), Some("accept-encoding"));
+        assert!(payload(&res).len() < payload(&original).len());
+        assert_eq!(decode(encoding, payload(&res)), payload(&original));
+    }
+}
+
+#[test]
+fn keeps_strong_validators_apart () {
+    let mut res = json_response(4096);
+    res.headers.set("etag".to_owned(), "\"v1\"".to_owned());
+    res.headers.set("vary".to_owned(), "Origin".to_owned());
+    compress_response(Some("gzip"), &mut res);
+
+    assert_eq!(res.headers.get("etag").as_deref(), Some("W/\"v1\""));
+    assert_eq!(res.headers.get("vary").as_deref(), Some("Origin, accept-encoding"));
+}
+
+#[test]
+fn leaves_other_responses_as_is () {
+    // Too small to be worth it
+    let mut res = json_response(100);
+    compress_response(Some("gzip"), &mut res);
+    assert_eq!(res.headers.get("content-encoding"), None);
+
+    // Not in types list
+    let mut res = json_response(4096);
+    res.headers.set("content-type".to_owned(), "image/png".to_owned());
+    compress_response(Some("gzip"), &mut res);
+    assert_eq!(res.headers.get("content-encoding"), None);
+    assert_eq!(res.headers.get("vary"), None);
+
+    // Opted out by handler
+    let mut res = json_response(4096);
+    res.compress = false;
+    compress_response(Some("gzip"), &mut res);
+    assert_eq!(res.headers.get("content-encoding"), None);
+
+    // Already encoded
+    let mut res = json_response(4096);
+    res.headers.set("content-encoding".to_owned(), "br".to_owned());
+    compress_response(Some("gzip"), &mut res);
+    assert_eq!(res.headers.get("content-encoding").as_deref(), Some("br"));
+    assert_eq!(payload(&res), payload(&json_response(4096)));
+
+    // Client doesn't accept any encoding, but caches still have to know the response varies
+    let mut res = json_response(4096);
+    compress_response(None, &mut res);
+    assert_eq!(res.headers.get("content-encoding"), None);
+    assert_eq!(res.headers.get("vary").as_deref(), Some("accept-encoding"));
+}
+
+fn compressed_request (encoding: &str, body: Vec<u8>) -> Request {
+    let mut req = Request::new(HttpMethod::POST, "/items".to_owned());
+    req.headers.set("content-encoding".to_owned(), encoding.to_owned());
+    req.headers.set("content-length".to_owned(), body.len().to_string());
+    req.body = body;
+    return req;
+}
+
+fn gzip (data: &[u8]) -> Vec<u8> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data).unwrap();
+    return encoder.finish().unwrap();
+}
+
+#[test]
+fn inflates_request_bodies () {
+    let json = br#"{"name": "item", "tags": ["a", "b", "c"]}"#;
+
+    let mut req = compressed_request("gzip", gzip(json));
+    assert!(decompress_request(&mut req).is_ok());
+    assert_eq!(req.body, json);
+    assert_eq!(req.headers.get("content-encoding"), None);
+    assert_eq!(req.headers.get("content-length"), Some(json.len().to_string()));
+
+    let mut req = compressed_request("deflate", ContentEncoding::Deflate.encode(json).unwrap());
+    assert!(decompress_request(&mut req).is_ok());
+    assert_eq!(req.body, json);
+
+    // Raw deflate stream without zlib wrapper
+    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(json).unwrap();
+    let mut req = compressed_request("deflate", encoder.finish().unwrap());
+    assert!(decompress_request(&mut req).is_ok());
+    assert_eq!(req.body, json);
+
+    // Codings are listed in the order they were applied
+    let mut req = compressed_request("deflate, identity, gzip", gzip(&ContentEncoding::Deflate.encode(json).unwrap()));
+    assert!(decompress_request(&mut req).is_ok());
+    assert_eq!(req.body, json);
+}
+
+#[test]
+fn rejects_bad_request_bodies () {
+    let mut req = compressed_request("br", ContentEncoding::Brotli.encode(b"data").unwrap());
+    assert!(matches!(decompress_request(&mut req), Err(HttpCode::UnsupportedMediaType)));
+
+    let mut req = compressed_request("gzip", b"definitely not gzip".to_vec());
+    assert!(matches!(decompress_request(&mut req), Err(HttpCode::BadRequest)));
+
+    // Small body inflating over the limit
+    let bomb = gzip(&vec![0; CONFIG.limits.decompressed_body + 1]);
+    assert!(bomb.len() < 64 * 1024);
+    let mut req = compressed_request("gzip", bomb);
+    assert!(matches!(decompress_request(&mut req), Err(HttpCode::RequestEntityTooLarge)));
+
+    let mut req = compressed_request("gzip", gzip(&vec![0; CONFIG.limits.decompressed_body]));
+    assert!(decompress_request(&mut req).is_ok());
+}
@@ -0,0 +1,12 @@
+use std::env;
+use photonyx::app::config::Config;
+
+#[test]
+fn missing_file_keeps_environment_overrides () {
+    env::set_var("PORT", "18090");
+    let mut config = Config::default();
+    config.load("missing-config.json");
+
+    assert_eq!(config.port, 18090);
+    assert!(config.listen.is_empty());
+}
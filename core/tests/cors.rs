@@ -0,0 +1,17 @@
+use photonyx::app::router::Router;
+use photonyx::http::codes::HttpCode;
+use photonyx::http::cors::Cors;
+use photonyx::http::entity::{HttpMethod, MethodSet, Request, Response, ResponseRet};
+
+#[test]
+fn preflight_allows_methods_of_the_route () {
+    let mut router = Router::empty();
+    router.register_methods(MethodSet::from(HttpMethod::GET), "/items/{id}".to_owned(), |_| ResponseRet::Return);
+    router.register_methods(MethodSet::from(HttpMethod::PATCH), "/items/{id}".to_owned(), |_| ResponseRet::Return);
+    router.register_methods(MethodSet::from(HttpMethod::DELETE), "/items/{id}".to_owned(), |_| ResponseRet::Return);
+
+    let req = Request::new(HttpMethod::OPTIONS, "/items/1".to_owned());
+    let mut res = Response::from_status(HttpCode::OK);
+    Cors::new(&req).apply_preflight(&mut res, router.allowed_methods(&req.path, ""));
+    assert_eq!(res.headers.get("Access-Control-Allow-Methods").as_deref(), Some("GET, HEAD, DELETE, OPTIONS, PATCH"));
+}
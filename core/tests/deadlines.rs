@@ -0,0 +1,90 @@
+use std::io::{BufReader, Read, Write};
+use std::net::{IpAddr, Ipv4Addr};
+use std::thread;
+use std::time::{Duration, Instant};
+use photonyx::app::config::CONFIG;
+use photonyx::http::codes::HttpCode;
+use photonyx::http::entity::{HttpConnection, ParsingResult, Response};
+use photonyx::http1::Http1Connection;
+use photonyx::utils::socket::Transport;
+use photonyx::utils::stream::{ReadError, StreamUtils};
+
+mod common;
+use common::{accept, http1};
+
+#[test]
+fn deadline_stops_trickled_input () {
+    let mut socket = accept(|mut client| {
+        for _ in 0..40 {
+            if client.write_all(b"G").is_err() {
+                return;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+    });
+
+    // Every read gets a byte in time, but the whole line doesn't
+    let start = Instant::now();
+    socket.set_deadline(Duration::from_millis(300)).unwrap();
+    let result = BufReader::new(socket).read_string_before('\n', 1024);
+
+    assert!(matches!(result, Err(ReadError::TimedOut)));
+    assert!(start.elapsed() >= Duration::from_millis(300));
+    assert!(start.elapsed() < Duration::from_millis(1500));
+}
+
+#[test]
+fn zero_deadline_removes_limit () {
+    let mut socket = accept(|mut client| {
+        thread::sleep(Duration::from_millis(400));
+        let _ = client.write_all(b"late\n");
+    });
+
+    socket.set_deadline(Duration::from_millis(200)).unwrap();
+    socket.set_deadline(Duration::ZERO).unwrap();
+    let result = BufReader::new(socket).read_string_before('\n', 1024);
+    assert_eq!(result.ok().as_deref(), Some("late"));
+}
+
+#[test]
+fn idle_timeout_depends_on_request_count () {
+    let data = b"GET / HTTP/1.1\r\n\r\n".to_vec();
+    let mut connection = http1(data);
+
+    // First request is limited by the header timeout since accept, next ones wait for keep-alive timeout
+    assert_eq!(connection.idle_timeout(), CONFIG.timeouts.header);
+    assert!(matches!(connection.parse(), ParsingResult::Complete(_)));
+    assert_eq!(connection.idle_timeout(), CONFIG.keep_alive.timeout);
+
+    // Idle client that goes away gets no response
+    assert!(matches!(connection.parse(), ParsingResult::Invalid));
+}
+
+#[test]
+fn body_cut_by_client_is_not_answered () {
+    let mut connection = Http1Connection::from_transport(accept(|mut client| {
+        let _ = client.write_all(b"POST / HTTP/1.1\r\nContent-Length: 10\r\n\r\nabc");
+    }), IpAddr::V4(Ipv4Addr::LOCALHOST));
+
+    assert!(matches!(connection.parse(), ParsingResult::Invalid));
+}
+
+#[test]
+fn disconnect_leaves_input_for_draining () {
+    let (sender, received) = std::sync::mpsc::channel();
+    let mut connection = Http1Connection::from_transport(accept(move |mut client| {
+        let _ = client.write_all(b"GET / HTTP/1.1\r\nConnection: close\r\n\r\nGET /unread HTTP/1.1\r\n\r\n");
+        let mut output = String::new();
+        let _ = client.read_to_string(&mut output);
+        sender.send(output).unwrap();
+    }), IpAddr::V4(Ipv4Addr::LOCALHOST));
+
+    assert!(matches!(connection.parse(), ParsingResult::Complete(_)));
+    connection.respond(Response::from_code(HttpCode::OK, "hello")).unwrap();
+    let _transport = connection.disconnect().unwrap();
+
+    // Client gets the whole response and the end of stream while unread request is still buffered
+    let output = received.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert!(output.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(output.ends_with("\r\n\r\nhello"));
+}
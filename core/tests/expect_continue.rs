@@ -0,0 +1,98 @@
+use photonyx::app::config::CONFIG;
+use photonyx::app::router::Router;
+use photonyx::http::codes::HttpCode;
+use photonyx::http::entity::{HttpConnection, HttpMethod, MethodSet, ParsingResult, Request, Response, ResponseRet};
+use photonyx::http1::Http1Connection;
+use photonyx::utils::socket::MemoryStream;
+
+mod common;
+use common::{http1, output};
+
+fn parse_head (connection: &mut Http1Connection<MemoryStream>) -> Request {
+    let ParsingResult::Complete(req) = connection.parse_head() else {
+        panic!("request expected");
+    };
+
+    return req;
+}
+
+fn output_is_empty (connection: &Http1Connection<MemoryStream>) -> bool {
+    return connection.get_transport().output.is_empty();
+}
+
+#[test]
+fn continue_is_sent_before_reading_body () {
+    let mut connection = http1("PUT /file HTTP/1.1\r\nExpect: 100-continue\r\nContent-Length: 4\r\n\r\ndata");
+    let mut req = parse_head(&mut connection);
+    assert!(output_is_empty(&connection));
+
+    assert!(connection.read_body(&mut req).is_ok());
+    assert_eq!(req.body, b"data");
+    connection.respond(Response::from_status(HttpCode::Created)).unwrap();
+    assert!(connection.is_persistent());
+
+    let output = output(connection);
+    assert!(output.starts_with("HTTP/1.1 100 Continue\r\n\r\nHTTP/1.1 201 Created\r\n"));
+}
+
+#[test]
+fn continue_is_sent_for_chunked_body () {
+    let mut connection = http1("POST / HTTP/1.1\r\nExpect: 100-Continue\r\nTransfer-Encoding: chunked\r\n\r\n1\r\nx\r\n0\r\n\r\n");
+    let mut req = parse_head(&mut connection);
+    assert!(connection.read_body(&mut req).is_ok());
+    assert_eq!(req.body, b"x");
+    assert_eq!(output(connection), "HTTP/1.1 100 Continue\r\n\r\n");
+}
+
+#[test]
+fn continue_is_not_sent_without_body_or_to_connection () {
+    let mut connection = http1("POST / HTTP/1.1\r\nExpect: 100-continue\r\nContent-Length: 0\r\n\r\n");
+    let mut req = parse_head(&mut connection);
+    assert!(connection.read_body(&mut req).is_ok());
+    assert_eq!(output(connection), "");
+
+    let mut connection = http1("POST / HTTP/1.0\r\nExpect: 100-continue\r\nContent-Length: 2\r\n\r\nok");
+    let mut req = parse_head(&mut connection);
+    assert!(connection.read_body(&mut req).is_ok());
+    assert_eq!(output(connection), "");
+}
+
+#[test]
+fn rejected_request_gets_final_response_only () {
+    let mut connection = http1("PUT /file HTTP/1.1\r\nExpect: 100-continue\r\nContent-Length: 4\r\n\r\ndata");
+    parse_head(&mut connection);
+    connection.respond(Response::from_status(HttpCode::Unauthorized)).unwrap();
+
+    // Body that client may still send would be parsed as the next request
+    assert!(!connection.is_persistent());
+    let output = output(connection);
+    assert!(output.starts_with("HTTP/1.1 401 Unauthorized\r\n"));
+    assert!(output.contains("connection: close\r\n"));
+    assert!(!output.contains("100 Continue"));
+}
+
+#[test]
+fn body_over_limit_is_rejected_without_continue () {
+    let data = format!("POST / HTTP/1.1\r\nExpect: 100-continue\r\nContent-Length: {}\r\n\r\n", CONFIG.limits.body + 1);
+    let mut connection = http1(&data);
+    let mut req = parse_head(&mut connection);
+
+    assert!(matches!(connection.read_body(&mut req), Err(ParsingResult::Error(HttpCode::RequestEntityTooLarge))));
+    assert_eq!(output(connection), "");
+}
+
+#[test]
+fn expectation_checks_are_matched_by_method_and_path () {
+    let mut router = Router::empty();
+    router.register_expect(MethodSet::from(HttpMethod::PUT), "/files/{name}".to_owned(), |_| ResponseRet::Return);
+
+    let (_, params) = router.match_expect(HttpMethod::PUT, "/files/a.txt", "").unwrap();
+    assert_eq!(params["name"], "a.txt");
+    assert!(router.match_expect(HttpMethod::POST, "/files/a.txt", "").is_none());
+    assert!(router.match_expect(HttpMethod::PUT, "/other", "").is_none());
+
+    // Check bound to a listener doesn't apply to requests from the others
+    router.expect_checks[0].listener = Some("admin".to_owned());
+    assert!(router.match_expect(HttpMethod::PUT, "/files/a.txt", "admin").is_some());
+    assert!(router.match_expect(HttpMethod::PUT, "/files/a.txt", "public").is_none());
+}
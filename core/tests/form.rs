@@ -0,0 +1,91 @@
+use std::net::{TcpListener, TcpStream};
+use std::collections::HashMap;
+use std::sync::Arc;
+use photonyx::app::config::UploadsConfig;
+use photonyx::context::http::HttpContext;
+use photonyx::http::entity::{HttpMethod, Request, ResponseRet};
+use photonyx::http::multipart::Multipart;
+use photonyx::http1::Http1Connection;
+use photonyx::utils::socket::Socket;
+use photonyx::validator_json;
+
+validator_json! {
+    Member,
+    age: i32 as range(0, 150),
+    role: String as str_enum("admin", "user")
+}
+
+fn context (content_type: &str, body: &str) -> HttpContext {
+    let mut req = Request::new(HttpMethod::POST, "/admin/members".to_owned());
+    req.headers.set("content-type".to_owned(), content_type.to_owned());
+    req.body = body.as_bytes().to_vec();
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let _client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (server, address) = listener.accept().unwrap();
+    let connection = Http1Connection::from_transport(Socket::new(server), address.ip());
+    return HttpContext::from(&connection, req, HashMap::new());
+}
+
+fn status (ctx: &HttpContext) -> &str {
+    return ctx.res.code.get_description().0;
+}
+
+#[test]
+fn parses_form_fields () {
+    let ctx = context("application/x-www-form-urlencoded; charset=UTF-8", "name=Jane+Doe&tag=a&tag=b&note=50%25");
+    let form = ctx.get_form().unwrap();
+    assert_eq!(form.get("name"), Some("Jane Doe"));
+    assert_eq!(form.get_all("tag"), vec!["a", "b"]);
+    assert_eq!(form.get("note"), Some("50%"));
+
+    assert!(context("application/json", "name=Jane").get_form().is_none());
+    assert!(context("multipart/form-data; boundary=x", "").get_form().is_none());
+}
+
+#[test]
+fn same_validator_serves_form_and_json () {
+    let mut ctx = context("application/x-www-form-urlencoded", "age=42&role=admin");
+    let ResponseRet::Result(member) = ctx.validate_body::<Member>() else {
+        panic!("form expected to be valid");
+    };
+    assert_eq!(member.age, 42);
+    assert_eq!(member.role, "admin");
+
+    let mut ctx = context("application/json", r#"{"age": 42, "role": "user"}"#);
+    let ResponseRet::Result(member) = ctx.validate_body::<Member>() else {
+        panic!("JSON expected to be valid");
+    };
+    assert_eq!(member.role, "user");
+}
+
+#[test]
+fn rejects_invalid_forms () {
+    let mut ctx = context("application/x-www-form-urlencoded", "age=200&role=admin");
+    assert!(matches!(ctx.validate_form::<Member>(), ResponseRet::Return));
+    assert_eq!(status(&ctx), "400");
+
+    let mut ctx = context("application/json", r#"{"age": 42, "role": "user"}"#);
+    assert!(matches!(ctx.validate_form::<Member>(), ResponseRet::Return));
+    assert_eq!(status(&ctx), "415");
+}
+
+#[test]
+fn multipart_fields_are_form_fields () {
+    let body = "--b\r\nContent-Disposition: form-data; name=\"age\"\r\n\r\n42\r\n\
+        --b\r\nContent-Disposition: form-data; name=\"role\"\r\n\r\nuser\r\n\
+        --b\r\nContent-Disposition: form-data; name=\"photo\"; filename=\"me.jpg\"\r\n\r\nJPEG\r\n--b--";
+
+    let mut ctx = context("multipart/form-data; boundary=b", "");
+    let multipart = Multipart::read(body.as_bytes(), "b", &UploadsConfig::default(), &mut |_| None).ok().unwrap();
+    ctx.req.multipart = Some(Arc::new(multipart));
+
+    assert_eq!(ctx.get_form().unwrap().get("role"), Some("user"));
+    assert_eq!(ctx.get_file("photo").unwrap().filename, "me.jpg");
+    assert!(ctx.get_file("role").is_none());
+
+    let ResponseRet::Result(member) = ctx.validate_body::<Member>() else {
+        panic!("multipart form expected to be valid");
+    };
+    assert_eq!(member.age, 42);
+}
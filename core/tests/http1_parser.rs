@@ -0,0 +1,237 @@
+use photonyx::app::config::CONFIG;
+use photonyx::http::codes::HttpCode;
+use photonyx::http::entity::{HttpConnection, HttpMethod, ParsingResult, Request, Response};
+use proptest::prelude::*;
+
+mod common;
+use common::http1;
+
+fn parse_all (data: Vec<u8>) -> Vec<Request> {
+    let mut connection = http1(data);
+    let mut requests = Vec::new();
+    while let ParsingResult::Complete(req) = connection.parse() {
+        requests.push(req);
+    }
+
+    return requests;
+}
+
+fn method () -> impl Strategy<Value = HttpMethod> {
+    return proptest::sample::select(HttpMethod::ALL.to_vec());
+}
+
+fn encode_chunked (body: &[u8], chunk_size: usize) -> Vec<u8> {
+    let mut encoded = Vec::new();
+    for chunk in body.chunks(chunk_size) {
+        encoded.extend(format!("{:x}\r\n", chunk.len()).as_bytes());
+        encoded.extend(chunk);
+        encoded.extend(b"\r\n");
+    }
+
+    encoded.extend(b"0\r\n\r\n");
+    return encoded;
+}
+
+fn parse_one (data: &str) -> ParsingResult {
+    return http1(data).parse();
+}
+
+fn is_error (result: ParsingResult, code: HttpCode) -> bool {
+    return matches!(result, ParsingResult::Error(result_code) if result_code.get_description() == code.get_description());
+}
+
+#[test]
+fn chunked_body_is_decoded () {
+    // Extensions are ignored, hex sizes are case insensitive
+    let data = "POST /a HTTP/1.1\r\nTransfer-Encoding: Chunked\r\n\r\n5;name=value\r\nhello\r\nA\r\n, chunked!\r\n0;last\r\nX-Checksum: 1\r\n\r\n";
+    let ParsingResult::Complete(req) = parse_one(data) else {
+        panic!("request expected");
+    };
+    assert_eq!(req.body, b"hello, chunked!");
+    assert!(req.headers.get("x-checksum").is_none());
+}
+
+#[test]
+fn trailers_do_not_replace_headers () {
+    let data = "POST /a HTTP/1.1\r\nTransfer-Encoding: chunked\r\nContent-Type: text/plain\r\n\r\n2\r\nhi\r\n0\r\nContent-Type: application/json\r\nTransfer-Encoding: identity\r\n\r\nGET /next HTTP/1.1\r\n\r\n";
+    let requests = parse_all(data.as_bytes().to_vec());
+
+    assert_eq!(requests.len(), 2);
+    assert_eq!(requests[0].headers.get("content-type").as_deref(), Some("text/plain"));
+    assert_eq!(requests[0].headers.get("transfer-encoding").as_deref(), Some("chunked"));
+    assert_eq!(requests[1].path, "/next");
+
+    // Trailer section is still limited like headers
+    let data = format!("POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n0\r\n{}\r\n", "X-Trailer: 1\r\n".repeat(CONFIG.limits.headers + 1));
+    assert!(is_error(parse_one(&data), HttpCode::RequestHeaderFieldsTooLarge));
+}
+
+#[test]
+fn malformed_chunked_body_is_rejected () {
+    assert!(is_error(parse_one("POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\nzz\r\nhello\r\n0\r\n\r\n"), HttpCode::BadRequest));
+    assert!(is_error(parse_one("POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n-5\r\nhello\r\n0\r\n\r\n"), HttpCode::BadRequest));
+    assert!(is_error(parse_one("POST / HTTP/1.1\r\nTransfer-Encoding: chunked, gzip\r\n\r\n0\r\n\r\n"), HttpCode::BadRequest));
+    assert!(is_error(parse_one("POST / HTTP/1.1\r\nTransfer-Encoding: gzip, chunked\r\n\r\n0\r\n\r\n"), HttpCode::NotImplemented));
+    // Chunk longer than its size has no CRLF where it's expected
+    assert!(matches!(parse_one("POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n2\r\nhello\r\n0\r\n\r\n"), ParsingResult::Invalid));
+    // Stream ending before the last chunk
+    assert!(matches!(parse_one("POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhel"), ParsingResult::Invalid));
+}
+
+#[test]
+fn body_without_length_is_rejected () {
+    assert!(is_error(parse_one("POST / HTTP/1.1\r\n\r\nhello"), HttpCode::LengthRequired));
+    assert!(is_error(parse_one("PUT / HTTP/1.1\r\n\r\nhello"), HttpCode::LengthRequired));
+    assert!(is_error(parse_one("POST / HTTP/1.1\r\nContent-Length: five\r\n\r\nhello"), HttpCode::BadRequest));
+    // Methods without body semantics don't need the length
+    assert!(matches!(parse_one("GET / HTTP/1.1\r\n\r\n"), ParsingResult::Complete(_)));
+    assert!(matches!(parse_one("DELETE / HTTP/1.1\r\n\r\n"), ParsingResult::Complete(_)));
+}
+
+#[test]
+fn ambiguous_framing_is_rejected () {
+    let conflicting = "POST / HTTP/1.1\r\nContent-Length: 5\r\nContent-Length: 3\r\n\r\nhello";
+    assert!(is_error(parse_one(conflicting), HttpCode::BadRequest));
+    let both = "POST / HTTP/1.1\r\nContent-Length: 5\r\nTransfer-Encoding: chunked\r\n\r\n0\r\n\r\n";
+    assert!(is_error(parse_one(both), HttpCode::BadRequest));
+    let both = "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\nContent-Length: 0\r\n\r\n0\r\n\r\n";
+    assert!(is_error(parse_one(both), HttpCode::BadRequest));
+
+    for len in ["+5", "-5", " 5 5", "0x5", "5,5", ""] {
+        let data = format!("POST / HTTP/1.1\r\nContent-Length: {len}\r\n\r\nhello");
+        assert!(is_error(parse_one(&data), HttpCode::BadRequest), "{len:?} must be rejected");
+    }
+
+    // Exact copy of the length is harmless
+    let ParsingResult::Complete(req) = parse_one("POST / HTTP/1.1\r\nContent-Length: 5\r\ncontent-length: 5\r\n\r\nhello") else {
+        panic!("request expected");
+    };
+    assert_eq!(req.body, b"hello");
+}
+
+#[test]
+fn malformed_header_lines_are_rejected () {
+    for header in ["Content-Length : 5", " Content-Length: 5", "X Header: 1", "X-Header\t: 1", ": empty", "no colon"] {
+        let data = format!("GET / HTTP/1.1\r\n{header}\r\n\r\n");
+        assert!(is_error(parse_one(&data), HttpCode::BadRequest), "{header:?} must be rejected");
+    }
+
+    // Obsolete line folding
+    assert!(is_error(parse_one("GET / HTTP/1.1\r\nX-Header: a\r\n b\r\n\r\n"), HttpCode::BadRequest));
+    assert!(is_error(parse_one("GET / HTTP/1.1\r\nX-Header: a\r\n\tb\r\n\r\n"), HttpCode::BadRequest));
+}
+
+#[test]
+fn repeated_headers_are_combined () {
+    let ParsingResult::Complete(req) = parse_one("GET / HTTP/1.1\r\nAccept: text/html\r\nX-Other: 1\r\naccept:\tapplication/json \r\n\r\n") else {
+        panic!("request expected");
+    };
+    assert_eq!(req.headers.get("accept").as_deref(), Some("text/html, application/json"));
+
+    // Host isn't a list, even the same value twice is rejected
+    assert!(is_error(parse_one("GET / HTTP/1.1\r\nHost: a.test\r\nHost: b.test\r\n\r\n"), HttpCode::BadRequest));
+    assert!(is_error(parse_one("GET / HTTP/1.1\r\nHost: a.test\r\nhost: a.test\r\n\r\n"), HttpCode::BadRequest));
+}
+
+#[test]
+fn oversized_requests_are_rejected () {
+    let limits = &CONFIG.limits;
+
+    let uri = format!("/{}", "a".repeat(limits.uri - 1));
+    assert!(matches!(parse_one(&format!("GET {uri} HTTP/1.1\r\n\r\n")), ParsingResult::Complete(_)));
+    assert!(is_error(parse_one(&format!("GET {uri}a HTTP/1.1\r\n\r\n")), HttpCode::URITooLong));
+
+    let value = "v".repeat(limits.header_line - 3);
+    assert!(matches!(parse_one(&format!("GET / HTTP/1.1\r\nX: {value}\r\n\r\n")), ParsingResult::Complete(_)));
+    assert!(is_error(parse_one(&format!("GET / HTTP/1.1\r\nX: {value}v\r\n\r\n")), HttpCode::RequestHeaderFieldsTooLarge));
+
+    let headers = "X-Header: 1\r\n".repeat(limits.headers);
+    assert!(matches!(parse_one(&format!("GET / HTTP/1.1\r\n{headers}\r\n")), ParsingResult::Complete(_)));
+    assert!(is_error(parse_one(&format!("GET / HTTP/1.1\r\n{headers}X-Header: 1\r\n\r\n")), HttpCode::RequestHeaderFieldsTooLarge));
+
+    // Body over the limit is rejected before anything is allocated or read
+    let data = format!("POST / HTTP/1.1\r\nContent-Length: {}\r\n\r\n", limits.body + 1);
+    assert!(is_error(parse_one(&data), HttpCode::RequestEntityTooLarge));
+    let data = format!("POST / HTTP/1.1\r\nContent-Length: {}\r\n\r\n", usize::MAX);
+    assert!(is_error(parse_one(&data), HttpCode::RequestEntityTooLarge));
+
+    let data = format!("POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n{:x}\r\n", limits.body + 1);
+    assert!(is_error(parse_one(&data), HttpCode::RequestEntityTooLarge));
+    let data = format!("POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n{:x}\r\n", u128::MAX);
+    assert!(is_error(parse_one(&data), HttpCode::RequestEntityTooLarge));
+}
+
+proptest! {
+    #[test]
+    fn arbitrary_bytes_do_not_panic (data in proptest::collection::vec(any::<u8>(), 0..512)) {
+        parse_all(data);
+    }
+
+    #[test]
+    fn mangled_request_does_not_panic (
+        prefix in "(GET|POST|PUT) /[a-z0-9/?=&%]{0,16} HTTP/1\\.[0-9]\r\n",
+        headers in "([A-Za-z-]{1,12}:[ -~]{0,24}\r\n){0,6}",
+        tail in proptest::collection::vec(any::<u8>(), 0..64)
+    ) {
+        let mut data = prefix.into_bytes();
+        data.extend(headers.into_bytes());
+        data.extend(b"\r\n");
+        data.extend(tail);
+        parse_all(data);
+    }
+
+    #[test]
+    fn content_length_body_round_trips (
+        method in method(),
+        path in "/[a-z0-9/]{0,24}",
+        query in "[a-z0-9=&]{0,16}",
+        body in proptest::collection::vec(any::<u8>(), 0..256)
+    ) {
+        let url = if query.is_empty() { path.clone() } else { format!("{path}?{query}") };
+        let mut data = format!("{} {url} HTTP/1.1\r\nHost: test\r\nContent-Length: {}\r\n\r\n", method.as_str(), body.len()).into_bytes();
+        data.extend(&body);
+
+        let requests = parse_all(data);
+        prop_assert_eq!(requests.len(), 1);
+        prop_assert_eq!(requests[0].method, method);
+        prop_assert_eq!(&requests[0].path, &path);
+        prop_assert_eq!(&requests[0].query, &query);
+        prop_assert_eq!(&requests[0].body, &body);
+        prop_assert_eq!(requests[0].headers.get("host"), Some("test".to_string()));
+    }
+
+    #[test]
+    fn pipelined_responses_keep_order (paths in proptest::collection::vec("/[a-z0-9]{1,12}", 1..8)) {
+        let mut data = Vec::new();
+        for path in &paths {
+            data.extend(format!("GET {path} HTTP/1.1\r\n\r\n").as_bytes());
+        }
+
+        let mut connection = http1(data);
+        while let ParsingResult::Complete(req) = connection.parse() {
+            prop_assert!(connection.respond(Response::from_code(HttpCode::OK, &req.path)).is_ok());
+        }
+
+        let output = String::from_utf8(connection.into_stream().into_inner().unwrap().output).unwrap();
+        let bodies: Vec<&str> = output.split("HTTP/1.1 200 OK").skip(1)
+            .map(|res| res.split("\r\n\r\n").nth(1).unwrap_or_default())
+            .collect();
+
+        prop_assert_eq!(bodies, paths);
+    }
+
+    #[test]
+    fn chunk_splitting_preserves_body (
+        body in proptest::collection::vec(any::<u8>(), 0..512),
+        chunk_size in 1usize..64
+    ) {
+        let mut data = b"POST /upload HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n".to_vec();
+        data.extend(encode_chunked(&body, chunk_size));
+        data.extend(b"GET /next HTTP/1.1\r\n\r\n");
+
+        let requests = parse_all(data);
+        prop_assert_eq!(requests.len(), 2);
+        prop_assert_eq!(&requests[0].body, &body);
+        prop_assert_eq!(&requests[1].path, "/next");
+    }
+}
@@ -0,0 +1,267 @@
+use std::io::Write;
+use std::net::{IpAddr, Ipv4Addr, TcpListener, TcpStream};
+use std::time::{Duration, Instant};
+use bufstream::BufStream;
+use photonyx::http::codes::HttpCode;
+use photonyx::http::entity::{HttpConnection, ParsingResult, Response};
+use photonyx::http2::Http2Connection;
+use photonyx::app::config::CONFIG;
+use photonyx::http2::frame::{ErrorCode, Frame, FrameKind, FLAG_END_HEADERS, FLAG_END_STREAM};
+use photonyx::http2::hpack::{Decoder, Encoder};
+use photonyx::utils::socket::{MemoryStream, Socket};
+use proptest::prelude::*;
+
+const PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+fn connection (data: Vec<u8>) -> Http2Connection<MemoryStream> {
+    let stream = BufStream::new(MemoryStream::new(data));
+    return Http2Connection::from_stream(stream, IpAddr::V4(Ipv4Addr::LOCALHOST), true);
+}
+
+fn client_frames (frames: &[(FrameKind, u8, u32, Vec<u8>)]) -> Vec<u8> {
+    let mut data = PREFACE.to_vec();
+    Frame::write(&mut data, FrameKind::Settings, 0, 0, &[]).unwrap();
+    for (kind, flags, stream_id, payload) in frames {
+        Frame::write(&mut data, *kind, *flags, *stream_id, payload).unwrap();
+    }
+
+    return data;
+}
+
+fn request_block (method: &str, path: &str) -> Vec<u8> {
+    return Encoder::encode([(":method", method), (":scheme", "http"), (":path", path), (":authority", "test")]);
+}
+
+/// Window updates sent by server as `(stream_id, increment)`, the initial connection one is skipped
+fn window_updates (output: &[u8]) -> Vec<(u32, u32)> {
+    let mut reader = output;
+    let mut updates = Vec::new();
+    while let Ok(frame) = Frame::read(&mut reader, Frame::DEFAULT_MAX_SIZE) {
+        if frame.kind == FrameKind::WindowUpdate as u8 {
+            updates.push((frame.stream_id, frame.read_u32(0).unwrap()));
+        }
+    }
+
+    return updates.split_off(1);
+}
+
+fn data_frames (stream_id: u32, len: usize, end_stream: bool) -> Vec<(FrameKind, u8, u32, Vec<u8>)> {
+    let mut frames: Vec<_> = (0..len).step_by(Frame::DEFAULT_MAX_SIZE)
+        .map(|offset| (FrameKind::Data, 0, stream_id, vec![b'x'; (len - offset).min(Frame::DEFAULT_MAX_SIZE)]))
+        .collect();
+    if end_stream {
+        frames.push((FrameKind::Data, FLAG_END_STREAM, stream_id, Vec::new()));
+    }
+
+    return frames;
+}
+
+#[test]
+fn decodes_rfc_huffman_example () {
+    // RFC 7541 C.4.1
+    let block = [0x82, 0x86, 0x84, 0x41, 0x8c, 0xf1, 0xe3, 0xc2, 0xe5, 0xf2, 0x3a, 0x6b, 0xa0, 0xab, 0x90, 0xf4, 0xff];
+    let fields = Decoder::new().decode(&block).ok().unwrap();
+    assert_eq!(fields, vec![
+        (":method".to_owned(), "GET".to_owned()),
+        (":scheme".to_owned(), "http".to_owned()),
+        (":path".to_owned(), "/".to_owned()),
+        (":authority".to_owned(), "www.example.com".to_owned())
+    ]);
+}
+
+#[test]
+fn answers_multiplexed_streams () {
+    let mut post_head = request_block("POST", "/upload");
+    post_head.extend(Encoder::encode([("content-type", "text/plain")]));
+    let data = client_frames(&[
+        (FrameKind::Headers, FLAG_END_HEADERS, 1, post_head),
+        (FrameKind::Headers, FLAG_END_HEADERS | FLAG_END_STREAM, 3, request_block("GET", "/first?a=1")),
+        (FrameKind::Data, FLAG_END_STREAM, 1, b"body".to_vec())
+    ]);
+
+    let mut connection = connection(data);
+    let mut paths = Vec::new();
+    while let ParsingResult::Complete(req) = connection.parse() {
+        assert_eq!(req.headers.get("host"), Some("test".to_owned()));
+        paths.push((req.path.clone(), req.body.clone()));
+        connection.respond(Response::from_code(HttpCode::OK, &req.path)).unwrap();
+    }
+
+    assert_eq!(paths, vec![("/first".to_owned(), Vec::new()), ("/upload".to_owned(), b"body".to_vec())]);
+
+    let output = connection.into_stream().into_inner().unwrap().output;
+    let mut reader = output.as_slice();
+    let mut bodies = Vec::new();
+    while let Ok(frame) = Frame::read(&mut reader, Frame::DEFAULT_MAX_SIZE) {
+        if frame.kind == FrameKind::Data as u8 {
+            assert!(frame.has_flag(FLAG_END_STREAM));
+            bodies.push((frame.stream_id, frame.payload));
+        }
+    }
+
+    assert_eq!(bodies, vec![(3, b"/first".to_vec()), (1, b"/upload".to_vec())]);
+}
+
+/// Block adding a large field to the dynamic table and then referencing it `repeat` times with one byte each
+fn bomb_block (repeat: usize) -> Vec<u8> {
+    let mut block = request_block("GET", "/");
+    // Literal with incremental indexing instead of the one without it, so the field becomes the first dynamic entry
+    let mut field = Encoder::encode([("x-bomb", "b".repeat(4000).as_str())]);
+    field[0] = 0x40;
+    block.extend(field);
+    block.extend(std::iter::repeat_n(0x80 | 62, repeat));
+    return block;
+}
+
+#[test]
+fn repeated_fields_are_joined_like_http1 () {
+    let mut block = request_block("GET", "/");
+    block.extend(Encoder::encode([("accept", "text/html"), ("cookie", "a=1"), ("accept", "application/json"), ("cookie", "b=2")]));
+    let mut connection = connection(client_frames(&[(FrameKind::Headers, FLAG_END_HEADERS | FLAG_END_STREAM, 1, block)]));
+
+    let ParsingResult::Complete(req) = connection.parse() else {
+        panic!("request expected");
+    };
+    assert_eq!(req.headers.get("accept").as_deref(), Some("text/html, application/json"));
+    assert_eq!(req.headers.get("cookie").as_deref(), Some("a=1; b=2"));
+}
+
+#[test]
+fn silent_client_does_not_block_parsing () {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let socket = Socket::new(listener.accept().unwrap().0);
+    let mut connection = Http2Connection::from_stream(BufStream::new(socket), IpAddr::V4(Ipv4Addr::LOCALHOST), true);
+
+    client.write_all(&client_frames(&[(FrameKind::Headers, FLAG_END_HEADERS, 1, request_block("POST", "/"))])).unwrap();
+    let start = Instant::now();
+    // Request waits for its body, which hasn't come yet
+    assert!(matches!(connection.parse_head(), ParsingResult::Partial));
+    assert!(start.elapsed() < Duration::from_secs(1));
+    assert!(!connection.has_pending_input());
+
+    client.write_all(&client_frames(&[(FrameKind::Data, FLAG_END_STREAM, 1, b"body".to_vec())])[PREFACE.len() + 9..]).unwrap();
+    assert!(connection.has_pending_input());
+    let ParsingResult::Complete(req) = connection.parse() else {
+        panic!("request expected");
+    };
+    assert_eq!(req.body, b"body");
+}
+
+#[test]
+fn header_list_size_is_limited () {
+    let block = bomb_block(10);
+    let fields = Decoder::with_list_limit(64 * 1024).decode(&block).ok().unwrap();
+    assert_eq!(fields.iter().filter(|(name, _)| name == "x-bomb").count(), 11);
+
+    // Small block expands far beyond the limit
+    let block = bomb_block(1000);
+    assert!(block.len() < 5200);
+    assert!(Decoder::with_list_limit(64 * 1024).decode(&block).is_err());
+
+    let limit = CONFIG.limits.header_line * CONFIG.limits.headers;
+    let data = client_frames(&[(FrameKind::Headers, FLAG_END_HEADERS | FLAG_END_STREAM, 1, bomb_block(limit / 1000))]);
+    let mut connection = connection(data);
+    assert!(matches!(connection.parse(), ParsingResult::Invalid));
+
+    let output = connection.into_stream().into_inner().unwrap().output;
+    let mut reader = output.as_slice();
+    let mut goaway = None;
+    while let Ok(frame) = Frame::read(&mut reader, Frame::DEFAULT_MAX_SIZE) {
+        if frame.kind == FrameKind::GoAway as u8 {
+            goaway = frame.read_u32(4);
+        }
+    }
+
+    assert_eq!(goaway, Some(ErrorCode::CompressionError as u32));
+}
+
+#[test]
+fn body_window_is_given_back_when_read () {
+    // Upload fills its window before its turn, the next request is served meanwhile
+    let mut frames = vec![(FrameKind::Headers, FLAG_END_HEADERS, 1, request_block("POST", "/upload"))];
+    frames.extend(data_frames(1, 65535, false));
+    frames.push((FrameKind::Headers, FLAG_END_HEADERS | FLAG_END_STREAM, 3, request_block("GET", "/")));
+    frames.extend(data_frames(1, 10, true));
+
+    let mut connection = connection(client_frames(&frames));
+    let ParsingResult::Complete(mut upload) = connection.parse_head() else {
+        panic!("request expected");
+    };
+    assert_eq!(upload.path, "/upload");
+    // Nothing is given back before the handler takes the body
+    assert!(window_updates(&connection.get_transport().output).is_empty());
+
+    assert!(connection.read_body(&mut upload).is_ok());
+    assert_eq!(upload.body.len(), 65545);
+    connection.respond(Response::from_status(HttpCode::OK)).unwrap();
+
+    let ParsingResult::Complete(next) = connection.parse() else {
+        panic!("request expected");
+    };
+    assert_eq!(next.path, "/");
+
+    let output = connection.into_stream().into_inner().unwrap().output;
+    assert_eq!(window_updates(&output), vec![(0, 65535), (1, 65535), (0, 10), (1, 10)]);
+}
+
+#[test]
+fn dropped_data_returns_connection_window_only () {
+    let mut frames = vec![(FrameKind::Headers, FLAG_END_HEADERS, 1, request_block("POST", "/a"))];
+    frames.extend(data_frames(1, 100, false));
+    frames.push((FrameKind::RstStream, 0, 1, (ErrorCode::Cancel as u32).to_be_bytes().to_vec()));
+    // Data sent before client saw its own reset
+    frames.extend(data_frames(1, 20, false));
+    // Client ignoring the window gets the stream reset
+    frames.push((FrameKind::Headers, FLAG_END_HEADERS, 3, request_block("POST", "/b")));
+    frames.extend(data_frames(3, 65536, false));
+
+    let mut connection = connection(client_frames(&frames));
+    assert!(matches!(connection.parse(), ParsingResult::Invalid));
+
+    let output = connection.into_stream().into_inner().unwrap().output;
+    let updates = window_updates(&output);
+    assert!(updates.iter().all(|(stream_id, _)| *stream_id == 0));
+    assert_eq!(updates.iter().map(|(_, increment)| increment).sum::<u32>(), 100 + 20 + 65536);
+
+    let mut reader = output.as_slice();
+    let mut resets = Vec::new();
+    while let Ok(frame) = Frame::read(&mut reader, Frame::DEFAULT_MAX_SIZE) {
+        if frame.kind == FrameKind::RstStream as u8 {
+            resets.push((frame.stream_id, frame.read_u32(0).unwrap()));
+        }
+    }
+
+    assert_eq!(resets, vec![(3, ErrorCode::FlowControlError as u32)]);
+}
+
+proptest! {
+    #[test]
+    fn arbitrary_frames_do_not_panic (frames in proptest::collection::vec((0u8..10, any::<u8>(), 0u32..8, proptest::collection::vec(any::<u8>(), 0..64)), 0..16)) {
+        let mut data = PREFACE.to_vec();
+        for (kind, flags, stream_id, payload) in frames {
+            let len = payload.len() as u32;
+            data.extend(&len.to_be_bytes()[1..]);
+            data.extend([kind, flags]);
+            data.extend(stream_id.to_be_bytes());
+            data.extend(payload);
+        }
+
+        let mut connection = connection(data);
+        while let ParsingResult::Complete(_) = connection.parse() {
+            let _ = connection.respond(Response::from_status(HttpCode::OK));
+        }
+    }
+
+    #[test]
+    fn arbitrary_header_blocks_do_not_panic (block in proptest::collection::vec(any::<u8>(), 0..256)) {
+        let _ = Decoder::new().decode(&block);
+    }
+
+    #[test]
+    fn header_block_round_trips (fields in proptest::collection::vec(("[a-z-]{1,16}", "[ -~]{0,32}"), 0..16)) {
+        let block = Encoder::encode(fields.iter().map(|(name, value)| (name.as_str(), value.as_str())));
+        let decoded = Decoder::new().decode(&block).ok().unwrap();
+        prop_assert_eq!(decoded, fields);
+    }
+}
@@ -0,0 +1,86 @@
+use photonyx::http::codes::HttpCode;
+use photonyx::http::entity::{HttpConnection, ParsingResult, Response};
+
+mod common;
+use common::{http1, output};
+
+/// Answers every request of the stream with `respond`, returns written responses and persistence after each of them
+fn serve (data: &str, respond: fn () -> Response) -> (String, Vec<bool>) {
+    let mut connection = http1(data);
+    let mut persistence = Vec::new();
+    while let ParsingResult::Complete(_) = connection.parse() {
+        connection.respond(respond()).unwrap();
+        persistence.push(connection.is_persistent());
+        if !connection.is_persistent() {
+            break;
+        }
+    }
+
+    return (output(connection), persistence);
+}
+
+fn hello () -> Response {
+    return Response::from_code(HttpCode::OK, "hello");
+}
+
+#[test]
+fn http11_connection_is_persistent_by_default () {
+    let (output, persistence) = serve("GET /a HTTP/1.1\r\n\r\nGET /b HTTP/1.1\r\nHost: x\r\n\r\n", hello);
+
+    assert_eq!(persistence, vec![true, true]);
+    assert_eq!(output.matches("HTTP/1.1 200 OK\r\n").count(), 2);
+    assert_eq!(output.matches("connection: keep-alive\r\n").count(), 2);
+    assert!(output.contains("keep-alive: timeout="));
+    assert!(output.contains("date: "));
+    // Each body is framed by its length, so the next response starts right after it
+    assert_eq!(output.matches("content-length: 5\r\n").count(), 2);
+    assert!(output.contains("\r\n\r\nhelloHTTP/1.1 200 OK"));
+    assert!(output.ends_with("\r\n\r\nhello"));
+}
+
+#[test]
+fn connection_close_ends_the_connection () {
+    let (output, persistence) = serve("GET /a HTTP/1.1\r\nConnection: Close\r\n\r\nGET /b HTTP/1.1\r\n\r\n", hello);
+
+    // Pipelined request after `Connection: close` is left unanswered
+    assert_eq!(persistence, vec![false]);
+    assert_eq!(output.matches("HTTP/1.1 200 OK").count(), 1);
+    assert!(output.contains("connection: close\r\n"));
+    assert!(!output.contains("keep-alive"));
+}
+
+#[test]
+fn http10_connection_closes_unless_asked_to_keep_alive () {
+    let (output, persistence) = serve("GET / HTTP/1.0\r\n\r\nGET / HTTP/1.0\r\n\r\n", hello);
+    assert_eq!(persistence, vec![false]);
+    assert!(output.starts_with("HTTP/1.0 200 OK\r\n"));
+    assert!(output.contains("connection: close\r\n"));
+
+    let (output, persistence) = serve("GET / HTTP/1.0\r\nConnection: keep-alive\r\n\r\nGET / HTTP/1.0\r\n\r\n", hello);
+    assert_eq!(persistence, vec![true, false]);
+    assert_eq!(output.matches("HTTP/1.0 200 OK").count(), 2);
+    assert!(output.contains("connection: keep-alive\r\n"));
+}
+
+#[test]
+fn responses_without_payload_are_framed () {
+    let (output, _) = serve("GET / HTTP/1.1\r\n\r\n", || Response::from_status(HttpCode::OK));
+    assert!(output.contains("content-length: 0\r\n"));
+
+    // Status that can't have a body has no length either
+    let (output, persistence) = serve("DELETE / HTTP/1.1\r\n\r\n", || Response::from_status(HttpCode::NoContent));
+    assert_eq!(persistence, vec![true]);
+    assert!(output.starts_with("HTTP/1.1 204 No Content\r\n"));
+    assert!(!output.contains("content-length"));
+}
+
+#[test]
+fn connection_closes_after_max_requests () {
+    let data = "GET / HTTP/1.1\r\n\r\n".repeat(101);
+    let (output, persistence) = serve(&data, hello);
+
+    assert_eq!(persistence.len(), 100);
+    assert!(persistence[..99].iter().all(|persistent| *persistent));
+    assert!(!persistence[99]);
+    assert_eq!(output.matches("connection: close\r\n").count(), 1);
+}
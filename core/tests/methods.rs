@@ -0,0 +1,75 @@
+use photonyx::app::router::{RouteMatch, Router};
+use photonyx::http::codes::HttpCode;
+use photonyx::http::entity::{HttpConnection, HttpMethod, MethodSet, ParsingResult, Response, ResponseRet};
+
+mod common;
+use common::{http1, output};
+
+#[test]
+fn all_standard_methods_are_parsed () {
+    for method in HttpMethod::ALL {
+        assert_eq!(HttpMethod::from_str(method.as_str()), Some(method));
+        let ParsingResult::Complete(req) = http1(format!("{} / HTTP/1.1\r\nContent-Length: 0\r\n\r\n", method.as_str())).parse() else {
+            panic!("{} request expected", method.as_str());
+        };
+        assert_eq!(req.method, method);
+    }
+
+    // Methods are case sensitive
+    assert!(HttpMethod::from_str("get").is_none());
+    assert!(matches!(http1("BREW / HTTP/1.1\r\n\r\n").parse(), ParsingResult::Error(HttpCode::NotImplemented)));
+}
+
+#[test]
+fn body_is_read_for_any_method_with_framing () {
+    for method in ["PUT", "PATCH", "DELETE", "GET", "OPTIONS"] {
+        let data = format!("{method} /item HTTP/1.1\r\nContent-Length: 4\r\n\r\ndataGET /next HTTP/1.1\r\n\r\n");
+        let mut connection = http1(&data);
+        let ParsingResult::Complete(req) = connection.parse() else {
+            panic!("{method} request expected");
+        };
+        assert_eq!(req.body, b"data");
+
+        // Body isn't mistaken for the next request
+        let ParsingResult::Complete(next) = connection.parse() else {
+            panic!("next request expected");
+        };
+        assert_eq!(next.path, "/next");
+    }
+
+    let data = "DELETE /item HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n2\r\nid\r\n0\r\n\r\n";
+    let ParsingResult::Complete(req) = http1(data).parse() else {
+        panic!("request expected");
+    };
+    assert_eq!(req.body, b"id");
+}
+
+#[test]
+fn head_is_served_by_get_route () {
+    let mut router = Router::empty();
+    router.register_methods(MethodSet::from(HttpMethod::GET), "/page".to_owned(), |_| ResponseRet::Return);
+    router.register_methods(MethodSet::from(HttpMethod::POST), "/form".to_owned(), |_| ResponseRet::Return);
+
+    assert!(matches!(router.match_route(HttpMethod::HEAD, "/page", ""), RouteMatch::Found(_, _)));
+    assert!(matches!(router.match_route(HttpMethod::HEAD, "/form", ""), RouteMatch::MethodNotAllowed(_)));
+}
+
+#[test]
+fn head_response_has_get_headers_without_body () {
+    let mut get = http1("GET / HTTP/1.1\r\n\r\n");
+    let mut head = http1("HEAD / HTTP/1.1\r\n\r\n");
+    for connection in [&mut get, &mut head] {
+        let ParsingResult::Complete(_) = connection.parse() else {
+            panic!("request expected");
+        };
+        connection.respond(Response::from_code(HttpCode::OK, "hello")).unwrap();
+        assert!(connection.is_persistent());
+    }
+
+    // Responses may be sent in different seconds
+    let without_date = |connection| output(connection).lines().filter(|line| !line.starts_with("date: ")).collect::<Vec<_>>().join("\n");
+    let get = without_date(get);
+    let head = without_date(head);
+    assert!(head.contains("content-length: 5\n"));
+    assert_eq!(format!("{head}\nhello"), get);
+}
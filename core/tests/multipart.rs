@@ -0,0 +1,242 @@
+use std::fs;
+use std::io::{self, Read, Write};
+use std::sync::{Arc, Mutex};
+use photonyx::app::config::UploadsConfig;
+use photonyx::http::entity::{HttpConnection, ParsingResult};
+use photonyx::http::multipart::{boundary, Multipart, MultipartError, PartInfo};
+use proptest::prelude::*;
+use tempfile::TempDir;
+
+mod common;
+use common::http1;
+
+const BOUNDARY: &str = "----form7MA4YWxk";
+
+/// Gives data in pieces of the same size, so delimiters get split between reads
+struct SlowReader<'a> {
+    data: &'a [u8],
+    step: usize
+}
+
+impl Read for SlowReader<'_> {
+    fn read (&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let count = self.step.min(buf.len()).min(self.data.len());
+        buf[..count].copy_from_slice(&self.data[..count]);
+        self.data = &self.data[count..];
+        return Ok(count);
+    }
+}
+
+/// Collects file written by sink
+#[derive(Clone, Default)]
+struct SharedSink(Arc<Mutex<Vec<u8>>>);
+
+impl Write for SharedSink {
+    fn write (&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        return Ok(buf.len());
+    }
+
+    fn flush (&mut self) -> io::Result<()> {
+        return Ok(());
+    }
+}
+
+fn config (dir: &TempDir) -> UploadsConfig {
+    let mut config = UploadsConfig::default();
+    config.dir = dir.path().to_path_buf();
+    return config;
+}
+
+fn body (parts: &[(&str, Option<&str>, &[u8])]) -> Vec<u8> {
+    let mut body = Vec::new();
+    for (name, filename, data) in parts {
+        body.extend(format!("--{BOUNDARY}\r\n").as_bytes());
+        match filename {
+            Some(filename) => body.extend(format!("Content-Disposition: form-data; name=\"{name}\"; filename=\"{filename}\"\r\nContent-Type: text/plain\r\n\r\n").as_bytes()),
+            None => body.extend(format!("Content-Disposition: form-data; name=\"{name}\"\r\n\r\n").as_bytes())
+        }
+
+        body.extend(*data);
+        body.extend(b"\r\n");
+    }
+
+    body.extend(format!("--{BOUNDARY}--\r\n").as_bytes());
+    return body;
+}
+
+fn read (data: &[u8], config: &UploadsConfig) -> Result<Multipart, MultipartError> {
+    return Multipart::read(data, BOUNDARY, config, &mut |_| None);
+}
+
+#[test]
+fn parses_fields_and_files () {
+    let dir = TempDir::new().unwrap();
+    let mut data = b"preamble\r\n".to_vec();
+    data.extend(body(&[
+        ("title", None, b"Hello, world"),
+        ("tag", None, b"a"),
+        ("tag", None, b"b"),
+        ("doc", Some("notes.txt"), b"line 1\r\n--line 2\r\n"),
+        ("empty", Some(""), b"")
+    ]));
+    data.extend(b"epilogue");
+
+    let multipart = Multipart::read(SlowReader { data: &data, step: 7 }, BOUNDARY, &config(&dir), &mut |_| None).unwrap();
+    assert_eq!(multipart.fields().get("title"), Some("Hello, world"));
+    assert_eq!(multipart.fields().get_all("tag"), vec!["a", "b"]);
+    assert_eq!(multipart.files().len(), 2);
+
+    let file = multipart.file("doc").unwrap();
+    assert_eq!(file.filename, "notes.txt");
+    assert_eq!(file.content_type.as_deref(), Some("text/plain"));
+    assert_eq!(file.size, 18);
+    let path = file.path().unwrap().to_path_buf();
+    assert!(path.starts_with(dir.path()));
+    assert_eq!(fs::read(&path).unwrap(), b"line 1\r\n--line 2\r\n");
+
+    assert_eq!(multipart.file("empty").unwrap().size, 0);
+
+    // Temporary files live as long as the form
+    drop(multipart);
+    assert!(!path.exists());
+}
+
+#[test]
+fn parses_quoted_parameters () {
+    let dir = TempDir::new().unwrap();
+    let data = format!(
+        "--{BOUNDARY}\r\ncontent-disposition: form-data; filename=\"a; \\\"b\\\".txt\"; name=\"upload\"\r\n\r\nx\r\n--{BOUNDARY}--"
+    );
+
+    let multipart = read(data.as_bytes(), &config(&dir)).unwrap();
+    assert_eq!(multipart.file("upload").unwrap().filename, "a; \"b\".txt");
+
+    assert_eq!(boundary("multipart/form-data; boundary=abc").as_deref(), Some("abc"));
+    assert_eq!(boundary("Multipart/Form-Data; charset=utf-8; boundary=\"a b;c\"").as_deref(), Some("a b;c"));
+    assert!(boundary("multipart/mixed; boundary=abc").is_none());
+    assert!(boundary("multipart/form-data").is_none());
+    assert!(boundary(&format!("multipart/form-data; boundary={}", "x".repeat(71))).is_none());
+}
+
+#[test]
+fn sends_files_to_sink () {
+    let dir = TempDir::new().unwrap();
+    let sink = SharedSink::default();
+    let data = body(&[("avatar", Some("me.png"), b"\x89PNG"), ("note", Some("note.txt"), b"text")]);
+
+    let mut parts = Vec::new();
+    let multipart = Multipart::read(data.as_slice(), BOUNDARY, &config(&dir), &mut |part: &PartInfo| {
+        parts.push(part.name.clone());
+        return (part.name == "avatar").then(|| Box::new(sink.clone()) as Box<dyn Write>);
+    }).unwrap();
+
+    assert_eq!(parts, vec!["avatar", "note"]);
+    assert_eq!(*sink.0.lock().unwrap(), b"\x89PNG");
+    let avatar = multipart.file("avatar").unwrap();
+    assert!(avatar.path().is_none());
+    assert_eq!(avatar.size, 4);
+    assert!(avatar.persist(dir.path().join("kept")).is_err());
+
+    // Persisted file stays after the form is gone
+    let target = dir.path().join("note.txt");
+    multipart.file("note").unwrap().persist(&target).unwrap();
+    drop(multipart);
+    assert_eq!(fs::read(&target).unwrap(), b"text");
+}
+
+#[test]
+fn enforces_limits () {
+    let dir = TempDir::new().unwrap();
+    let data = body(&[("name", None, b"0123456789"), ("file", Some("f.bin"), &[7; 100])]);
+    assert!(read(&data, &config(&dir)).is_ok());
+
+    let mut limited = config(&dir);
+    limited.field_size = 9;
+    assert!(matches!(read(&data, &limited), Err(MultipartError::TooLarge)));
+
+    let mut limited = config(&dir);
+    limited.part_size = 99;
+    assert!(matches!(read(&data, &limited), Err(MultipartError::TooLarge)));
+
+    let mut limited = config(&dir);
+    limited.total_size = data.len() as u64 - 1;
+    assert!(matches!(read(&data, &limited), Err(MultipartError::TooLarge)));
+
+    let mut limited = config(&dir);
+    limited.parts = 1;
+    assert!(matches!(read(&data, &limited), Err(MultipartError::TooLarge)));
+
+    // Files of rejected form are removed
+    assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+}
+
+#[test]
+fn rejects_malformed_bodies () {
+    let dir = TempDir::new().unwrap();
+    let config = config(&dir);
+    let data = body(&[("name", None, b"value")]);
+
+    // Missing closing delimiter
+    assert!(matches!(read(&data[..data.len() - 6], &config), Err(MultipartError::Malformed)));
+    assert!(matches!(read(b"no delimiter at all", &config), Err(MultipartError::Malformed)));
+
+    let no_name = format!("--{BOUNDARY}\r\nContent-Disposition: form-data\r\n\r\nx\r\n--{BOUNDARY}--");
+    assert!(matches!(read(no_name.as_bytes(), &config), Err(MultipartError::Malformed)));
+
+    let no_disposition = format!("--{BOUNDARY}\r\nContent-Type: text/plain\r\n\r\nx\r\n--{BOUNDARY}--");
+    assert!(matches!(read(no_disposition.as_bytes(), &config), Err(MultipartError::Malformed)));
+
+    let garbage_after_delimiter = format!("--{BOUNDARY}x\r\nContent-Disposition: form-data; name=a\r\n\r\nx\r\n--{BOUNDARY}--");
+    assert!(matches!(read(garbage_after_delimiter.as_bytes(), &config), Err(MultipartError::Malformed)));
+}
+
+#[test]
+fn streams_body_from_http1_connection () {
+    let data = body(&[("file", Some("f.txt"), b"contents")]);
+    let mut stream = format!(
+        "POST /upload HTTP/1.1\r\nContent-Type: multipart/form-data; boundary={BOUNDARY}\r\nContent-Length: {}\r\n\r\n",
+        data.len()
+    ).into_bytes();
+    stream.extend(&data);
+    stream.extend(b"GET /next HTTP/1.1\r\n\r\n");
+
+    let mut connection = http1(stream);
+    let ParsingResult::Complete(req) = connection.parse_head() else {
+        panic!("request expected");
+    };
+
+    let sink = SharedSink::default();
+    let multipart = connection.read_multipart(&req, BOUNDARY, &mut |_| Some(Box::new(sink.clone()))).unwrap().unwrap();
+    assert_eq!(multipart.file("file").unwrap().size, 8);
+    assert_eq!(*sink.0.lock().unwrap(), b"contents");
+
+    // Body is consumed exactly, so the next request is parsed from where it ends
+    let ParsingResult::Complete(next) = connection.parse_head() else {
+        panic!("next request expected");
+    };
+    assert_eq!(next.path, "/next");
+
+    // Chunked body can't be streamed
+    let chunked = format!("POST /upload HTTP/1.1\r\nContent-Type: multipart/form-data; boundary={BOUNDARY}\r\nTransfer-Encoding: chunked\r\n\r\n0\r\n\r\n");
+    let mut connection = http1(chunked);
+    let ParsingResult::Complete(req) = connection.parse_head() else {
+        panic!("request expected");
+    };
+    assert!(connection.read_multipart(&req, BOUNDARY, &mut |_| None).is_none());
+}
+
+proptest! {
+    #[test]
+    fn file_round_trips_across_reads (
+        contents in proptest::collection::vec(prop_oneof![Just(b'\r'), Just(b'\n'), Just(b'-'), any::<u8>()], 0..512),
+        step in 1usize..64
+    ) {
+        let sink = SharedSink::default();
+        let data = body(&[("file", Some("f.bin"), &contents)]);
+
+        let multipart = Multipart::read(SlowReader { data: &data, step }, BOUNDARY, &UploadsConfig::default(), &mut |_| Some(Box::new(sink.clone()))).unwrap();
+        prop_assert_eq!(multipart.file("file").unwrap().size, contents.len() as u64);
+        prop_assert_eq!(&*sink.0.lock().unwrap(), &contents);
+    }
+}
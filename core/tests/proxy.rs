@@ -0,0 +1,144 @@
+use std::io::{Cursor, Read};
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+use photonyx::http::entity::HttpHeaders;
+use photonyx::http::forwarded::ClientInfo;
+use photonyx::utils::proxy::{read_proxy_header, Cidr};
+
+/// Parses header from `input`, returns address and data left after it
+fn read (input: &[u8]) -> (std::io::Result<Option<SocketAddr>>, Vec<u8>) {
+    let mut stream = Cursor::new(input.to_vec());
+    let result = read_proxy_header(&mut stream);
+    let mut rest = Vec::new();
+    stream.read_to_end(&mut rest).unwrap();
+    return (result, rest);
+}
+
+fn v2 (command: u8, family: u8, addresses: &[u8]) -> Vec<u8> {
+    let mut header = b"\r\n\r\n\0\r\nQUIT\n".to_vec();
+    header.extend([0x20 | command, family]);
+    header.extend((addresses.len() as u16).to_be_bytes());
+    header.extend(addresses);
+    header.extend(b"GET /");
+    return header;
+}
+
+fn headers (list: &[(&str, &str)]) -> HttpHeaders {
+    let mut headers = HttpHeaders::empty();
+    for (name, value) in list {
+        headers.set(name.to_string(), value.to_string());
+    }
+
+    return headers;
+}
+
+fn ip (address: &str) -> IpAddr {
+    return address.parse().unwrap();
+}
+
+#[test]
+fn reads_v1_header () {
+    let (result, rest) = read(b"PROXY TCP4 192.0.2.1 198.51.100.1 56324 443\r\nGET /");
+    assert_eq!(result.unwrap(), Some("192.0.2.1:56324".parse().unwrap()));
+    assert_eq!(rest, b"GET /");
+
+    let (result, _) = read(b"PROXY TCP6 2001:db8::1 2001:db8::2 4711 443\r\n");
+    assert_eq!(result.unwrap(), Some("[2001:db8::1]:4711".parse().unwrap()));
+
+    let (result, rest) = read(b"PROXY UNKNOWN\r\nGET /");
+    assert_eq!(result.unwrap(), None);
+    assert_eq!(rest, b"GET /");
+}
+
+#[test]
+fn reads_v2_header () {
+    let mut ipv4 = vec![192, 0, 2, 1, 198, 51, 100, 1];
+    ipv4.extend(56324u16.to_be_bytes());
+    ipv4.extend(443u16.to_be_bytes());
+    // TLV after addresses is skipped
+    ipv4.extend([0x04, 0x00, 0x01, 0xFF]);
+
+    let (result, rest) = read(&v2(1, 0x11, &ipv4));
+    assert_eq!(result.unwrap(), Some("192.0.2.1:56324".parse().unwrap()));
+    assert_eq!(rest, b"GET /");
+
+    let mut ipv6 = "2001:db8::1".parse::<Ipv6Addr>().unwrap().octets().to_vec();
+    ipv6.extend([0; 16]);
+    ipv6.extend(4711u16.to_be_bytes());
+    ipv6.extend(443u16.to_be_bytes());
+    let (result, _) = read(&v2(1, 0x21, &ipv6));
+    assert_eq!(result.unwrap(), Some("[2001:db8::1]:4711".parse().unwrap()));
+
+    // Health check of balancer itself
+    let (result, rest) = read(&v2(0, 0x00, &[]));
+    assert_eq!(result.unwrap(), None);
+    assert_eq!(rest, b"GET /");
+}
+
+#[test]
+fn rejects_missing_or_broken_header () {
+    assert!(read(b"GET / HTTP/1.1\r\n\r\n").0.is_err());
+    assert!(read(b"PROXY TCP4 not-an-ip 198.51.100.1 1 2\r\n").0.is_err());
+    assert!(read(&[b"PROXY TCP4 ".as_slice(), &[b'1'; 200]].concat()).0.is_err());
+    assert!(read(b"PROXY TCP4 192.0.2.1").0.is_err());
+}
+
+#[test]
+fn matches_networks () {
+    let network = "10.0.0.0/8".parse::<Cidr>().unwrap();
+    assert!(network.contains(ip("10.1.2.3")));
+    assert!(network.contains(ip("::ffff:10.1.2.3")));
+    assert!(!network.contains(ip("11.0.0.1")));
+
+    let single = "2001:db8::1".parse::<Cidr>().unwrap();
+    assert!(single.contains(ip("2001:db8::1")));
+    assert!(!single.contains(ip("2001:db8::2")));
+    assert!("0.0.0.0/0".parse::<Cidr>().unwrap().contains(ip("203.0.113.9")));
+
+    assert!("10.0.0.0/33".parse::<Cidr>().is_err());
+    assert!("example.com".parse::<Cidr>().is_err());
+}
+
+#[test]
+fn ignores_headers_from_untrusted_peer () {
+    let trusted = ["10.0.0.0/8".parse().unwrap()];
+    let headers = headers(&[("host", "api.test"), ("x-forwarded-for", "203.0.113.9"), ("x-forwarded-proto", "https")]);
+
+    let client = ClientInfo::resolve(ip("198.51.100.7"), false, &headers, &trusted);
+    assert_eq!(client, ClientInfo { address: ip("198.51.100.7"), scheme: "http".to_owned(), host: Some("api.test".to_owned()) });
+}
+
+#[test]
+fn walks_x_forwarded_chain () {
+    let trusted = ["10.0.0.0/8".parse().unwrap()];
+    let headers = headers(&[
+        ("host", "internal"),
+        // Leftmost entry is spoofable by client, so the first untrusted one from the right is taken
+        ("x-forwarded-for", "192.0.2.66, 203.0.113.9, 10.0.0.5"),
+        ("x-forwarded-proto", "https"),
+        ("x-forwarded-host", "api.test")
+    ]);
+
+    let client = ClientInfo::resolve(ip("::ffff:10.0.0.1"), false, &headers, &trusted);
+    assert_eq!(client.address, ip("203.0.113.9"));
+    assert_eq!(client.scheme, "https");
+    assert_eq!(client.host.as_deref(), Some("api.test"));
+}
+
+#[test]
+fn prefers_forwarded_header () {
+    let trusted = ["10.0.0.0/8".parse().unwrap()];
+    let headers = headers(&[
+        ("host", "internal"),
+        ("forwarded", r#"for="[2001:db8::7]:4711";proto=https;host=api.test, for=10.0.0.5;proto=http"#),
+        ("x-forwarded-for", "192.0.2.66")
+    ]);
+
+    let client = ClientInfo::resolve(ip("10.0.0.1"), false, &headers, &trusted);
+    assert_eq!(client.address, ip("2001:db8::7"));
+    assert_eq!(client.scheme, "https");
+    assert_eq!(client.host.as_deref(), Some("api.test"));
+
+    // Obfuscated client keeps the address of the last trusted hop
+    let headers = self::headers(&[("forwarded", "for=_hidden, for=10.0.0.5")]);
+    assert_eq!(ClientInfo::resolve(ip("10.0.0.1"), true, &headers, &trusted).address, ip("10.0.0.5"));
+}
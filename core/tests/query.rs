@@ -0,0 +1,119 @@
+use photonyx::http::query::Query;
+use photonyx::utils::validator::{validate_json, validate_query, Validate, ValidateJson, ValidationError};
+use photonyx::validator_json;
+
+validator_json! {
+    Paging,
+    page: i32 as range(1, 1000),
+    exact: bool,
+    sort: String as str_enum("name", "date")
+}
+
+#[test]
+fn parses_and_decodes_pairs () {
+    let query = Query::parse("q=caf%C3%A9+au+lait&tag=a&tag=b&&flag&empty=&bad=100%&plus=%2B");
+
+    assert_eq!(query.get("q"), Some("café au lait"));
+    assert_eq!(query.get("tag"), Some("a"));
+    assert_eq!(query.get_all("tag"), vec!["a", "b"]);
+    assert_eq!(query.get("flag"), Some(""));
+    assert_eq!(query.get("empty"), Some(""));
+    // Malformed sequence is kept as it is
+    assert_eq!(query.get("bad"), Some("100%"));
+    assert_eq!(query.get("plus"), Some("+"));
+    assert_eq!(query.get("missing"), None);
+    assert!(query.has("flag"));
+    assert_eq!(query.len(), 7);
+    assert!(Query::parse("").is_empty());
+
+    let pairs = query.iter().take(2).collect::<Vec<_>>();
+    assert_eq!(pairs, vec![("q", "café au lait"), ("tag", "a")]);
+}
+
+#[test]
+fn parses_typed_values () {
+    let query = Query::parse("page=3&ratio=0.5&limit=ten&on=yes&off=0&flag&maybe=perhaps");
+
+    assert_eq!(query.get_parsed::<u32>("page").ok(), Some(Some(3)));
+    assert_eq!(query.get_parsed::<f64>("ratio").ok(), Some(Some(0.5)));
+    assert_eq!(query.get_parsed::<u32>("missing").ok(), Some(None));
+
+    let error = query.get_parsed::<u32>("limit").err().unwrap();
+    assert_eq!(error.message, "expected u32");
+    assert_eq!(error.path, vec!["limit"]);
+
+    assert_eq!(query.get_bool("on").ok(), Some(Some(true)));
+    assert_eq!(query.get_bool("off").ok(), Some(Some(false)));
+    assert_eq!(query.get_bool("flag").ok(), Some(Some(true)));
+    assert!(query.get_bool("maybe").is_err());
+}
+
+#[test]
+fn converts_to_json () {
+    let json = Query::parse("a=1&b=x&b=y&b=z").to_json();
+    assert_eq!(json["a"], "1");
+    assert_eq!(json["b"].len(), 3);
+    assert_eq!(json["b"][2], "z");
+}
+
+#[test]
+fn validates_with_coercion () {
+    let paging = validate_query::<Paging>(&Query::parse("page=20&exact=true&sort=date")).ok().unwrap();
+    assert_eq!(paging.page, 20);
+    assert_eq!(paging.sort, "date");
+    // Field without validation method is skipped as in JSON
+    assert!(!paging.exact);
+    assert!(!validate_json::<Paging>("{\"page\": 20, \"exact\": true, \"sort\": \"date\"}").ok().unwrap().exact);
+
+    // Fields required in JSON are required in query as well
+    let error = validate_query::<Paging>(&Query::parse("page=20")).err().unwrap();
+    assert_eq!(error.message, "expected string");
+    assert_eq!(error.path, vec!["sort"]);
+
+    let error = validate_query::<Paging>(&Query::parse("page=0&sort=name")).err().unwrap();
+    assert_eq!(error.path, vec!["page"]);
+
+    let error = validate_query::<Paging>(&Query::parse("page=x&sort=name")).err().unwrap();
+    assert_eq!(error.message, "expected i32");
+
+    let error = validate_query::<Paging>(&Query::parse("page=1&sort=size")).err().unwrap();
+    assert_eq!(error.path, vec!["sort"]);
+
+    // Repeated parameter isn't a single value
+    let error = validate_query::<Paging>(&Query::parse("page=1&page=2&sort=name")).err().unwrap();
+    assert_eq!(error.path, vec!["page"]);
+}
+
+/// Implemented by hand without `assign_coerced`, so query values are assigned strictly
+#[derive(Default)]
+struct Token {
+    value: String
+}
+
+impl ValidateJson for Token {
+    fn parse_json (&mut self, raw: &str) -> Result<(), ValidationError> {
+        return self.assign_json(&json::parse(raw).unwrap_or(json::JsonValue::Null));
+    }
+
+    fn assign_json (&mut self, parsed: &json::JsonValue) -> Result<(), ValidationError> {
+        let Some(value) = parsed["value"].as_str() else {
+            return Err(ValidationError { message: "expected string".to_owned(), path: vec!["value".to_owned()] });
+        };
+
+        self.value = value.to_owned();
+        return Ok(());
+    }
+}
+
+impl Validate for Token {
+    fn validate (&self) -> Result<(), ValidationError> {
+        return Ok(());
+    }
+}
+
+#[test]
+fn manual_implementation_validates_query_strictly () {
+    let token = validate_query::<Token>(&Query::parse("value=abc")).ok().unwrap();
+    assert_eq!(token.value, "abc");
+    assert!(validate_query::<Token>(&Query::parse("other=abc")).is_err());
+}
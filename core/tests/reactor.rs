@@ -0,0 +1,157 @@
+use std::io::{Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::mpsc::{channel, Sender};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use photonyx::utils::reactor::{Idle, LoadStats, Reactor};
+use photonyx::utils::socket::Stream;
+use threadpool::ThreadPool;
+
+struct TestConnection {
+    stream: Stream,
+    timeout: Duration,
+    events: Sender<String>
+}
+
+impl Idle for TestConnection {
+    fn socket (&self) -> &Stream { &self.stream }
+    fn timeout (&self) -> Duration { self.timeout }
+
+    fn resume (mut self: Box<Self>) -> Option<Box<dyn Idle>> {
+        let mut buf = [0u8; 64];
+        let len = self.stream.read(&mut buf).ok()?;
+        if len == 0 {
+            self.events.send("closed".to_owned()).unwrap();
+            return None;
+        }
+
+        self.events.send(String::from_utf8_lossy(&buf[..len]).into_owned()).unwrap();
+        return Some(self);
+    }
+
+    fn expire (self: Box<Self>) -> Option<Box<dyn Idle>> {
+        self.events.send("expired".to_owned()).unwrap();
+        return None;
+    }
+
+    fn close (self: Box<Self>) {
+        self.events.send("closing".to_owned()).unwrap();
+    }
+}
+
+fn socket_pair () -> (TcpStream, Stream) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    return (client, listener.accept().unwrap().0.into());
+}
+
+fn start (stats: &'static LoadStats, stop: &'static AtomicBool) -> Arc<Reactor> {
+    let reactor = Arc::new(Reactor::new(ThreadPool::new(2), stats).unwrap());
+    let runner = reactor.clone();
+    thread::spawn(move || runner.run(stop));
+    return reactor;
+}
+
+const WAIT: Duration = Duration::from_secs(5);
+
+#[test]
+fn resumes_connections_with_input () {
+    static STATS: LoadStats = LoadStats::new();
+    static STOP: AtomicBool = AtomicBool::new(false);
+    let reactor = start(&STATS, &STOP);
+    let (events, received) = channel();
+
+    let mut clients = Vec::new();
+    for _ in 0..100 {
+        let (client, server) = socket_pair();
+        reactor.park(Box::new(TestConnection { stream: server, timeout: Duration::ZERO, events: events.clone() }));
+        clients.push(client);
+    }
+
+    assert_eq!(STATS.waiting(), 100);
+
+    clients[42].write_all(b"first").unwrap();
+    assert_eq!(received.recv_timeout(WAIT).unwrap(), "first");
+    // Resumed connection is parked again
+    clients[42].write_all(b"second").unwrap();
+    assert_eq!(received.recv_timeout(WAIT).unwrap(), "second");
+
+    clients[7].shutdown(Shutdown::Write).unwrap();
+    assert_eq!(received.recv_timeout(WAIT).unwrap(), "closed");
+    assert!(received.recv_timeout(Duration::from_millis(300)).is_err());
+    assert_eq!(STATS.waiting(), 99);
+    assert_eq!(STATS.queued(), 0);
+}
+
+#[cfg(unix)]
+#[test]
+fn resumes_unix_connections () {
+    static STATS: LoadStats = LoadStats::new();
+    static STOP: AtomicBool = AtomicBool::new(false);
+    let reactor = start(&STATS, &STOP);
+    let (events, received) = channel();
+
+    let (mut client, server) = std::os::unix::net::UnixStream::pair().unwrap();
+    reactor.park(Box::new(TestConnection { stream: server.into(), timeout: Duration::ZERO, events }));
+    client.write_all(b"local").unwrap();
+    assert_eq!(received.recv_timeout(WAIT).unwrap(), "local");
+}
+
+#[test]
+fn expires_silent_connections () {
+    static STATS: LoadStats = LoadStats::new();
+    static STOP: AtomicBool = AtomicBool::new(false);
+    let reactor = start(&STATS, &STOP);
+    let (events, received) = channel();
+
+    let (_client, server) = socket_pair();
+    reactor.park(Box::new(TestConnection { stream: server, timeout: Duration::from_millis(300), events }));
+
+    assert!(received.recv_timeout(Duration::from_millis(150)).is_err());
+    assert_eq!(received.recv_timeout(WAIT).unwrap(), "expired");
+    thread::sleep(Duration::from_millis(300));
+    assert_eq!(STATS.waiting(), 0);
+    assert_eq!(STATS.active(), 0);
+}
+
+#[test]
+fn lingering_socket_is_closed_after_peer () {
+    static STATS: LoadStats = LoadStats::new();
+    static STOP: AtomicBool = AtomicBool::new(false);
+    let reactor = start(&STATS, &STOP);
+
+    let (mut client, server) = socket_pair();
+    server.shutdown(Shutdown::Write).unwrap();
+    reactor.linger(server);
+    assert_eq!(STATS.waiting(), 1);
+
+    // Input is discarded without resetting the connection
+    client.write_all(&[0; 100000]).unwrap();
+    assert_eq!(client.read(&mut [0; 16]).unwrap(), 0);
+
+    client.shutdown(Shutdown::Write).unwrap();
+    thread::sleep(Duration::from_millis(500));
+    assert_eq!(STATS.waiting(), 0);
+}
+
+#[test]
+fn stop_closes_parked_connections () {
+    static STATS: LoadStats = LoadStats::new();
+    static STOP: AtomicBool = AtomicBool::new(false);
+    let reactor = start(&STATS, &STOP);
+    let (events, received) = channel();
+
+    let (_client, server) = socket_pair();
+    reactor.park(Box::new(TestConnection { stream: server, timeout: Duration::ZERO, events: events.clone() }));
+    STOP.store(true, Ordering::Relaxed);
+    assert_eq!(received.recv_timeout(WAIT).unwrap(), "closing");
+    assert!(reactor.wait_idle(WAIT));
+    assert_eq!(STATS.waiting(), 0);
+
+    // Connections parked after the loop has stopped are closed right away
+    let (_client, server) = socket_pair();
+    reactor.park(Box::new(TestConnection { stream: server, timeout: Duration::ZERO, events }));
+    assert_eq!(received.recv_timeout(WAIT).unwrap(), "closing");
+}
@@ -0,0 +1,111 @@
+use std::ffi::c_void;
+use std::io;
+use std::ptr;
+use photonyx::http::codes::HttpCode;
+use photonyx::http::entity_c::{response_set_stream, stream_writer_write, StreamWriter};
+use photonyx::http::entity::{HttpConnection, ParsingResult, Response, ResponseStream, ResponseType};
+
+mod common;
+use common::{http1, output};
+
+/// Sends streamed response to the single request of `data`, returns written output and whether connection stays open
+fn respond (data: &str, stream: ResponseStream) -> (String, bool) {
+    let mut connection = http1(data);
+    let ParsingResult::Complete(_) = connection.parse() else {
+        panic!("request expected");
+    };
+
+    let mut res = Response::from_status(HttpCode::OK);
+    res.payload = ResponseType::Stream(stream);
+    let _ = connection.respond(res);
+    let is_persistent = connection.is_persistent();
+
+    return (output(connection), is_persistent);
+}
+
+fn body (output: &str) -> &str {
+    return output.split_once("\r\n\r\n").unwrap().1;
+}
+
+#[test]
+fn stream_of_unknown_size_is_chunked () {
+    let chunks = vec![b"id,name\n".to_vec(), Vec::new(), b"1,a\n".to_vec()];
+    let (output, is_persistent) = respond("GET /export HTTP/1.1\r\n\r\n", ResponseStream::from_chunks(chunks));
+
+    assert!(is_persistent);
+    assert!(output.contains("transfer-encoding: chunked\r\n"));
+    assert!(!output.contains("content-length"));
+    // Empty chunk is skipped, otherwise it would end the body early
+    assert_eq!(body(&output), "8\r\nid,name\n\r\n4\r\n1,a\n\r\n0\r\n\r\n");
+}
+
+#[test]
+fn stream_of_known_size_has_content_length () {
+    let (output, is_persistent) = respond("GET / HTTP/1.1\r\n\r\n", ResponseStream::from_reader(&b"0123456789"[..]).with_size(10));
+
+    assert!(is_persistent);
+    assert!(output.contains("content-length: 10\r\n"));
+    assert!(!output.contains("transfer-encoding"));
+    assert_eq!(body(&output), "0123456789");
+}
+
+#[test]
+fn http10_stream_is_delimited_by_closing () {
+    let (output, is_persistent) = respond("GET / HTTP/1.0\r\nConnection: keep-alive\r\n\r\n", ResponseStream::from_chunks(vec![b"abc".to_vec()]));
+
+    assert!(!is_persistent);
+    assert!(output.contains("connection: close\r\n"));
+    assert!(!output.contains("transfer-encoding"));
+    assert_eq!(body(&output), "abc");
+}
+
+#[test]
+fn head_response_has_no_stream_body () {
+    let (output, _) = respond("HEAD / HTTP/1.1\r\n\r\n", ResponseStream::from_chunks(vec![b"abc".to_vec()]));
+
+    assert!(output.contains("transfer-encoding: chunked\r\n"));
+    assert_eq!(body(&output), "");
+}
+
+#[test]
+fn failed_stream_closes_the_connection () {
+    let chunks = vec![Ok(b"partial".to_vec()), Err(io::Error::other("source failed"))];
+    let (output, is_persistent) = respond("GET / HTTP/1.1\r\n\r\n", ResponseStream::new(chunks.into_iter()));
+    assert!(!is_persistent);
+    // Body is left without the last chunk, so client sees it's incomplete
+    assert_eq!(body(&output), "7\r\npartial\r\n");
+
+    let (_, is_persistent) = respond("GET / HTTP/1.1\r\n\r\n", ResponseStream::from_chunks(vec![b"short".to_vec()]).with_size(10));
+    assert!(!is_persistent);
+
+    let (_, is_persistent) = respond("GET / HTTP/1.1\r\n\r\n", ResponseStream::from_chunks(vec![b"too long".to_vec()]).with_size(3));
+    assert!(!is_persistent);
+}
+
+extern "C" fn pull_twice (writer: *mut StreamWriter, state: *mut c_void) -> bool {
+    let calls = unsafe { &mut *(state as *mut u32) };
+    *calls += 1;
+    unsafe {
+        // Empty write without buffer is allowed
+        stream_writer_write(&mut *writer, ptr::null(), 0);
+        stream_writer_write(&mut *writer, b"part".as_ptr(), 4);
+    }
+
+    return *calls < 2;
+}
+
+extern "C" fn free_counter (state: *mut c_void) {
+    drop(unsafe { Box::from_raw(state as *mut u32) });
+}
+
+#[test]
+fn c_callback_stream_is_sent_in_chunks () {
+    let mut res = Response::from_status(HttpCode::OK);
+    response_set_stream(&mut res, pull_twice, Some(free_counter), Box::into_raw(Box::new(0u32)) as *mut c_void);
+    let ResponseType::Stream(stream) = res.payload else {
+        panic!("stream expected");
+    };
+
+    let (output, _) = respond("GET / HTTP/1.1\r\n\r\n", stream);
+    assert_eq!(body(&output), "4\r\npart\r\n4\r\npart\r\n0\r\n\r\n");
+}
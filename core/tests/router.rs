@@ -0,0 +1,85 @@
+use photonyx::app::router::{RouteMatch, Router};
+use photonyx::http::entity::{HttpMethod, MethodSet, ResponseRet};
+
+fn router () -> Router {
+    let mut router = Router::empty();
+    router.register_methods(MethodSet::from(HttpMethod::GET), "/status".to_owned(), |_| ResponseRet::Return);
+    router.register_on("admin", MethodSet::ALL, "/admin/reload".to_owned(), |_| ResponseRet::Return);
+    router.register_on("admin", MethodSet::from(HttpMethod::DELETE), "/status".to_owned(), |_| ResponseRet::Return);
+    return router;
+}
+
+#[test]
+fn restricted_routes_exist_only_on_their_listener () {
+    let router = router();
+
+    assert!(matches!(router.match_route(HttpMethod::POST, "/admin/reload", "admin"), RouteMatch::Found(_, _)));
+    assert!(matches!(router.match_route(HttpMethod::POST, "/admin/reload", ""), RouteMatch::NotFound));
+    assert!(matches!(router.match_route(HttpMethod::POST, "/admin/reload", "public"), RouteMatch::NotFound));
+    // Unrestricted routes are served everywhere
+    assert!(matches!(router.match_route(HttpMethod::GET, "/status", "admin"), RouteMatch::Found(_, _)));
+    assert!(matches!(router.match_route(HttpMethod::GET, "/status", ""), RouteMatch::Found(_, _)));
+}
+
+#[test]
+fn allowed_methods_depend_on_listener () {
+    let router = router();
+
+    assert!(router.allowed_methods("/status", "admin").contains(HttpMethod::DELETE));
+    assert!(!router.allowed_methods("/status", "").contains(HttpMethod::DELETE));
+    assert!(matches!(router.match_route(HttpMethod::DELETE, "/status", ""), RouteMatch::MethodNotAllowed(_)));
+    assert!(router.allowed_methods("/admin/reload", "").is_empty());
+}
+
+#[test]
+fn variables_capture_path_segments () {
+    let mut router = Router::empty();
+    router.register_methods(MethodSet::from(HttpMethod::GET), "/users/{id}/posts/{post}".to_owned(), |_| ResponseRet::Return);
+    router.register_methods(MethodSet::from(HttpMethod::GET), "/files/{path}".to_owned(), |_| ResponseRet::Return);
+
+    let RouteMatch::Found(_, params) = router.match_route(HttpMethod::GET, "/users/7/posts/42", "") else {
+        panic!("route not found");
+    };
+    assert_eq!(params["id"], "7");
+    assert_eq!(params["post"], "42");
+
+    let RouteMatch::Found(_, params) = router.match_route(HttpMethod::GET, "/files/docs/readme.txt", "") else {
+        panic!("route not found");
+    };
+    assert_eq!(params["path"], "docs/readme.txt");
+
+    // Params are decoded, encoded slash stays within the param
+    let RouteMatch::Found(_, params) = router.match_route(HttpMethod::GET, "/users/caf%C3%A9%2Fbar/posts/%20", "") else {
+        panic!("route not found");
+    };
+    assert_eq!(params["id"], "café/bar");
+    assert_eq!(params["post"], " ");
+
+    assert!(matches!(router.match_route(HttpMethod::GET, "/users/7/comments/42", ""), RouteMatch::NotFound));
+    assert!(matches!(router.match_route(HttpMethod::GET, "/files/", ""), RouteMatch::NotFound));
+}
+
+#[test]
+fn wrong_method_lists_allowed_ones () {
+    let mut router = Router::empty();
+    router.register_methods(HttpMethod::GET | HttpMethod::PUT, "/items/{id}".to_owned(), |_| ResponseRet::Return);
+    router.register_methods(MethodSet::from(HttpMethod::DELETE), "/items/{id}".to_owned(), |_| ResponseRet::Return);
+    router.register_methods(MethodSet::from(HttpMethod::POST), "/items".to_owned(), |_| ResponseRet::Return);
+
+    // Handlers are chosen by method among routes of the same path
+    assert!(matches!(router.match_route(HttpMethod::PUT, "/items/1", ""), RouteMatch::Found(_, _)));
+    assert!(matches!(router.match_route(HttpMethod::DELETE, "/items/1", ""), RouteMatch::Found(_, _)));
+
+    let RouteMatch::MethodNotAllowed(methods) = router.match_route(HttpMethod::POST, "/items/1", "") else {
+        panic!("method must be rejected");
+    };
+    // `HEAD` comes with `GET` and `OPTIONS` is answered by core
+    assert_eq!(methods.to_header(), "GET, HEAD, PUT, DELETE, OPTIONS");
+    assert_eq!(router.allowed_methods("/items/1", "").to_header(), "GET, HEAD, PUT, DELETE, OPTIONS");
+
+    let RouteMatch::MethodNotAllowed(methods) = router.match_route(HttpMethod::GET, "/items", "") else {
+        panic!("method must be rejected");
+    };
+    assert_eq!(methods.to_header(), "POST, OPTIONS");
+    assert!(matches!(router.match_route(HttpMethod::GET, "/other", ""), RouteMatch::NotFound));
+}
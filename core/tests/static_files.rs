@@ -0,0 +1,196 @@
+use std::fs;
+use std::net::{TcpListener, TcpStream};
+use photonyx::app::router::{RouteMatch, Router};
+use photonyx::app::static_files::StaticDir;
+use photonyx::context::http::HttpContext;
+use photonyx::http::compression::compress_response;
+use photonyx::http::entity::{HttpMethod, Request, Response, ResponseRet, ResponseType};
+use photonyx::http1::Http1Connection;
+use photonyx::utils::socket::Socket;
+use tempfile::TempDir;
+
+/// Directory with a few files, served under `/assets` and with single-page fallback at `/app`
+fn site () -> (TempDir, Router) {
+    let dir = TempDir::new().unwrap();
+    fs::create_dir_all(dir.path().join("public/docs")).unwrap();
+    fs::write(dir.path().join("public/index.html"), "<h1>home</h1>").unwrap();
+    fs::write(dir.path().join("public/app.js"), "console.log('hello');").unwrap();
+    fs::write(dir.path().join("public/docs/readme.txt"), "0123456789").unwrap();
+    fs::write(dir.path().join("secret.txt"), "secret").unwrap();
+
+    let mut router = Router::empty();
+    router.serve_dir("/assets", StaticDir::new(dir.path().join("public")));
+    router.serve_dir("/app/", StaticDir::new(dir.path().join("public")).with_fallback("index.html"));
+    return (dir, router);
+}
+
+fn request (router: &Router, method: HttpMethod, path: &str, headers: &[(&str, &str)]) -> Response {
+    let mut req = Request::new(method, path.to_owned());
+    for (name, value) in headers {
+        req.headers.set(name.to_string(), value.to_string());
+    }
+
+    let RouteMatch::Found(route, params) = router.match_route(method, &req.path, "") else {
+        panic!("no route for {path}");
+    };
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let _client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (server, address) = listener.accept().unwrap();
+    let connection = Http1Connection::from_transport(Socket::new(server), address.ip());
+
+    let mut ctx = HttpContext::from(&connection, req, params);
+    return match (route.call)(&mut ctx) {
+        ResponseRet::Replace(res) => res,
+        _ => ctx.res
+    };
+}
+
+fn get (router: &Router, path: &str, headers: &[(&str, &str)]) -> Response {
+    return request(router, HttpMethod::GET, path, headers);
+}
+
+fn status (res: &Response) -> &str {
+    return res.code.get_description().0;
+}
+
+fn body (res: &Response) -> &[u8] {
+    let ResponseType::Payload(payload) = &res.payload else {
+        panic!("payload expected");
+    };
+
+    return payload;
+}
+
+#[test]
+fn serves_files_with_validators () {
+    let (_dir, router) = site();
+
+    let res = get(&router, "/assets/app.js", &[]);
+    assert_eq!(status(&res), "200");
+    assert_eq!(body(&res), b"console.log('hello');");
+    assert_eq!(res.headers.get("content-type").as_deref(), Some("text/javascript; charset=utf-8"));
+    assert_eq!(res.headers.get("accept-ranges").as_deref(), Some("bytes"));
+    assert!(res.headers.get("last-modified").is_some());
+
+    let etag = res.headers.get("etag").unwrap();
+    assert!(etag.starts_with('"'));
+    let res = get(&router, "/assets/app.js", &[("if-none-match", &format!("\"other\", W/{etag}"))]);
+    assert_eq!(status(&res), "304");
+    assert!(matches!(res.payload, ResponseType::NoContent));
+
+    let last_modified = get(&router, "/assets/app.js", &[]).headers.get("last-modified").unwrap();
+    assert_eq!(status(&get(&router, "/assets/app.js", &[("if-modified-since", &last_modified)])), "304");
+    assert_eq!(status(&get(&router, "/assets/app.js", &[("if-modified-since", "Thu, 01 Jan 1970 00:00:00 GMT")])), "200");
+    // Entity tag takes precedence over date
+    assert_eq!(status(&get(&router, "/assets/app.js", &[("if-none-match", "\"other\""), ("if-modified-since", &last_modified)])), "200");
+
+}
+
+#[test]
+fn head_gets_headers_of_compressed_get () {
+    let (dir, router) = site();
+    fs::write(dir.path().join("public/data.json"), "{\"items\": []}".repeat(200)).unwrap();
+
+    let headers = |method| {
+        let mut res = request(&router, method, "/assets/data.json", &[]);
+        compress_response(Some("gzip"), &mut res);
+        let mut headers = res.headers.into_iter().map(|header| format!("{}: {}", header.name, header.value)).collect::<Vec<_>>();
+        headers.push(format!("length: {}", body(&res).len()));
+        headers.sort();
+        return headers;
+    };
+
+    let get = headers(HttpMethod::GET);
+    assert!(get.contains(&"content-encoding: gzip".to_owned()));
+    assert_eq!(headers(HttpMethod::HEAD), get);
+}
+
+#[test]
+fn serves_byte_ranges () {
+    let (_dir, router) = site();
+    let path = "/assets/docs/readme.txt";
+
+    let res = get(&router, path, &[("range", "bytes=2-5")]);
+    assert_eq!(status(&res), "206");
+    assert_eq!(body(&res), b"2345");
+    assert_eq!(res.headers.get("content-range").as_deref(), Some("bytes 2-5/10"));
+
+    let res = get(&router, path, &[("range", "bytes=7-")]);
+    assert_eq!(body(&res), b"789");
+
+    let res = get(&router, path, &[("range", "bytes=-3")]);
+    assert_eq!(body(&res), b"789");
+    assert_eq!(res.headers.get("content-range").as_deref(), Some("bytes 7-9/10"));
+
+    let res = get(&router, path, &[("range", "bytes=5-100")]);
+    assert_eq!(body(&res), b"56789");
+
+    let res = get(&router, path, &[("range", "bytes=10-")]);
+    assert_eq!(status(&res), "416");
+    assert_eq!(res.headers.get("content-range").as_deref(), Some("bytes */10"));
+
+    // Multiple ranges and malformed ones are ignored
+    assert_eq!(status(&get(&router, path, &[("range", "bytes=0-1,4-5")])), "200");
+    assert_eq!(status(&get(&router, path, &[("range", "bytes=5-2")])), "200");
+    assert_eq!(status(&get(&router, path, &[("range", "lines=1-2")])), "200");
+
+    // Range of a changed file isn't served
+    let etag = get(&router, path, &[]).headers.get("etag").unwrap();
+    assert_eq!(status(&get(&router, path, &[("range", "bytes=0-1"), ("if-range", &etag)])), "206");
+    assert_eq!(status(&get(&router, path, &[("range", "bytes=0-1"), ("if-range", "\"stale\"")])), "200");
+}
+
+#[test]
+fn serves_index_files () {
+    let (_dir, router) = site();
+
+    let res = get(&router, "/assets/", &[]);
+    assert_eq!(body(&res), b"<h1>home</h1>");
+    assert_eq!(res.headers.get("content-type").as_deref(), Some("text/html; charset=utf-8"));
+
+    let res = get(&router, "/assets", &[]);
+    assert_eq!(status(&res), "301");
+    assert_eq!(res.headers.get("location").as_deref(), Some("/assets/"));
+
+    let res = get(&router, "/assets/docs?page=2", &[]);
+    assert_eq!(res.headers.get("location").as_deref(), Some("/assets/docs/?page=2"));
+
+    // Directory without index file
+    assert_eq!(status(&get(&router, "/assets/docs/", &[])), "404");
+    assert_eq!(status(&get(&router, "/assets/missing.js", &[])), "404");
+}
+
+#[test]
+fn falls_back_to_application_page () {
+    let (_dir, router) = site();
+
+    assert_eq!(body(&get(&router, "/app/", &[])), b"<h1>home</h1>");
+    assert_eq!(body(&get(&router, "/app/users/42", &[])), b"<h1>home</h1>");
+    assert_eq!(body(&get(&router, "/app/docs/", &[])), b"<h1>home</h1>");
+    // Existing files are still served
+    assert_eq!(body(&get(&router, "/app/docs/readme.txt", &[])), b"0123456789");
+}
+
+#[test]
+fn blocks_path_traversal () {
+    let (_dir, router) = site();
+
+    assert_eq!(status(&get(&router, "/assets/../secret.txt", &[])), "403");
+    assert_eq!(status(&get(&router, "/assets/docs/../../secret.txt", &[])), "403");
+    assert_eq!(status(&get(&router, "/assets/..\\secret.txt", &[])), "403");
+    // Fallback isn't served for rejected paths either
+    assert_eq!(status(&get(&router, "/app/../secret.txt", &[])), "403");
+}
+
+#[cfg(unix)]
+#[test]
+fn follows_symlinks_only_within_root () {
+    let (dir, mut router) = site();
+    std::os::unix::fs::symlink(dir.path().join("secret.txt"), dir.path().join("public/link.txt")).unwrap();
+    std::os::unix::fs::symlink(dir.path().join("public/app.js"), dir.path().join("public/docs/app.js")).unwrap();
+    router.serve_dir("/linked", StaticDir::new(dir.path().join("public")));
+
+    assert_eq!(status(&get(&router, "/linked/link.txt", &[])), "403");
+    assert_eq!(status(&get(&router, "/linked/docs/app.js", &[])), "200");
+}
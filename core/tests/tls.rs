@@ -0,0 +1,175 @@
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+use photonyx::app::config::{CertificateConfig, TlsConfig};
+use photonyx::app::tls::{CertResolver, TlsAcceptor};
+use photonyx::utils::socket::is_timeout;
+use rustls::pki_types::{CertificateDer, ServerName};
+use rustls::{ClientConfig, ClientConnection, RootCertStore, StreamOwned};
+use tempfile::TempDir;
+
+/// Writes self-signed certificate for `names` into `dir`, returns its config and DER for trusting it
+fn write_certificate (dir: &Path, file: &str, names: &[&str], served_for: &[&str]) -> (CertificateConfig, CertificateDer<'static>) {
+    let generated = rcgen::generate_simple_self_signed(names.iter().map(|name| name.to_string()).collect::<Vec<_>>()).unwrap();
+    let cert = dir.join(format!("{file}.crt"));
+    let key = dir.join(format!("{file}.key"));
+    fs::write(&cert, generated.cert.pem()).unwrap();
+    fs::write(&key, generated.key_pair.serialize_pem()).unwrap();
+
+    let config = CertificateConfig {
+        cert: cert.to_string_lossy().into_owned(),
+        key: key.to_string_lossy().into_owned(),
+        names: served_for.iter().map(|name| name.to_string()).collect()
+    };
+
+    return (config, generated.cert.der().clone());
+}
+
+fn leaf_of (resolver: &CertResolver, server_name: Option<&str>) -> CertificateDer<'static> {
+    return resolver.find(server_name).unwrap().cert[0].clone();
+}
+
+fn tls_config (certificates: Vec<CertificateConfig>) -> TlsConfig {
+    let mut config = TlsConfig::default();
+    config.certificates = certificates;
+    return config;
+}
+
+/// Connects to `port` trusting only `root`, sends a line and returns the echoed one with negotiated ALPN
+fn exchange (port: u16, root: CertificateDer<'static>, server_name: &str, alpn: &[&[u8]]) -> (String, Option<Vec<u8>>) {
+    let mut roots = RootCertStore::empty();
+    roots.add(root).unwrap();
+
+    let mut config = ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    config.alpn_protocols = alpn.iter().map(|protocol| protocol.to_vec()).collect();
+
+    let name = ServerName::try_from(server_name.to_owned()).unwrap();
+    let connection = ClientConnection::new(Arc::new(config), name).unwrap();
+    let mut stream = StreamOwned::new(connection, TcpStream::connect(("127.0.0.1", port)).unwrap());
+    stream.write_all(b"ping\n").unwrap();
+
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line).unwrap();
+    let alpn = reader.get_ref().conn.alpn_protocol().map(<[u8]>::to_vec);
+    return (line, alpn);
+}
+
+/// Accepts connections on a loopback port, echoing the first line of each back
+fn echo_server (acceptor: Arc<TlsAcceptor>) -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut socket) = acceptor.accept(stream.unwrap()) else {
+                continue;
+            };
+
+            let mut line = String::new();
+            BufReader::new(&mut socket).read_line(&mut line).unwrap();
+            socket.write_all(line.as_bytes()).unwrap();
+            socket.flush().unwrap();
+        }
+    });
+
+    return port;
+}
+
+#[test]
+fn selects_certificate_by_server_name () {
+    let dir = TempDir::new().unwrap();
+    let (default, default_der) = write_certificate(dir.path(), "default", &["localhost"], &[]);
+    let (exact, exact_der) = write_certificate(dir.path(), "exact", &["api.test"], &["api.test"]);
+    let (wildcard, wildcard_der) = write_certificate(dir.path(), "wildcard", &["*.apps.test"], &["*.apps.test"]);
+    let resolver = CertResolver::load(&[exact, default, wildcard]).unwrap();
+
+    assert_eq!(leaf_of(&resolver, Some("api.test")), exact_der);
+    assert_eq!(leaf_of(&resolver, Some("API.Test")), exact_der);
+    assert_eq!(leaf_of(&resolver, Some("one.apps.test")), wildcard_der);
+    // Wildcard covers a single label only
+    assert_eq!(leaf_of(&resolver, Some("a.b.apps.test")), default_der);
+    assert_eq!(leaf_of(&resolver, Some("apps.test")), default_der);
+    assert_eq!(leaf_of(&resolver, None), default_der);
+}
+
+#[test]
+fn reloads_changed_certificate () {
+    let dir = TempDir::new().unwrap();
+    let (config, old_der) = write_certificate(dir.path(), "site", &["site.test"], &[]);
+    let resolver = CertResolver::load(&[config]).unwrap();
+
+    resolver.reload();
+    assert_eq!(leaf_of(&resolver, None), old_der);
+
+    // Broken files keep the old certificate
+    fs::write(dir.path().join("site.crt"), "not a certificate").unwrap();
+    File::options().write(true).open(dir.path().join("site.crt")).unwrap()
+        .set_modified(SystemTime::now() + Duration::from_secs(10)).unwrap();
+    resolver.reload();
+    assert_eq!(leaf_of(&resolver, None), old_der);
+
+    let (_, new_der) = write_certificate(dir.path(), "site", &["site.test"], &[]);
+    File::options().write(true).open(dir.path().join("site.crt")).unwrap()
+        .set_modified(SystemTime::now() + Duration::from_secs(20)).unwrap();
+    resolver.reload();
+    assert_eq!(leaf_of(&resolver, None), new_der);
+}
+
+#[test]
+fn rejects_missing_files () {
+    let config = CertificateConfig { cert: "missing.crt".to_owned(), key: "missing.key".to_owned(), names: Vec::new() };
+    assert!(TlsAcceptor::new(&tls_config(vec![config])).is_err());
+    assert!(TlsAcceptor::new(&tls_config(Vec::new())).is_err());
+}
+
+#[test]
+fn negotiates_alpn_and_sni () {
+    let dir = TempDir::new().unwrap();
+    let (default, default_der) = write_certificate(dir.path(), "default", &["localhost"], &[]);
+    let (named, named_der) = write_certificate(dir.path(), "named", &["named.test"], &["named.test"]);
+    let port = echo_server(Arc::new(TlsAcceptor::new(&tls_config(vec![default, named])).unwrap()));
+
+    let (line, alpn) = exchange(port, default_der.clone(), "localhost", &[b"h2", b"http/1.1"]);
+    assert_eq!(line, "ping\n");
+    assert_eq!(alpn.as_deref(), Some(&b"h2"[..]));
+
+    let (_, alpn) = exchange(port, named_der, "named.test", &[b"http/1.1"]);
+    assert_eq!(alpn.as_deref(), Some(&b"http/1.1"[..]));
+
+    let (_, alpn) = exchange(port, default_der, "localhost", &[]);
+    assert_eq!(alpn, None);
+}
+
+#[test]
+fn handshake_has_total_deadline () {
+    let dir = TempDir::new().unwrap();
+    let (default, _) = write_certificate(dir.path(), "default", &["localhost"], &[]);
+    let acceptor = TlsAcceptor::new(&tls_config(vec![default])).unwrap();
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    thread::spawn(move || {
+        // Start of a long handshake record, trickled so that every single read succeeds in time
+        let mut client = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        for byte in [0x16, 0x03, 0x01, 0x40, 0x00].into_iter().chain(std::iter::repeat_n(0x01, 40)) {
+            if client.write_all(&[byte]).is_err() {
+                return;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+    });
+
+    let start = Instant::now();
+    let error = acceptor.accept_within(listener.accept().unwrap().0, Duration::from_millis(300)).err().unwrap();
+    assert!(is_timeout(&error));
+    assert!(start.elapsed() < Duration::from_millis(1500));
+}
@@ -0,0 +1,55 @@
+use photonyx::http::url::{encode_path, normalize_path, percent_decode};
+
+#[test]
+fn decodes_percent_sequences () {
+    assert_eq!(percent_decode("caf%C3%A9%20au%20lait").as_deref(), Some("café au lait"));
+    assert_eq!(percent_decode("plain+text").as_deref(), Some("plain+text"));
+    assert_eq!(percent_decode("a%2fb%25").as_deref(), Some("a/b%"));
+
+    assert_eq!(percent_decode("%"), None);
+    assert_eq!(percent_decode("%4"), None);
+    assert_eq!(percent_decode("%zz"), None);
+    // Not UTF-8
+    assert_eq!(percent_decode("%C3%28"), None);
+}
+
+#[test]
+fn normalizes_paths () {
+    assert_eq!(normalize_path("/").as_deref(), Some("/"));
+    assert_eq!(normalize_path("/users/42").as_deref(), Some("/users/42"));
+    assert_eq!(normalize_path("//users///42/").as_deref(), Some("/users/42/"));
+    assert_eq!(normalize_path("/a/./b/../c").as_deref(), Some("/a/c"));
+    assert_eq!(normalize_path("/a/b/..").as_deref(), Some("/a/"));
+    assert_eq!(normalize_path("/a/.").as_deref(), Some("/a/"));
+    assert_eq!(normalize_path("/../../etc/passwd").as_deref(), Some("/etc/passwd"));
+    // Encoded dot segments are resolved too
+    assert_eq!(normalize_path("/files/%2e%2E/secret").as_deref(), Some("/secret"));
+    assert_eq!(normalize_path("/caf%C3%A9/%7Euser").as_deref(), Some("/café/~user"));
+}
+
+#[test]
+fn keeps_encoded_separators () {
+    assert_eq!(normalize_path("/files/a%2fb").as_deref(), Some("/files/a%2Fb"));
+    assert_eq!(normalize_path("/100%25").as_deref(), Some("/100%25"));
+    // Decoded percent sign doesn't start another sequence
+    assert_eq!(normalize_path("/%252F").as_deref(), Some("/%252F"));
+}
+
+#[test]
+fn rejects_malformed_paths () {
+    assert_eq!(normalize_path("/a%"), None);
+    assert_eq!(normalize_path("/a%2"), None);
+    assert_eq!(normalize_path("/a%g0"), None);
+    assert_eq!(normalize_path("/nul%00byte"), None);
+    assert_eq!(normalize_path("/%FF"), None);
+    assert_eq!(normalize_path("relative"), None);
+}
+
+#[test]
+fn encodes_canonical_paths () {
+    assert_eq!(encode_path("/users/42"), "/users/42");
+    assert_eq!(encode_path("/café au lait"), "/caf%C3%A9%20au%20lait");
+    assert_eq!(encode_path("/files/a%2Fb"), "/files/a%2Fb");
+    assert_eq!(encode_path("/~user/a:b@c"), "/~user/a:b@c");
+    assert_eq!(encode_path(&normalize_path("/caf%c3%a9").unwrap()), "/caf%C3%A9");
+}
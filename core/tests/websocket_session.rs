@@ -0,0 +1,80 @@
+use std::net::{TcpListener, TcpStream};
+use std::time::Duration;
+use photonyx::app::App;
+use photonyx::context::ws::SocketContext;
+use photonyx::http::entity::{HttpMethod, Request};
+use photonyx::http1::Http1Connection;
+use photonyx::utils::socket::Socket;
+use photonyx::websocket::WebSocketSession;
+use tungstenite::protocol::Role;
+use tungstenite::{Message, WebSocket};
+
+/// Server session and raw client connected over loopback, handshake is considered done
+fn connect () -> (WebSocketSession, WebSocket<TcpStream>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (server, address) = listener.accept().unwrap();
+
+    client.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let connection = Http1Connection::from_transport(Socket::new(server), address.ip());
+    let ctx = SocketContext::from(connection, Request::new(HttpMethod::GET, "/ws".to_owned()));
+
+    return (WebSocketSession::new(ctx, 0), WebSocket::from_raw_socket(client, Role::Client, None));
+}
+
+fn app () -> App {
+    let mut app = App::new();
+    app.ws_endpoints.register("/ws", "hello", |ctx| ctx.text("hello", "world"));
+    return app;
+}
+
+#[test]
+fn receive_returns_when_no_input () {
+    let app = app();
+    let (mut session, _client) = connect();
+
+    // Nothing sent yet, so receive must not block
+    assert!(session.receive(&app).is_ok());
+}
+
+#[test]
+fn receive_dispatches_all_arrived_messages () {
+    let app = app();
+    let (mut session, mut client) = connect();
+
+    client.write_message(Message::text("hello")).unwrap();
+    client.write_message(Message::text("hello:again")).unwrap();
+    std::thread::sleep(Duration::from_millis(50));
+    assert!(session.receive(&app).is_ok());
+
+    for _ in 0..2 {
+        assert_eq!(client.read_message().unwrap(), Message::text("hello:[\"world\"]"));
+    }
+}
+
+#[test]
+fn unanswered_ping_closes_session () {
+    let app = app();
+    let (mut session, mut client) = connect();
+
+    assert!(session.ping().is_ok());
+    assert!(matches!(client.read_message().unwrap(), Message::Ping(_)));
+    // Client queued pong while reading, it's sent with the next write
+    client.write_pending().unwrap();
+    std::thread::sleep(Duration::from_millis(50));
+    assert!(session.receive(&app).is_ok());
+    assert!(session.ping().is_ok());
+
+    // Second ping without anything received in between means peer is gone
+    assert!(session.ping().is_err());
+}
+
+#[test]
+fn closed_peer_ends_session () {
+    let app = app();
+    let (mut session, client) = connect();
+
+    drop(client);
+    std::thread::sleep(Duration::from_millis(50));
+    assert!(session.receive(&app).is_err());
+}
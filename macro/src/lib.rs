@@ -14,17 +14,17 @@ pub fn assert_stream (input: TokenStream) -> TokenStream {
         .replace("\\r", "\r")
         .replace("\\n", "\n");
 
+    // Read error is treated as mismatch, so truncated input can't cause a panic
     let mut result = String::new();
     for char in sequence.chars() {
-        result.push_str("{");
-        result.push_str("let mut byte = ");
+        result.push_str("match ");
         result.push_str(stream);
-        result.push_str(".read_u8().unwrap();");
-        result.push_str("if byte != ");
+        result.push_str(".read_u8() {");
+        result.push_str("Ok(");
         result.push_str(&(char as u8).to_string());
-        result.push_str(" { return ");
+        result.push_str(") => {}");
+        result.push_str("_ => return ");
         result.push_str(invalid_result);
-        result.push_str(" }");
         result.push_str("}");
     }
 